@@ -0,0 +1,162 @@
+//! DBus server addresses.
+//!
+//! Spec: https://dbus.freedesktop.org/doc/dbus-specification.html#addresses
+
+use std::path::PathBuf;
+
+/// Address of a DBus server that we can connect to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Address {
+    /// Unix domain socket at a path in the filesystem, `unix:path=...`.
+    UnixPath(PathBuf),
+
+    /// Unix domain socket in the Linux abstract namespace, `unix:abstract=...`.
+    ///
+    /// The name does not include the leading null byte.
+    UnixAbstract(Vec<u8>),
+}
+
+impl Address {
+    /// Parse a server address string such as `unix:path=/var/run/dbus/system_bus_socket`.
+    ///
+    /// The string may contain several addresses separated by `;`, in which case the first
+    /// one that is supported is returned.
+    pub fn parse(addresses: &str) -> crate::Result<Self> {
+        addresses
+            .split(';')
+            .filter(|address| !address.is_empty())
+            .find_map(|address| Self::parse_single(address).ok())
+            .ok_or_else(|| crate::Error::InvalidAddress(addresses.to_string()))
+    }
+
+    fn parse_single(address: &str) -> crate::Result<Self> {
+        let invalid = || crate::Error::InvalidAddress(address.to_string());
+
+        let (transport, key_values) = address.split_once(':').ok_or_else(invalid)?;
+
+        match transport {
+            "unix" => {
+                for key_value in key_values.split(',') {
+                    let (key, value) = key_value.split_once('=').ok_or_else(invalid)?;
+                    match key {
+                        "path" => {
+                            let path = path_from_bytes(decode_address_value(value)?)?;
+                            return Ok(Self::UnixPath(path));
+                        }
+                        "abstract" => return Ok(Self::UnixAbstract(decode_address_value(value)?)),
+                        _ => continue,
+                    }
+                }
+
+                Err(invalid())
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Decode an address value, in which any byte may be escaped as `%` followed by two hex digits.
+fn decode_address_value(value: &str) -> crate::Result<Vec<u8>> {
+    let invalid = || crate::Error::InvalidAddress(value.to_string());
+
+    let mut decoded = Vec::with_capacity(value.len());
+    let mut bytes = value.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte == b'%' {
+            let escaped = [
+                bytes.next().ok_or_else(invalid)?,
+                bytes.next().ok_or_else(invalid)?,
+            ];
+            decoded.extend(hex::decode(escaped).map_err(|_| invalid())?);
+        } else {
+            decoded.push(byte);
+        }
+    }
+
+    Ok(decoded)
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: Vec<u8>) -> crate::Result<PathBuf> {
+    use std::ffi::OsString;
+    use std::os::unix::ffi::OsStringExt;
+
+    Ok(PathBuf::from(OsString::from_vec(bytes)))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: Vec<u8>) -> crate::Result<PathBuf> {
+    Ok(PathBuf::from(String::from_utf8(bytes)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_unix_path() -> crate::Result<()> {
+        assert_eq!(
+            Address::parse("unix:path=/var/run/dbus/system_bus_socket")?,
+            Address::UnixPath(PathBuf::from("/var/run/dbus/system_bus_socket"))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_unix_abstract() -> crate::Result<()> {
+        assert_eq!(
+            Address::parse("unix:abstract=/foo")?,
+            Address::UnixAbstract(b"/foo".to_vec())
+        );
+        assert_eq!(
+            Address::parse("unix:abstract=/foo,guid=0123456789abcdef")?,
+            Address::UnixAbstract(b"/foo".to_vec())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_percent_encoded() -> crate::Result<()> {
+        assert_eq!(
+            Address::parse("unix:path=/tmp/dbus%20test%2Fbus")?,
+            Address::UnixPath(PathBuf::from("/tmp/dbus test/bus"))
+        );
+        assert_eq!(
+            Address::parse("unix:abstract=%2ffoo%00")?,
+            Address::UnixAbstract(b"/foo\0".to_vec())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn decode_address_value_malformed() {
+        for value in &["/tmp/bus%", "/tmp/bus%2", "/tmp/bus%zz"] {
+            assert!(matches!(
+                decode_address_value(value),
+                Err(crate::Error::InvalidAddress(_))
+            ));
+        }
+        assert!(Address::parse("unix:path=/tmp/bus%").is_err());
+    }
+
+    #[test]
+    fn parse_first_supported() -> crate::Result<()> {
+        assert_eq!(
+            Address::parse("tcp:host=localhost,port=1234;unix:path=/tmp/bus")?,
+            Address::UnixPath(PathBuf::from("/tmp/bus"))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert!(Address::parse("").is_err());
+        assert!(Address::parse("unix").is_err());
+        assert!(Address::parse("unix:guid=0123").is_err());
+        assert!(Address::parse("tcp:host=localhost,port=1234").is_err());
+    }
+}
@@ -1,54 +1,253 @@
+mod bus;
+mod introspectable;
+mod keepalive;
+mod match_rule;
+mod method_call;
+mod object_manager;
+mod peer;
+mod properties;
+mod server;
+mod signal;
+mod split;
+mod unix_fds;
+
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
 use std::num::NonZeroU32;
+use std::ops::Deref;
+use std::ops::DerefMut;
+use std::sync::Arc;
+use std::time::Duration;
 
 use smol::io::BufReader;
 use smol::io::BufWriter;
 use smol::prelude::*;
 
+pub use self::bus::Credentials;
+pub use self::bus::StartServiceReply;
+use self::keepalive::Keepalive;
+pub use self::match_rule::MatchRule;
+pub use self::method_call::MethodCallBuilder;
+pub use self::object_manager::ManagedObjects;
+pub use self::properties::PropertiesChanged;
+pub use self::signal::SignalBuilder;
+pub use self::split::Incoming;
+pub use self::split::Receiver;
+pub use self::split::Sender;
+use self::unix_fds::FdQueue;
+use crate::address::Address;
 use crate::message_protocol::body::Body;
+use crate::message_protocol::body::FromBody;
+use crate::message_protocol::names::BusName;
 use crate::message_protocol::Message;
 use crate::message_protocol::MessageTypeParam;
-use crate::message_protocol::MethodCall;
+use crate::message_protocol::Serial;
+use crate::timer::Sleep;
+use crate::timer::SmolTimer;
+use crate::timer::Timer;
+use crate::type_system::marshal::Encoder;
 use crate::type_system::types::*;
+use crate::type_system::unmarshal::unmarshal_message;
+use crate::type_system::Endianness;
+
+/// Mechanism used to authenticate with the DBus server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AuthMechanism {
+    /// Authenticate with the credentials of the process, as seen by the server.
+    External,
+}
+
+type Reader = BufReader<Box<dyn AsyncRead + Unpin + Send>>;
+type Writer = BufWriter<Box<dyn AsyncWrite + Unpin + Send>>;
 
 pub struct Connection {
     /// Serial that is unique for each message, so replies can be identified.
     /// Increment by one for each sent message to keep it unique.
     serial: u32,
 
-    reader: BufReader<Box<dyn AsyncRead + Unpin>>,
-    writer: BufWriter<Box<dyn AsyncWrite + Unpin>>,
+    /// Serials of sent method calls that are still waiting for a reply.
+    pending_replies: HashSet<NonZeroU32>,
+
+    /// Address the connection was made to, if any. Used to reconnect.
+    address: Option<Address>,
+
+    auth_mechanism: AuthMechanism,
+
+    /// Unique name assigned by the message bus in reply to Hello.
+    unique_name: Option<String>,
+
+    reader: Reader,
+    writer: Writer,
+
+    /// Buffer for marshalling sent messages, reused so it is not allocated for each message.
+    encoder: Encoder,
+
+    keepalive: Option<Keepalive>,
+
+    /// Called when the keepalive detects that the connection is lost.
+    disconnect_hook: Option<Arc<dyn Fn() + Send + Sync>>,
+
+    /// Received messages that are longer than this are rejected before reading them.
+    max_message_size: usize,
+
+    /// Timer for timeouts and the keepalive.
+    timer: Arc<dyn Timer + Send + Sync>,
+
+    /// File descriptors received by the reader, when connected over a unix socket.
+    received_fds: Option<FdQueue>,
+
+    /// Match rules that were added with the message bus.
+    match_rules: HashSet<String>,
+
+    /// Messages that were read while waiting for a signal, but did not match it. They are
+    /// returned by the next reads, before anything else is read from the stream.
+    queued: VecDeque<Message>,
+
+    /// Destination of method calls built with [Connection::method_call] that do not set one.
+    default_destination: Option<BusName>,
 }
 
 impl Connection {
     pub async fn new_system() -> crate::Result<Self> {
         log::info!("Connecting to system DBus.");
         let mut conn = Self::connect_to_system_bus().await?;
-        // Spec for some reason requires that the first thing we do is to send a null byte.
-        conn.writer.write(&[0]).await?;
         log::info!("Connected.");
 
-        log::info!("Authenticating.");
-        conn.auth().await?;
-        log::info!("Authenticated.");
+        conn.setup().await?;
 
-        log::info!("Saying hello.");
-        conn.say_hello().await?;
+        Ok(conn)
+    }
+
+    /// Set up a connection on an already connected unix socket, for example one that was handed
+    /// over by systemd.
+    ///
+    /// The connection can not be re-established with [Connection::reconnect], since the address
+    /// is not known.
+    ///
+    /// # Safety
+    ///
+    /// The file descriptor must be an open unix stream socket, and it must not be used or closed
+    /// elsewhere, since it is owned by the connection from now on.
+    #[cfg(unix)]
+    pub async unsafe fn from_raw_fd(fd: std::os::unix::io::RawFd) -> crate::Result<Self> {
+        use std::os::unix::io::FromRawFd;
+
+        let stream = std::os::unix::net::UnixStream::from_raw_fd(fd);
+        let stream = smol::net::unix::UnixStream::try_from(stream)?;
+
+        let mut conn = Self::new_unix(stream);
+        conn.setup().await?;
 
         Ok(conn)
     }
 
     fn new<R, W>(reader: R, writer: W) -> Self
     where
-        R: AsyncRead + Unpin + 'static,
-        W: AsyncWrite + Unpin + 'static,
+        R: AsyncRead + Unpin + Send + 'static,
+        W: AsyncWrite + Unpin + Send + 'static,
     {
         Connection {
             reader: BufReader::new(Box::new(reader)),
             writer: BufWriter::new(Box::new(writer)),
             serial: 0,
+            pending_replies: HashSet::new(),
+            address: None,
+            auth_mechanism: AuthMechanism::External,
+            unique_name: None,
+            encoder: Encoder::default(),
+            keepalive: None,
+            disconnect_hook: None,
+            max_message_size: crate::MAX_MESSAGE_SIZE,
+            timer: Arc::new(SmolTimer),
+            received_fds: None,
+            match_rules: HashSet::new(),
+            queued: VecDeque::new(),
+            default_destination: None,
         }
     }
 
+    /// Connection on a unix socket, which can receive file descriptors along with messages.
+    #[cfg(unix)]
+    fn new_unix(stream: smol::net::unix::UnixStream) -> Self {
+        let received_fds = FdQueue::default();
+        let reader = unix_fds::FdReader::new(stream.clone().into(), received_fds.clone());
+
+        let mut conn = Self::new(reader, stream);
+        conn.received_fds = Some(received_fds);
+        conn
+    }
+
+    /// Set the maximum length in bytes of received messages, including header and body.
+    ///
+    /// Longer messages fail with [crate::Error::MessageTooLarge], before memory is allocated for
+    /// them. The default is [crate::MAX_MESSAGE_SIZE].
+    pub fn set_max_message_size(&mut self, max_message_size: usize) {
+        self.max_message_size = max_message_size;
+    }
+
+    /// Set the timer used for timeouts, for example to use the timer of another async runtime
+    /// than smol.
+    pub fn set_timer<T: Timer + Send + Sync + 'static>(&mut self, timer: T) {
+        self.timer = Arc::new(timer);
+    }
+
+    /// Set the destination of method calls built with [Connection::method_call] that do not set
+    /// one, for when most calls go to the same service.
+    pub fn set_default_destination(&mut self, name: BusName) {
+        self.default_destination = Some(name);
+    }
+
+    /// Unique name assigned to this connection by the message bus, for example `:1.42`.
+    pub fn unique_name(&self) -> Option<&str> {
+        self.unique_name.as_deref()
+    }
+
+    /// Re-establish the connection to the address it was originally made to, for example after
+    /// the message bus has restarted.
+    ///
+    /// The connection gets a new unique name, and serials start over.
+    ///
+    /// Connections that were not made to an address can not be re-established, and fail with
+    /// [crate::Error::Disconnected].
+    #[cfg(unix)]
+    pub async fn reconnect(&mut self) -> crate::Result<()> {
+        let address = self.address.clone().ok_or(crate::Error::Disconnected)?;
+
+        log::info!("Reconnecting.");
+        let mut conn = Self::connect_to_address(&address).await?;
+        conn.auth_mechanism = self.auth_mechanism;
+        conn.max_message_size = self.max_message_size;
+        conn.timer = self.timer.clone();
+        conn.default_destination = self.default_destination.clone();
+        conn.keepalive = self
+            .keepalive
+            .map(|keepalive| Keepalive::new(keepalive.interval()));
+        conn.disconnect_hook = self.disconnect_hook.clone();
+        log::info!("Connected.");
+
+        conn.setup().await?;
+
+        *self = conn;
+
+        Ok(())
+    }
+
+    /// Authenticate and say hello on a newly established connection.
+    async fn setup(&mut self) -> crate::Result<()> {
+        // Spec for some reason requires that the first thing we do is to send a null byte.
+        self.writer.write(&[0]).await?;
+
+        log::info!("Authenticating.");
+        self.auth().await?;
+        log::info!("Authenticated.");
+
+        log::info!("Saying hello.");
+        self.say_hello().await?;
+
+        Ok(())
+    }
+
     #[cfg(windows)]
     async fn connect_to_system_bus() -> crate::Result<Connection> {
         use smol::net::TcpStream;
@@ -68,65 +267,214 @@ impl Connection {
 
     #[cfg(unix)]
     async fn connect_to_system_bus() -> crate::Result<Connection> {
-        use smol::net::unix::UnixStream;
+        let address = match std::env::var("DBUS_SYSTEM_BUS_ADDRESS") {
+            Ok(address) => Address::parse(&address)?,
+            Err(_) => Address::UnixPath("/var/run/dbus/system_bus_socket".into()),
+        };
 
-        // TODO check DBUS_SYSTEM_BUS_ADDRESS env variable, if it is set, connect to that instead.
-        let address = "/var/run/dbus/system_bus_socket";
+        Self::connect_to_address(&address).await
+    }
 
-        let stream = UnixStream::connect(address).await?;
+    #[cfg(unix)]
+    async fn connect_to_address(address: &Address) -> crate::Result<Connection> {
+        use smol::net::unix::UnixStream;
 
-        // Split up into buffered read/write.
-        let reader = stream.clone();
-        let writer = stream;
+        let stream = match address {
+            Address::UnixPath(path) => UnixStream::connect(path).await?,
+            Address::UnixAbstract(name) => Self::connect_abstract(name).await?,
+        };
 
-        let conn = Self::new(reader, writer);
+        let mut conn = Self::new_unix(stream);
+        conn.address = Some(address.clone());
 
         Ok(conn)
     }
 
-    fn get_serial(&mut self) -> NonZeroU32 {
-        // Increment
-        self.serial += 1;
+    /// Connect to a unix socket in the abstract namespace.
+    #[cfg(target_os = "linux")]
+    async fn connect_abstract(name: &[u8]) -> crate::Result<smol::net::unix::UnixStream> {
+        use std::convert::TryFrom;
+        use std::os::linux::net::SocketAddrExt;
+        use std::os::unix::net::SocketAddr;
+        use std::os::unix::net::UnixStream;
+
+        // The leading null byte of the abstract socket name is added by `from_abstract_name`.
+        let socket_addr = SocketAddr::from_abstract_name(name)?;
+        let stream = smol::unblock(move || UnixStream::connect_addr(&socket_addr)).await?;
+
+        Ok(smol::net::unix::UnixStream::try_from(stream)?)
+    }
 
-        NonZeroU32::new(self.serial).expect("Serial overflow")
+    /// Abstract unix sockets only exist on Linux.
+    #[cfg(all(unix, not(target_os = "linux")))]
+    async fn connect_abstract(name: &[u8]) -> crate::Result<smol::net::unix::UnixStream> {
+        let name = String::from_utf8_lossy(name);
+        Err(crate::Error::InvalidAddress(format!(
+            "unix:abstract={}",
+            name
+        )))
+    }
+
+    /// Get the next serial that is not waiting for a reply.
+    fn get_serial(&mut self) -> crate::Result<NonZeroU32> {
+        let pending_replies = &self.pending_replies;
+        next_serial(&mut self.serial, pending_replies.len(), |serial| {
+            pending_replies.contains(serial)
+        })
     }
 
     /// Send marshalled message.
     async fn send_message(&mut self, message: &Message) -> crate::Result<()> {
-        log::debug!("Marshalling message");
-        let marshalled = message.marshal_be()?;
+        write_message(&mut self.writer, &mut self.encoder, message).await
+    }
 
-        // debug_assert_eq!(
-        //     message,
-        //     &crate::type_system::unmarshall::unmarshal_message(&marshalled)?
-        // );
+    /// DBus method call, with reply.
+    ///
+    /// Other messages that arrive in the meantime, such as signals and incoming method calls,
+    /// are queued for later reads, for example by [Connection::serve] or
+    /// [Connection::wait_for_signal]. Late replies to calls whose future was dropped before the
+    /// reply arrived are discarded.
+    ///
+    /// An error reply is returned as [crate::Error::ErrorReply].
+    pub async fn call_method_expect_reply(&mut self, message: &Message) -> crate::Result<Message> {
+        self.call_method_until(message, None).await
+    }
 
-        log::debug!("Transmitting message");
-        self.writer.write_all(&marshalled).await?;
-        self.writer.flush().await?;
-        Ok(())
+    /// DBus method call, with reply, like [Connection::call_method_expect_reply].
+    ///
+    /// If the reply has not arrived within the timeout, this fails with [crate::Error::Timeout].
+    /// If a message has started to arrive but is not complete by then, the stream can not be
+    /// read any further, and this fails with [crate::Error::Disconnected]. The connection can then
+    /// be re-established with [Connection::reconnect].
+    pub async fn call_method_expect_reply_timeout(
+        &mut self,
+        message: &Message,
+        timeout: Duration,
+    ) -> crate::Result<Message> {
+        let deadline = self.timer.sleep(timeout);
+        self.call_method_until(message, Some(deadline)).await
     }
 
-    /// DBus method call, with reply.
-    pub async fn call_method_expect_reply(&mut self, message: &Message) -> crate::Result<()> {
-        self.send_message(&message).await?;
+    /// DBus method call, with a reply that is converted to Rust values.
+    ///
+    /// Fails with [crate::Error::UnexpectedReplySignature] if the signature of the reply does not
+    /// match the requested type.
+    ///
+    /// Example: `let (n, name): (u32, String) = conn.call_expecting(&message).await?;`
+    pub async fn call_expecting<T: FromBody>(&mut self, message: &Message) -> crate::Result<T> {
+        let reply = self.call_method_expect_reply(message).await?;
+
+        let signature = reply.body.signature();
+        if !T::matches_signature(&signature) {
+            return Err(crate::Error::UnexpectedReplySignature(signature));
+        }
+
+        T::from_body(reply.body.arguments)
+    }
+
+    /// DBus method call, with reply, that fails when the deadline completes, if any.
+    async fn call_method_until(
+        &mut self,
+        message: &Message,
+        mut deadline: Option<Sleep>,
+    ) -> crate::Result<Message> {
+        let mut pending = PendingReply::new(self, message.serial);
+
+        pending.send_message(message).await?;
+
         log::debug!("Reading");
-        self.read_header_and_body().await?;
+        let reply = loop {
+            let reply = match &mut deadline {
+                Some(deadline) => {
+                    if !pending.wait_readable(deadline).await? {
+                        return Err(crate::Error::Timeout);
+                    }
+                    pending.read_message_before(deadline).await?
+                }
+                None => pending.read_message_from_stream().await?,
+            };
+            if reply.reply_serial() == Some(Serial::from(message.serial)) {
+                break reply;
+            }
+            log::debug!(
+                "Queueing message that is not the reply to {}",
+                message.serial
+            );
+            pending.queued.push_back(reply);
+        };
 
-        todo!("Complete this. Read and unmarshal the whole message");
+        drop(pending);
 
-        Ok(())
+        match &reply.message_type_param {
+            MessageTypeParam::Error(error) => {
+                Err(crate::Error::ErrorReply(error.error_name.string.clone()))
+            }
+            _ => Ok(reply),
+        }
     }
 
-    async fn read_header_and_body(&mut self) -> crate::Result<()> {
-        // length of body is declared in header.
-        // length of header itself isn't actually known - must parse the header fields in a streaming fashion.
-        let mut buf = [0; 12];
-        self.reader.read_exact(&mut buf).await?;
+    /// Wait until there is something to read, or until the timer completes. Returns whether
+    /// there is something to read.
+    ///
+    /// Unlike reading a message, waiting for buffered data can be given up without losing any
+    /// data. The end of the stream also counts, so that reading reports it.
+    async fn wait_readable(&mut self, timer: &mut Sleep) -> crate::Result<bool> {
+        let reader = &mut self.reader;
+        let readable = smol::future::or(async { reader.fill_buf().await.map(|_| true) }, async {
+            timer.await;
+            Ok(false)
+        })
+        .await?;
 
-        dbg!(buf);
+        Ok(readable)
+    }
 
-        Ok(())
+    /// Read one complete message from the stream, failing if the timer completes first.
+    ///
+    /// Giving up in the middle of a message leaves the stream at an unknown position, so the
+    /// connection is lost, and this fails with [crate::Error::Disconnected].
+    async fn read_message_before(&mut self, timer: &mut Sleep) -> crate::Result<Message> {
+        smol::future::or(self.read_message_from_stream(), async {
+            timer.await;
+            log::warn!("Message was not received completely in time");
+            Err(crate::Error::Disconnected)
+        })
+        .await
+    }
+
+    /// Next received message, either one that was queued or else one read from the stream.
+    async fn read_message(&mut self) -> crate::Result<Message> {
+        match self.queued.pop_front() {
+            Some(message) => Ok(message),
+            None => self.read_message_from_stream().await,
+        }
+    }
+
+    /// Read one complete message from the stream and unmarshal it, skipping the queued messages.
+    ///
+    /// Replies to keepalive pings are consumed here. Replies to calls that are no longer pending,
+    /// because their future was dropped, are discarded.
+    async fn read_message_from_stream(&mut self) -> crate::Result<Message> {
+        loop {
+            self.keepalive_wait().await?;
+
+            let message = read_message(
+                &mut self.reader,
+                self.max_message_size,
+                self.received_fds.as_ref(),
+            )
+            .await?;
+            if self.keepalive_reply(&message) {
+                continue;
+            }
+
+            match message.reply_serial() {
+                Some(Serial(serial)) if !self.pending_replies.contains(&serial) => {
+                    log::debug!("Discarding late reply {}", message.summary());
+                }
+                _ => return Ok(message),
+            }
+        }
     }
 
     fn formulate_message(
@@ -134,45 +482,34 @@ impl Connection {
         message_type_param: MessageTypeParam,
         destination: Option<DBusString>,
         body: Body,
-    ) -> Message {
-        let serial = self.get_serial();
+    ) -> crate::Result<Message> {
+        let serial = self.get_serial()?;
 
-        Message {
+        Ok(Message {
             flag_no_reply_expected: false,
             flag_no_auto_start: false,
             flag_allow_interactive_authorization: false,
             serial,
             message_type_param,
             destination,
+            sender: None,
+            unix_fds: None,
+            received_fds: Vec::new(),
             body,
-        }
+        })
     }
 
     /// Spec requires us to say hello on new connections immediately after AUTH.
     async fn say_hello(&mut self) -> crate::Result<()> {
-        let destination = DBusString::from("org.freedesktop.DBus");
-        let path = DBusObjectPath::from("/org/freedesktop/DBus");
-        let interface = DBusString::from("org.freedesktop.DBus");
-        let member = DBusString::from("Hello");
-
-        let body = Body { arguments: vec![] };
-
-        let method_call = MethodCall {
-            path,
-            interface: Some(interface),
-            member,
-        };
+        let reply = self.call_bus_method("Hello", vec![]).await?;
 
-        let message = self.formulate_message(
-            MessageTypeParam::MethodCall(method_call),
-            Some(destination),
-            body,
-        );
-
-        let reply = self.call_method_expect_reply(&message).await?;
-        todo!("What to do with the reply?");
-
-        Ok(())
+        match reply.body.arguments.as_slice() {
+            [Type::String(unique_name)] => {
+                self.unique_name = Some(unique_name.string.clone());
+                Ok(())
+            }
+            _ => Err(crate::Error::UnexpectedReply),
+        }
     }
 
     /// Get AUTH EXTERNAL parameter for unix: UID as hex.
@@ -207,21 +544,44 @@ impl Connection {
 
     /// Authenticate with the DBus.
     async fn auth(&mut self) -> crate::Result<()> {
-        // Send AUTH EXTERNAL
-        self.auth_write_line(format!(
-            "AUTH EXTERNAL {}",
-            Self::get_auth_external_param()?
-        ))
-        .await?;
+        match self.auth_mechanism {
+            AuthMechanism::External => {
+                // Send AUTH EXTERNAL
+                self.auth_write_line(format!(
+                    "AUTH EXTERNAL {}",
+                    Self::get_auth_external_param()?
+                ))
+                .await?;
+            }
+        }
 
-        // Expect to get OK from server
+        // Expect to get OK from server. REJECTED means that the credentials were not accepted,
+        // while ERROR means that the server did not understand the command.
         let line: String = self.auth_read_line().await?;
+        if line.starts_with("ERROR") {
+            return Err(crate::Error::AuthError(line));
+        }
         if !line.starts_with("OK") {
             return Err(crate::Error::FailedAuth);
         }
 
-        // Send BEGIN command
-        self.auth_write_line("BEGIN").await?;
+        // Ask the server to send file descriptors, if they can be received on this stream. If the
+        // server does not agree, the connection works without them.
+        if self.received_fds.is_some() {
+            self.auth_write_line("NEGOTIATE_UNIX_FD").await?;
+            let line: String = self.auth_read_line().await?;
+            if !line.starts_with("AGREE_UNIX_FD") {
+                log::info!("Server does not pass unix file descriptors.");
+            }
+        }
+
+        // Send BEGIN command. If that fails, the server hung up in the middle of the handshake.
+        self.auth_write_line("BEGIN")
+            .await
+            .map_err(|err| match err {
+                crate::Error::IoError(_) => crate::Error::AuthHandshakeIncomplete,
+                err => err,
+            })?;
 
         Ok(())
     }
@@ -252,14 +612,18 @@ impl Connection {
     async fn auth_read_line(&mut self) -> crate::Result<String> {
         let mut line: String = String::new();
 
-        self.reader.read_line(&mut line).await?;
-        debug_assert!(line.ends_with('\n'));
+        if self.reader.read_line(&mut line).await? == 0 {
+            return Err(crate::Error::Disconnected);
+        }
 
         // In DBus, \r\n indicates a line ending, but Rust will split on \n in `read_line` above.
         // So we need to check that we found a \r\n line ending.
         // These messages are not expected to span multiple lines, so we should expect that
-        // the \n character we found is preceded by \r.
-        assert!(line.ends_with("\r\n"));
+        // the \n character we found is preceded by \r. The line may also have been cut off by the
+        // peer closing the connection.
+        if !line.ends_with("\r\n") {
+            return Err(crate::Error::AuthProtocol(line));
+        }
 
         // Pop the trailing "\r\n" from the line.
         line.pop();
@@ -270,3 +634,780 @@ impl Connection {
         Ok(line)
     }
 }
+
+/// Get the next serial after the given one that is not waiting for a reply.
+///
+/// Serials wrap around from `u32::MAX` to 1, since 0 is not a valid serial.
+fn next_serial<F>(
+    serial: &mut u32,
+    count_pending: usize,
+    is_pending: F,
+) -> crate::Result<NonZeroU32>
+where
+    F: Fn(&NonZeroU32) -> bool,
+{
+    if count_pending >= u32::MAX as usize {
+        return Err(crate::Error::NoFreeSerial);
+    }
+
+    loop {
+        *serial = serial.checked_add(1).unwrap_or(1);
+
+        if let Some(serial) = NonZeroU32::new(*serial) {
+            if !is_pending(&serial) {
+                return Ok(serial);
+            }
+        }
+    }
+}
+
+/// Marshal a message and send it.
+async fn write_message(
+    writer: &mut Writer,
+    encoder: &mut Encoder,
+    message: &Message,
+) -> crate::Result<()> {
+    log::debug!("Marshalling message");
+    encoder.reset();
+    message.marshal_into(encoder, Endianness::BigEndian)?;
+    let marshalled = &encoder.buf;
+
+    // debug_assert_eq!(
+    //     message,
+    //     &crate::type_system::unmarshall::unmarshal_message(&marshalled)?
+    // );
+
+    log::trace!("Sending {} ({} bytes)", message.summary(), marshalled.len());
+
+    log::debug!("Transmitting message");
+    writer.write_all(marshalled).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Read one complete message from the stream and unmarshal it.
+///
+/// Messages of unknown type are skipped, as required by the spec.
+///
+/// The file descriptors that accompany the message are taken from the received file
+/// descriptors, if the stream can receive them.
+async fn read_message(
+    reader: &mut Reader,
+    max_message_size: usize,
+    received_fds: Option<&FdQueue>,
+) -> crate::Result<Message> {
+    loop {
+        let marshalled = read_marshalled_message(reader, max_message_size).await?;
+
+        match unmarshal_message(&marshalled) {
+            Err(crate::Error::UnknownMessageType(message_type)) => {
+                log::debug!("Ignoring message of unknown type {}", message_type);
+            }
+            Ok(mut message) => {
+                if let (Some(received_fds), Some(unix_fds)) = (received_fds, message.unix_fds) {
+                    message.received_fds = unix_fds::take_fds(received_fds, unix_fds)?;
+                }
+
+                log::trace!(
+                    "Received {} ({} bytes)",
+                    message.summary(),
+                    marshalled.len()
+                );
+                return Ok(message);
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Read the bytes of one complete message.
+///
+/// The first 16 bytes contain the fixed part of the header followed by the length of the
+/// header fields array. The header is padded to an 8-byte boundary, and the length of the body
+/// is declared in the fixed part of the header, so this is enough to know how much to read.
+async fn read_marshalled_message(
+    reader: &mut Reader,
+    max_message_size: usize,
+) -> crate::Result<Vec<u8>> {
+    let mut buf: Vec<u8> = vec![0; 16];
+    read_exact(reader, &mut buf).await?;
+
+    let parse_u32 = match buf[0] {
+        b'B' => u32::from_be_bytes,
+        b'l' => u32::from_le_bytes,
+        _ => return Err(crate::Error::ParseError),
+    };
+    let length_of_body = parse_u32([buf[4], buf[5], buf[6], buf[7]]);
+    let length_of_header_fields = parse_u32([buf[12], buf[13], buf[14], buf[15]]);
+
+    // The lengths are given by the peer, so they are checked before allocating for them.
+    let length_of_header = 16 + u64::from(length_of_header_fields);
+    let length_of_padded_header = length_of_header.div_ceil(8) * 8;
+    let length_of_message = length_of_padded_header + u64::from(length_of_body);
+    if length_of_message > u64::try_from(max_message_size)? {
+        return Err(crate::Error::MessageTooLarge(length_of_message));
+    }
+    let length_of_message = usize::try_from(length_of_message)?;
+
+    buf.resize(length_of_message, 0);
+    read_exact(reader, &mut buf[16..]).await?;
+
+    Ok(buf)
+}
+
+/// Fill the buffer from the stream.
+///
+/// Returns [crate::Error::Disconnected] if the stream ends before the buffer is filled.
+async fn read_exact(reader: &mut Reader, mut buf: &mut [u8]) -> crate::Result<()> {
+    while !buf.is_empty() {
+        match reader.read(buf).await? {
+            0 => return Err(crate::Error::Disconnected),
+            n => buf = &mut buf[n..],
+        }
+    }
+
+    Ok(())
+}
+
+/// Serial of a method call that is waiting for a reply.
+///
+/// The serial is removed from the pending replies when this is dropped, also when the future
+/// waiting for the reply is dropped before it completes.
+struct PendingReply<'a> {
+    connection: &'a mut Connection,
+    serial: NonZeroU32,
+}
+
+impl<'a> PendingReply<'a> {
+    fn new(connection: &'a mut Connection, serial: NonZeroU32) -> Self {
+        connection.pending_replies.insert(serial);
+
+        Self { connection, serial }
+    }
+}
+
+impl Deref for PendingReply<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.connection
+    }
+}
+
+impl DerefMut for PendingReply<'_> {
+    fn deref_mut(&mut self) -> &mut Connection {
+        self.connection
+    }
+}
+
+impl Drop for PendingReply<'_> {
+    fn drop(&mut self) {
+        self.connection.pending_replies.remove(&self.serial);
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::message_protocol::MethodCall;
+    use crate::message_protocol::MethodReturn;
+    use crate::message_protocol::Signal;
+
+    /// Marshal a method return with the given body, as a reply from the message bus to the first
+    /// method call on a connection.
+    pub(crate) fn method_return(body: Body) -> crate::Result<Vec<u8>> {
+        method_return_to(NonZeroU32::new(1).unwrap(), body)
+    }
+
+    /// Marshal a method return with the given body, as a reply from the message bus to the
+    /// method call with the given serial.
+    pub(crate) fn method_return_to(reply_serial: NonZeroU32, body: Body) -> crate::Result<Vec<u8>> {
+        let message = Message {
+            flag_no_reply_expected: true,
+            message_type_param: MessageTypeParam::MethodReturn(MethodReturn { reply_serial }),
+            sender: Some(DBusString::from("org.freedesktop.DBus")),
+            body,
+            ..crate::message_protocol::tests::method_call(1)
+        };
+
+        message.marshal_be()
+    }
+
+    /// Marshal an error with the given name, as a reply from the message bus to the first method
+    /// call on a connection.
+    pub(crate) fn error_reply(error_name: &str) -> crate::Result<Vec<u8>> {
+        let message = Message {
+            flag_no_reply_expected: true,
+            message_type_param: MessageTypeParam::Error(crate::message_protocol::Error {
+                error_name: DBusString::from(error_name),
+                reply_serial: NonZeroU32::new(1).unwrap(),
+            }),
+            sender: Some(DBusString::from("org.freedesktop.DBus")),
+            ..crate::message_protocol::tests::method_call(1)
+        };
+
+        message.marshal_be()
+    }
+
+    /// Connection that reads the given marshalled messages, and discards everything written.
+    pub(crate) fn connection_with_replies(replies: &[Vec<u8>]) -> Connection {
+        Connection::new(smol::io::Cursor::new(replies.concat()), smol::io::sink())
+    }
+
+    #[test]
+    fn read_message_disconnected() {
+        // First 10 bytes of a message, then end of stream.
+        let partial: Vec<u8> = vec![b'B', 2, 0, 1, 0, 0, 0, 0, 0, 0];
+        let mut conn = Connection::new(smol::io::Cursor::new(partial), smol::io::sink());
+
+        let result = smol::block_on(conn.read_message());
+        assert!(matches!(result, Err(crate::Error::Disconnected)));
+    }
+
+    /// Logger that keeps every record, so that tests can check what was logged.
+    struct RecordingLogger;
+
+    static RECORDED: std::sync::Mutex<Vec<String>> = std::sync::Mutex::new(Vec::new());
+
+    impl log::Log for RecordingLogger {
+        fn enabled(&self, _metadata: &log::Metadata<'_>) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record<'_>) {
+            RECORDED.lock().unwrap().push(record.args().to_string());
+        }
+
+        fn flush(&self) {}
+    }
+
+    /// Restores the maximum log level and clears the recorded logs when dropped, so that the
+    /// other tests do not format trace output.
+    struct RestoreLogLevel(log::LevelFilter);
+
+    impl Drop for RestoreLogLevel {
+        fn drop(&mut self) {
+            log::set_max_level(self.0);
+            RECORDED.lock().unwrap().clear();
+        }
+    }
+
+    #[test]
+    fn trace_logging_round_trip() -> crate::Result<()> {
+        static LOGGER: RecordingLogger = RecordingLogger;
+        let _ = log::set_logger(&LOGGER);
+        let _restore = RestoreLogLevel(log::max_level());
+        log::set_max_level(log::LevelFilter::Trace);
+
+        let mut sender = Connection::new(smol::io::empty(), smol::io::sink());
+        let signal = Signal {
+            path: DBusObjectPath::from("/org/example/Traced"),
+            interface: DBusString::from("org.example.Interface"),
+            member: DBusString::from("Changed"),
+        };
+        let message = sender.formulate_message(
+            MessageTypeParam::Signal(signal),
+            None,
+            Body {
+                arguments: vec![Type::from(DBusUint32::from(7_u32))],
+            },
+        )?;
+        smol::block_on(sender.send_message(&message))?;
+
+        let mut receiver = connection_with_replies(&[message.marshal_be()?]);
+        assert_eq!(smol::block_on(receiver.read_message())?, message);
+
+        // The summary is followed by the size in bytes.
+        let summary = "Signal serial=1 path=/org/example/Traced interface=org.example.Interface \
+                       member=Changed signature=u (";
+        let logged = |prefix: &str| {
+            let line = format!("{} {}", prefix, summary);
+            RECORDED
+                .lock()
+                .unwrap()
+                .iter()
+                .any(|record| record.starts_with(&line) && record.ends_with(" bytes)"))
+        };
+        assert!(logged("Sending"));
+        assert!(logged("Received"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn read_message_skips_unknown_message_type() -> crate::Result<()> {
+        let mut unknown = method_return(Body::default())?;
+        unknown[1] = 5;
+        let mut conn = connection_with_replies(&[unknown, method_return(Body::default())?]);
+        conn.pending_replies.insert(NonZeroU32::new(1).unwrap());
+
+        let message = smol::block_on(conn.read_message())?;
+        assert!(matches!(
+            message.message_type_param,
+            MessageTypeParam::MethodReturn(_)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn call_expecting() -> crate::Result<()> {
+        let body = || Body {
+            arguments: vec![
+                Type::from(DBusUint32::from(42_u32)),
+                Type::from(DBusString::from("name")),
+            ],
+        };
+        let mut conn = connection_with_replies(&[
+            method_return(body())?,
+            method_return_to(NonZeroU32::new(2).unwrap(), body())?,
+        ]);
+        let method_call = |conn: &mut Connection| {
+            conn.formulate_message(
+                MessageTypeParam::MethodCall(MethodCall {
+                    path: DBusObjectPath::from("/org/example/Object"),
+                    interface: Some(DBusString::from("org.example.Interface")),
+                    member: DBusString::from("Get"),
+                }),
+                None,
+                Body::default(),
+            )
+        };
+
+        let message = method_call(&mut conn)?;
+        let (n, name): (u32, String) = smol::block_on(conn.call_expecting(&message))?;
+        assert_eq!(n, 42);
+        assert_eq!(name, "name");
+
+        let message = method_call(&mut conn)?;
+        let result: crate::Result<(String, u32)> = smol::block_on(conn.call_expecting(&message));
+        assert!(matches!(
+            result,
+            Err(crate::Error::UnexpectedReplySignature(_))
+        ));
+
+        Ok(())
+    }
+
+    /// Writer that fails when BEGIN is sent, as if the server hung up after OK.
+    struct FailOnBegin;
+
+    impl AsyncWrite for FailOnBegin {
+        fn poll_write(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+            buf: &[u8],
+        ) -> std::task::Poll<std::io::Result<usize>> {
+            if buf.starts_with(b"BEGIN") {
+                std::task::Poll::Ready(Err(std::io::ErrorKind::BrokenPipe.into()))
+            } else {
+                std::task::Poll::Ready(Ok(buf.len()))
+            }
+        }
+
+        fn poll_flush(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+    }
+
+    #[test]
+    fn auth_failures() {
+        let auth = |server: &str, writer: Box<dyn AsyncWrite + Unpin + Send>| {
+            let reader = smol::io::Cursor::new(server.as_bytes().to_vec());
+            let mut conn = Connection::new(reader, writer);
+            smol::block_on(conn.auth())
+        };
+
+        assert!(matches!(
+            auth("REJECTED EXTERNAL\r\n", Box::new(smol::io::sink())),
+            Err(crate::Error::FailedAuth)
+        ));
+        assert!(matches!(
+            auth("ERROR \"Unknown command\"\r\n", Box::new(smol::io::sink())),
+            Err(crate::Error::AuthError(_))
+        ));
+        assert!(matches!(
+            auth("OK 0123456789abcdef\r\n", Box::new(FailOnBegin)),
+            Err(crate::Error::AuthHandshakeIncomplete)
+        ));
+        assert!(auth("OK 0123456789abcdef\r\n", Box::new(smol::io::sink())).is_ok());
+    }
+
+    #[test]
+    fn auth_read_line_malformed() {
+        let read_line = |server: &str| {
+            let reader = smol::io::Cursor::new(server.as_bytes().to_vec());
+            let mut conn = Connection::new(reader, smol::io::sink());
+            smol::block_on(conn.auth_read_line())
+        };
+
+        assert_eq!(read_line("OK 1234\r\n").unwrap(), "OK 1234");
+        assert!(matches!(
+            read_line("OK 1234\n"),
+            Err(crate::Error::AuthProtocol(_))
+        ));
+        assert!(matches!(
+            read_line("OK 12"),
+            Err(crate::Error::AuthProtocol(_))
+        ));
+        assert!(matches!(read_line(""), Err(crate::Error::Disconnected)));
+    }
+
+    #[test]
+    fn get_serial_wraps_around() -> crate::Result<()> {
+        let mut conn = connection_with_replies(&[]);
+        conn.serial = u32::MAX - 1;
+
+        assert_eq!(conn.get_serial()?.get(), u32::MAX);
+        assert_eq!(conn.get_serial()?.get(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn get_serial_skips_pending() -> crate::Result<()> {
+        let mut conn = connection_with_replies(&[]);
+        conn.serial = u32::MAX;
+        conn.pending_replies.insert(NonZeroU32::new(1).unwrap());
+
+        assert_eq!(conn.get_serial()?.get(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn read_marshalled_message_framing() -> crate::Result<()> {
+        let mut bytes: Vec<u8> = vec![
+            b'l', 2, 0, 1, // Endianness, type, flags, version
+            4, 0, 0, 0, // Length of body
+            1, 0, 0, 0, // Serial
+            5, 0, 0, 0, // Length of header fields array
+            0, 0, 0, 0, 0, // Header fields
+            0, 0, 0, // Padding to 8-byte boundary
+            9, 9, 9, 9, // Body
+        ];
+        let length_of_message = bytes.len();
+        // Start of the next message.
+        bytes.extend_from_slice(&[b'l', 2]);
+
+        let mut conn = Connection::new(smol::io::Cursor::new(bytes), smol::io::sink());
+        let marshalled = smol::block_on(read_marshalled_message(
+            &mut conn.reader,
+            crate::MAX_MESSAGE_SIZE,
+        ))?;
+        assert_eq!(marshalled.len(), length_of_message);
+        assert_eq!(&marshalled[length_of_message - 4..], &[9, 9, 9, 9]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn read_message_too_large() {
+        let bytes: Vec<u8> = vec![
+            b'l', 2, 0, 1, // Endianness, type, flags, version
+            0xff, 0xff, 0xff, 0xff, // Length of body
+            1, 0, 0, 0, // Serial
+            0, 0, 0, 0, // Length of header fields array
+        ];
+
+        let mut conn = Connection::new(smol::io::Cursor::new(bytes), smol::io::sink());
+        let result = smol::block_on(conn.read_message());
+        assert!(matches!(
+            result,
+            Err(crate::Error::MessageTooLarge(0x1_0000_000f))
+        ));
+
+        // Also a message within the spec limit can be rejected.
+        let mut conn = connection_with_replies(&[method_return(Body::default()).unwrap()]);
+        conn.set_max_message_size(16);
+        let result = smol::block_on(conn.read_message());
+        assert!(matches!(result, Err(crate::Error::MessageTooLarge(_))));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn connect_to_abstract_socket() -> crate::Result<()> {
+        use std::os::linux::net::SocketAddrExt;
+        use std::os::unix::net::SocketAddr;
+        use std::os::unix::net::UnixListener;
+
+        let name = format!("dbus-stream-test-{}", std::process::id()).into_bytes();
+        let _listener = UnixListener::bind_addr(&SocketAddr::from_abstract_name(&name)?)?;
+
+        smol::block_on(Connection::connect_to_address(&Address::UnixAbstract(name)))?;
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn dropped_call_removes_pending_reply() -> crate::Result<()> {
+        use smol::net::unix::UnixStream;
+
+        let (stream, mut bus) = UnixStream::pair()?;
+        let mut conn = Connection::new(stream.clone(), stream);
+
+        smol::block_on(async {
+            // Send the call, and give up while waiting for the reply.
+            let mut call = Box::pin(conn.call_bus_method("Hello", vec![]));
+            assert!(smol::future::poll_once(&mut call).await.is_none());
+            drop(call);
+
+            assert!(conn.pending_replies.is_empty());
+
+            // The late reply to the first call and a signal arrive before the reply to the
+            // second call.
+            let late = method_return(Body {
+                arguments: vec![Type::from(DBusString::from(":1.1"))],
+            })?;
+            let signal = Message {
+                flag_no_reply_expected: true,
+                message_type_param: MessageTypeParam::Signal(Signal {
+                    path: DBusObjectPath::from("/org/example/Object"),
+                    interface: DBusString::from("org.example.Interface"),
+                    member: DBusString::from("Changed"),
+                }),
+                ..crate::message_protocol::tests::method_call(1)
+            };
+            let id = Body {
+                arguments: vec![Type::from(DBusString::from("0123456789abcdef"))],
+            };
+            bus.write_all(&late).await?;
+            bus.write_all(&signal.marshal_be()?).await?;
+            bus.write_all(&method_return_to(NonZeroU32::new(2).unwrap(), id)?)
+                .await?;
+
+            let reply = conn.call_bus_method("GetId", vec![]).await?;
+            assert_eq!(
+                reply.body.extract::<(String,)>()?,
+                ("0123456789abcdef".to_string(),)
+            );
+            assert!(conn.pending_replies.is_empty());
+
+            // The signal was queued, and the late reply was discarded.
+            assert_eq!(conn.read_message().await?, signal);
+            assert!(conn.queued.is_empty());
+
+            // Late replies are also discarded when reading without waiting for a reply.
+            bus.write_all(&late).await?;
+            bus.write_all(&signal.marshal_be()?).await?;
+            assert_eq!(conn.read_message().await?, signal);
+
+            crate::Result::Ok(())
+        })
+    }
+
+    /// Timer that fires immediately.
+    struct ExpiredTimer;
+
+    impl Timer for ExpiredTimer {
+        fn sleep(&self, _duration: Duration) -> Sleep {
+            Box::pin(async {})
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn call_method_timeout() -> crate::Result<()> {
+        use smol::net::unix::UnixStream;
+
+        let ping =
+            |conn: &mut Connection| conn.peer_method_call("org.freedesktop.DBus", "/", "Ping");
+
+        // The bus never replies.
+        let (stream, _bus) = UnixStream::pair()?;
+        let mut conn = Connection::new(stream.clone(), stream);
+        conn.set_timer(ExpiredTimer);
+
+        let message = ping(&mut conn)?;
+        let result =
+            smol::block_on(conn.call_method_expect_reply_timeout(&message, Duration::from_secs(1)));
+        assert!(matches!(result, Err(crate::Error::Timeout)));
+        assert!(conn.pending_replies.is_empty());
+
+        // A reply that can already be read is not timed out.
+        let mut conn = connection_with_replies(&[method_return(Body::default())?]);
+        conn.set_timer(ExpiredTimer);
+
+        let message = ping(&mut conn)?;
+        smol::block_on(conn.call_method_expect_reply_timeout(&message, Duration::from_secs(1)))?;
+
+        // A reply that has only partly arrived fails the connection.
+        let (stream, mut bus) = UnixStream::pair()?;
+        let mut conn = Connection::new(stream.clone(), stream);
+        conn.set_timer(ExpiredTimer);
+
+        let message = ping(&mut conn)?;
+        let reply = method_return_to(message.serial, Body::default())?;
+        let result = smol::block_on(async {
+            bus.write_all(&reply[..10]).await?;
+            conn.call_method_expect_reply_timeout(&message, Duration::from_secs(1))
+                .await
+        });
+        assert!(matches!(result, Err(crate::Error::Disconnected)));
+        assert!(conn.pending_replies.is_empty());
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn call_and_reply_without_arguments() -> crate::Result<()> {
+        use smol::net::unix::UnixStream;
+
+        let (stream, mut bus) = UnixStream::pair()?;
+        let mut conn = Connection::new(stream.clone(), stream);
+
+        smol::block_on(async {
+            let mut call = Box::pin(conn.call_bus_method("ReloadConfig", vec![]));
+            assert!(smol::future::poll_once(&mut call).await.is_none());
+
+            let mut fixed = [0_u8; 16];
+            bus.read_exact(&mut fixed).await?;
+            let body_len = u32::from_be_bytes([fixed[4], fixed[5], fixed[6], fixed[7]]) as usize;
+            let fields_len =
+                u32::from_be_bytes([fixed[12], fixed[13], fixed[14], fixed[15]]) as usize;
+            let mut rest = vec![0; (16 + fields_len).div_ceil(8) * 8 - 16 + body_len];
+            bus.read_exact(&mut rest).await?;
+
+            // No body, and no SIGNATURE header field: each field is an 8-aligned struct starting
+            // with the field code 8 and the variant signature "g".
+            assert_eq!(body_len, 0);
+            assert!(!rest[..fields_len]
+                .chunks(8)
+                .any(|field| field.starts_with(&[8, 1, b'g', 0])));
+
+            let call_message = unmarshal_message(&[&fixed[..], &rest].concat())?;
+            assert!(call_message.body.is_empty());
+
+            bus.write_all(&method_return_to(call_message.serial, Body::default())?)
+                .await?;
+
+            let reply = call.await?;
+            assert!(reply.body.is_empty());
+            reply.body.extract::<()>()?;
+
+            crate::Result::Ok(())
+        })
+    }
+
+    /// Accept a connection on behalf of the message bus, and handle AUTH and Hello.
+    #[cfg(unix)]
+    fn accept_hello(
+        listener: &std::os::unix::net::UnixListener,
+        unique_name: &str,
+    ) -> crate::Result<std::os::unix::net::UnixStream> {
+        let (stream, _) = listener.accept()?;
+        handle_hello(stream, unique_name)
+    }
+
+    /// Handle AUTH and Hello on behalf of the message bus.
+    #[cfg(unix)]
+    fn handle_hello(
+        mut stream: std::os::unix::net::UnixStream,
+        unique_name: &str,
+    ) -> crate::Result<std::os::unix::net::UnixStream> {
+        use std::io::BufRead;
+        use std::io::Read;
+        use std::io::Write;
+
+        let mut reader = std::io::BufReader::new(stream.try_clone()?);
+        let mut line = Vec::new();
+
+        // Null byte and AUTH command.
+        reader.read_until(b'\n', &mut line)?;
+        stream.write_all(b"OK 0123456789abcdef0123456789abcdef\r\n")?;
+
+        // Unix file descriptors are agreed to, then BEGIN command.
+        line.clear();
+        reader.read_until(b'\n', &mut line)?;
+        if line.starts_with(b"NEGOTIATE_UNIX_FD") {
+            stream.write_all(b"AGREE_UNIX_FD\r\n")?;
+            reader.read_until(b'\n', &mut line)?;
+        }
+
+        // Hello. Read all of it, so that dropping the stream does not reset the connection.
+        let mut header = [0; 16];
+        reader.read_exact(&mut header)?;
+        let length_of_body = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+        let length_of_header_fields =
+            u32::from_be_bytes([header[12], header[13], header[14], header[15]]);
+        let length_of_rest =
+            (16 + length_of_header_fields as usize).div_ceil(8) * 8 - 16 + length_of_body as usize;
+        reader.read_exact(&mut vec![0; length_of_rest])?;
+
+        let body = Body {
+            arguments: vec![Type::from(DBusString::from(unique_name))],
+        };
+        stream.write_all(&method_return(body)?)?;
+
+        Ok(stream)
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn from_raw_fd() -> crate::Result<()> {
+        use std::os::unix::io::IntoRawFd;
+        use std::os::unix::net::UnixStream;
+
+        let (stream, bus) = UnixStream::pair()?;
+        let bus = std::thread::spawn(move || handle_hello(bus, ":1.7"));
+
+        let conn = smol::block_on(unsafe { Connection::from_raw_fd(stream.into_raw_fd()) })?;
+        assert_eq!(conn.unique_name(), Some(":1.7"));
+        assert!(conn.address.is_none());
+
+        bus.join().unwrap()?;
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reconnect() -> crate::Result<()> {
+        use std::os::unix::net::UnixListener;
+
+        let path =
+            std::env::temp_dir().join(format!("dbus-stream-test-reconnect-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path)?;
+
+        let bus = std::thread::spawn(move || -> crate::Result<()> {
+            // The first connection is dropped, as if the message bus restarted.
+            drop(accept_hello(&listener, ":1.1")?);
+            accept_hello(&listener, ":1.2")?;
+            Ok(())
+        });
+
+        smol::block_on(async {
+            let mut conn = Connection::connect_to_address(&Address::UnixPath(path.clone())).await?;
+            conn.setup().await?;
+            assert_eq!(conn.unique_name(), Some(":1.1"));
+
+            let result = conn.read_message().await;
+            assert!(matches!(result, Err(crate::Error::Disconnected)));
+
+            conn.reconnect().await?;
+            assert_eq!(conn.unique_name(), Some(":1.2"));
+            // Hello is the first message on the new connection.
+            assert_eq!(conn.serial, 1);
+
+            crate::Result::Ok(())
+        })?;
+
+        bus.join().unwrap()?;
+        std::fs::remove_file(&path)?;
+
+        Ok(())
+    }
+}
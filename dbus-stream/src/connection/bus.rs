@@ -0,0 +1,386 @@
+//! Methods provided by the message bus itself, on the `org.freedesktop.DBus` interface.
+
+use super::Connection;
+use crate::message_protocol::body::Body;
+use crate::message_protocol::Message;
+use crate::message_protocol::MessageTypeParam;
+use crate::message_protocol::MethodCall;
+use crate::type_system::types::*;
+
+/// Reply to [Connection::start_service].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StartServiceReply {
+    /// The service was successfully started.
+    Started,
+
+    /// A connection already owns the given name.
+    AlreadyRunning,
+}
+
+impl StartServiceReply {
+    fn from_body(body: &Body) -> crate::Result<Self> {
+        match body.arguments.as_slice() {
+            [Type::Uint32(DBusUint32 { u32: 1 })] => Ok(Self::Started),
+            [Type::Uint32(DBusUint32 { u32: 2 })] => Ok(Self::AlreadyRunning),
+            _ => Err(crate::Error::UnexpectedReply),
+        }
+    }
+}
+
+/// Credentials of a connection, as returned by [Connection::get_connection_credentials].
+///
+/// The message bus may leave out credentials that are not known or not supported on the platform.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Credentials {
+    /// Unix user ID of the process that owns the connection.
+    pub unix_user_id: Option<u32>,
+
+    /// Process ID of the process that owns the connection.
+    pub process_id: Option<u32>,
+
+    /// Security label of the connection, as understood by the Linux security module in use.
+    ///
+    /// The label is given as raw bytes, including the trailing null byte if the bus sent it.
+    pub linux_security_label: Option<Vec<u8>>,
+}
+
+impl Credentials {
+    fn from_body(body: &Body) -> crate::Result<Self> {
+        let dict = match body.arguments.as_slice() {
+            [Type::Array(dict)] => dict,
+            _ => return Err(crate::Error::UnexpectedReply),
+        };
+
+        let mut credentials = Self::default();
+
+        for item in &dict.items {
+            let (key, value) = match item {
+                Type::DictEntry(DBusDictEntry { key, value }) => match (&**key, &**value) {
+                    (Type::String(key), Type::Variant(value)) => (key, &*value.variant),
+                    _ => return Err(crate::Error::UnexpectedReply),
+                },
+                _ => return Err(crate::Error::UnexpectedReply),
+            };
+
+            match (key.string.as_str(), value) {
+                ("UnixUserID", Type::Uint32(value)) => credentials.unix_user_id = Some(value.u32),
+                ("ProcessID", Type::Uint32(value)) => credentials.process_id = Some(value.u32),
+                ("LinuxSecurityLabel", Type::Array(label)) => {
+                    let label = label
+                        .items
+                        .iter()
+                        .map(|item| match item {
+                            Type::Byte(byte) => Ok(byte.u8),
+                            _ => Err(crate::Error::UnexpectedReply),
+                        })
+                        .collect::<crate::Result<Vec<u8>>>()?;
+                    credentials.linux_security_label = Some(label);
+                }
+                // Other credentials may be added to the spec in the future.
+                _ => {}
+            }
+        }
+
+        Ok(credentials)
+    }
+}
+
+impl Connection {
+    /// Call a method on the message bus.
+    pub(super) async fn call_bus_method(
+        &mut self,
+        member: &str,
+        arguments: Vec<Type>,
+    ) -> crate::Result<Message> {
+        let destination = DBusString::from("org.freedesktop.DBus");
+
+        let method_call = MethodCall {
+            path: DBusObjectPath::from("/org/freedesktop/DBus"),
+            interface: Some(DBusString::from("org.freedesktop.DBus")),
+            member: DBusString::from(member),
+        };
+
+        let message = self.formulate_message(
+            MessageTypeParam::MethodCall(method_call),
+            Some(destination),
+            Body { arguments },
+        )?;
+
+        self.call_method_expect_reply(&message).await
+    }
+
+    /// Try to launch the executable associated with a name (service activation).
+    ///
+    /// The flags are currently not used by the message bus, and should be 0.
+    ///
+    /// If no service is known by that name, the bus replies with the
+    /// `org.freedesktop.DBus.Error.ServiceUnknown` error, which is returned as
+    /// [crate::Error::ErrorReply].
+    pub async fn start_service(
+        &mut self,
+        name: &str,
+        flags: u32,
+    ) -> crate::Result<StartServiceReply> {
+        let arguments = vec![
+            Type::from(DBusString::from(name)),
+            Type::from(DBusUint32::from(flags)),
+        ];
+
+        let reply = self
+            .call_bus_method("StartServiceByName", arguments)
+            .await?;
+
+        StartServiceReply::from_body(&reply.body)
+    }
+
+    /// Get the Unix process ID of the process that owns the given name.
+    ///
+    /// If the name has no owner, the bus replies with the
+    /// `org.freedesktop.DBus.Error.NameHasNoOwner` error, which is returned as
+    /// [crate::Error::ErrorReply].
+    pub async fn get_connection_unix_process_id(&mut self, name: &str) -> crate::Result<u32> {
+        let arguments = vec![Type::from(DBusString::from(name))];
+
+        let reply = self
+            .call_bus_method("GetConnectionUnixProcessID", arguments)
+            .await?;
+
+        match reply.body.arguments.as_slice() {
+            [Type::Uint32(pid)] => Ok(pid.u32),
+            _ => Err(crate::Error::UnexpectedReply),
+        }
+    }
+
+    /// Get the Unix user ID of the process that owns the given name.
+    pub async fn get_connection_unix_user(&mut self, name: &str) -> crate::Result<u32> {
+        let arguments = vec![Type::from(DBusString::from(name))];
+
+        let reply = self
+            .call_bus_method("GetConnectionUnixUser", arguments)
+            .await?;
+
+        match reply.body.arguments.as_slice() {
+            [Type::Uint32(uid)] => Ok(uid.u32),
+            _ => Err(crate::Error::UnexpectedReply),
+        }
+    }
+
+    /// Get the credentials of the process that owns the given name.
+    pub async fn get_connection_credentials(&mut self, name: &str) -> crate::Result<Credentials> {
+        let arguments = vec![Type::from(DBusString::from(name))];
+
+        let reply = self
+            .call_bus_method("GetConnectionCredentials", arguments)
+            .await?;
+
+        Credentials::from_body(&reply.body)
+    }
+
+    /// Get the SELinux security context of the process that owns the given name.
+    ///
+    /// The context is returned as raw bytes. If the bus does not know the context, for example
+    /// because SELinux is not in use, it replies with the
+    /// `org.freedesktop.DBus.Error.SELinuxSecurityContextUnknown` error, which is returned as
+    /// [crate::Error::ErrorReply].
+    pub async fn get_selinux_security_context(&mut self, name: &str) -> crate::Result<Vec<u8>> {
+        let arguments = vec![Type::from(DBusString::from(name))];
+
+        let reply = self
+            .call_bus_method("GetConnectionSELinuxSecurityContext", arguments)
+            .await?;
+
+        match reply.body.arguments.as_slice() {
+            [Type::Array(context)] => context.iter_as().collect(),
+            _ => Err(crate::Error::UnexpectedReply),
+        }
+    }
+
+    /// Get the unique ID of the message bus, as a hex string.
+    ///
+    /// Useful to tell apart the buses of an application that connects to several.
+    pub async fn get_bus_id(&mut self) -> crate::Result<String> {
+        let reply = self.call_bus_method("GetId", vec![]).await?;
+
+        match reply.body.arguments.as_slice() {
+            [Type::String(id)] => Ok(id.string.clone()),
+            _ => Err(crate::Error::UnexpectedReply),
+        }
+    }
+
+    /// List the unique names of the connections waiting in the queue to own the given name,
+    /// starting with the current owner.
+    ///
+    /// If the name has no owner, the bus replies with the
+    /// `org.freedesktop.DBus.Error.NameHasNoOwner` error, which is returned as
+    /// [crate::Error::ErrorReply].
+    pub async fn list_queued_owners(&mut self, name: &str) -> crate::Result<Vec<String>> {
+        let reply = self
+            .call_bus_method("ListQueuedOwners", vec![Type::from(DBusString::from(name))])
+            .await?;
+
+        match reply.body.arguments.as_slice() {
+            [Type::Array(owners)] => owners.iter_as().collect(),
+            _ => Err(crate::Error::UnexpectedReply),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use super::*;
+    use crate::connection::tests::connection_with_replies;
+    use crate::connection::tests::error_reply;
+    use crate::connection::tests::method_return;
+    use crate::connection::tests::method_return_to;
+    use crate::type_system::signature::SingleCompleteTypeSignature;
+
+    fn body_with_u32(value: u32) -> Body {
+        Body {
+            arguments: vec![Type::from(DBusUint32::from(value))],
+        }
+    }
+
+    #[test]
+    fn start_service_reply_decoding() -> crate::Result<()> {
+        assert_eq!(
+            StartServiceReply::from_body(&body_with_u32(1))?,
+            StartServiceReply::Started
+        );
+        assert_eq!(
+            StartServiceReply::from_body(&body_with_u32(2))?,
+            StartServiceReply::AlreadyRunning
+        );
+        assert!(StartServiceReply::from_body(&body_with_u32(3)).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn get_connection_unix_user() -> crate::Result<()> {
+        let mut conn = connection_with_replies(&[method_return(body_with_u32(1000))?]);
+
+        assert_eq!(
+            smol::block_on(conn.get_connection_unix_user(":1.42"))?,
+            1000
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn get_connection_unix_process_id() -> crate::Result<()> {
+        let mut conn = connection_with_replies(&[method_return(body_with_u32(4242))?]);
+        assert_eq!(
+            smol::block_on(conn.get_connection_unix_process_id(":1.42"))?,
+            4242
+        );
+
+        let mut conn =
+            connection_with_replies(&[error_reply("org.freedesktop.DBus.Error.NameHasNoOwner")?]);
+        let result = smol::block_on(conn.get_connection_unix_process_id("org.example.Gone"));
+        assert!(
+            matches!(result, Err(crate::Error::ErrorReply(name)) if name == "org.freedesktop.DBus.Error.NameHasNoOwner")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn get_connection_credentials() -> crate::Result<()> {
+        let mut dict = DBusArray::new(SingleCompleteTypeSignature::DBusDictEntry {
+            key: Box::new(SingleCompleteTypeSignature::DBusString),
+            value: Box::new(SingleCompleteTypeSignature::DBusVariant),
+        });
+        dict.push(DBusDictEntry::new(
+            DBusString::from("UnixUserID"),
+            DBusVariant::new(DBusUint32::from(1000_u32)),
+        ))?;
+        dict.push(DBusDictEntry::new(
+            DBusString::from("ProcessID"),
+            DBusVariant::new(DBusUint32::from(4321_u32)),
+        ))?;
+        let body = Body {
+            arguments: vec![Type::from(dict)],
+        };
+        let mut conn = connection_with_replies(&[method_return(body)?]);
+
+        let credentials = smol::block_on(conn.get_connection_credentials(":1.42"))?;
+        assert_eq!(
+            credentials,
+            Credentials {
+                unix_user_id: Some(1000),
+                process_id: Some(4321),
+                linux_security_label: None,
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn list_queued_owners() -> crate::Result<()> {
+        let owners = Body {
+            arguments: vec![Type::from(vec![":1.7".to_string(), ":1.9".to_string()])],
+        };
+        let no_owners = Body {
+            arguments: vec![Type::from(DBusArray::new(
+                SingleCompleteTypeSignature::DBusString,
+            ))],
+        };
+        let mut conn = connection_with_replies(&[
+            method_return(owners)?,
+            method_return_to(NonZeroU32::new(2).unwrap(), no_owners)?,
+        ]);
+
+        assert_eq!(
+            smol::block_on(conn.list_queued_owners("org.example.Service"))?,
+            vec![":1.7".to_string(), ":1.9".to_string()]
+        );
+        assert!(smol::block_on(conn.list_queued_owners("org.example.Other"))?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn get_selinux_security_context() -> crate::Result<()> {
+        let context: &[u8] = b"system_u:system_r:init_t:s0";
+        let body = Body {
+            arguments: vec![Type::from(context)],
+        };
+        let mut conn = connection_with_replies(&[method_return(body)?]);
+
+        assert_eq!(
+            smol::block_on(conn.get_selinux_security_context(":1.5"))?,
+            context
+        );
+
+        let mut conn = connection_with_replies(&[error_reply(
+            "org.freedesktop.DBus.Error.SELinuxSecurityContextUnknown",
+        )?]);
+        let result = smol::block_on(conn.get_selinux_security_context(":1.5"));
+        assert!(
+            matches!(result, Err(crate::Error::ErrorReply(name)) if name == "org.freedesktop.DBus.Error.SELinuxSecurityContextUnknown")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn get_bus_id() -> crate::Result<()> {
+        let body = Body {
+            arguments: vec![Type::from(DBusString::from(
+                "0a1b2c3d4e5f67890a1b2c3d4e5f6789",
+            ))],
+        };
+        let mut conn = connection_with_replies(&[method_return(body)?]);
+
+        assert_eq!(
+            smol::block_on(conn.get_bus_id())?,
+            "0a1b2c3d4e5f67890a1b2c3d4e5f6789"
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,58 @@
+//! Methods on the `org.freedesktop.DBus.Introspectable` interface.
+
+use super::Connection;
+use crate::introspection;
+use crate::introspection::Node;
+use crate::message_protocol::body::Body;
+use crate::message_protocol::MessageTypeParam;
+use crate::message_protocol::MethodCall;
+use crate::type_system::types::*;
+
+impl Connection {
+    /// Get the interfaces and child nodes of an object, see [introspection::parse].
+    pub async fn introspect(&mut self, destination: &str, path: &str) -> crate::Result<Node> {
+        let method_call = MethodCall {
+            path: DBusObjectPath::from(path),
+            interface: Some(DBusString::from("org.freedesktop.DBus.Introspectable")),
+            member: DBusString::from("Introspect"),
+        };
+
+        let message = self.formulate_message(
+            MessageTypeParam::MethodCall(method_call),
+            Some(DBusString::from(destination)),
+            Body::default(),
+        )?;
+
+        let (xml,): (String,) = self
+            .call_method_expect_reply(&message)
+            .await?
+            .body
+            .extract()?;
+
+        introspection::parse(&xml)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::connection::tests::connection_with_replies;
+    use crate::connection::tests::method_return;
+    use crate::message_protocol::body::Body;
+    use crate::type_system::types::*;
+
+    #[test]
+    fn introspect() -> crate::Result<()> {
+        let xml = r#"<node><interface name="org.example.Interface"/><node name="child"/></node>"#;
+        let body = Body {
+            arguments: vec![Type::from(xml)],
+        };
+        let mut conn = connection_with_replies(&[method_return(body)?]);
+
+        let node = smol::block_on(conn.introspect("org.example.Service", "/org/example"))?;
+
+        assert!(node.interface("org.example.Interface").is_some());
+        assert_eq!(node.nodes[0].name.as_deref(), Some("child"));
+
+        Ok(())
+    }
+}
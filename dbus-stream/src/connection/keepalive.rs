@@ -0,0 +1,209 @@
+//! Keeping idle connections alive by pinging the message bus.
+
+use std::num::NonZeroU32;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::Connection;
+use crate::message_protocol::Message;
+use crate::message_protocol::Serial;
+
+/// Periodic ping of the message bus, see [Connection::enable_keepalive].
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Keepalive {
+    interval: Duration,
+
+    /// Serial of the ping that is waiting for a reply.
+    pending_ping: Option<NonZeroU32>,
+}
+
+impl Keepalive {
+    pub(super) fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            pending_ping: None,
+        }
+    }
+
+    pub(super) fn interval(&self) -> Duration {
+        self.interval
+    }
+}
+
+impl Connection {
+    /// Ping the message bus when no message has been received for the given interval.
+    ///
+    /// If the ping is not answered within another interval, the connection is considered lost and
+    /// reading fails with [crate::Error::Disconnected], after calling the hook set with
+    /// [Connection::set_disconnect_hook]. It can then be re-established with
+    /// [Connection::reconnect].
+    ///
+    /// The keepalive is driven while the connection is reading, for example in
+    /// [Connection::serve] or while waiting for a reply.
+    pub fn enable_keepalive(&mut self, interval: Duration) {
+        self.keepalive = Some(Keepalive::new(interval));
+    }
+
+    pub fn disable_keepalive(&mut self) {
+        self.clear_pending_ping();
+        self.keepalive = None;
+    }
+
+    /// Set a hook that is called when the keepalive detects that the connection is lost, for
+    /// example to schedule a [Connection::reconnect].
+    pub fn set_disconnect_hook<F: Fn() + Send + Sync + 'static>(&mut self, hook: F) {
+        self.disconnect_hook = Some(Arc::new(hook));
+    }
+
+    /// Forget the ping that is waiting for a reply, if any.
+    fn clear_pending_ping(&mut self) {
+        let pending_ping = self
+            .keepalive
+            .as_mut()
+            .and_then(|keepalive| keepalive.pending_ping.take());
+        if let Some(serial) = pending_ping {
+            self.pending_replies.remove(&serial);
+        }
+    }
+
+    /// Wait until there is something to read, pinging the message bus if nothing arrives in time.
+    pub(super) async fn keepalive_wait(&mut self) -> crate::Result<()> {
+        let interval = match &self.keepalive {
+            Some(keepalive) => keepalive.interval,
+            None => return Ok(()),
+        };
+
+        loop {
+            let mut timer = self.timer.sleep(interval);
+            if self.wait_readable(&mut timer).await? {
+                return Ok(());
+            }
+
+            let pending_ping = self.keepalive.and_then(|keepalive| keepalive.pending_ping);
+            if let Some(serial) = pending_ping {
+                log::warn!("Ping {} was not answered in time", serial);
+                self.clear_pending_ping();
+                if let Some(hook) = &self.disconnect_hook {
+                    hook();
+                }
+                return Err(crate::Error::Disconnected);
+            }
+
+            let ping =
+                self.peer_method_call("org.freedesktop.DBus", "/org/freedesktop/DBus", "Ping")?;
+            self.send_message(&ping).await?;
+            self.pending_replies.insert(ping.serial);
+            if let Some(keepalive) = &mut self.keepalive {
+                keepalive.pending_ping = Some(ping.serial);
+            }
+        }
+    }
+
+    /// Whether the message is the reply to a keepalive ping, which is consumed here.
+    pub(super) fn keepalive_reply(&mut self, message: &Message) -> bool {
+        let keepalive = match &mut self.keepalive {
+            Some(keepalive) => keepalive,
+            None => return false,
+        };
+
+        match keepalive.pending_ping {
+            Some(serial) if message.reply_serial() == Some(Serial::from(serial)) => {
+                keepalive.pending_ping = None;
+                self.pending_replies.remove(&serial);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+
+    use super::*;
+    use crate::message_protocol::body::Body;
+    use crate::message_protocol::MessageTypeParam;
+    use crate::timer::Sleep;
+    use crate::Timer;
+
+    /// Timer whose sleeps complete when the test sends a tick, so that intervals pass in a
+    /// deterministic order.
+    struct ManualTimer(smol::channel::Receiver<()>);
+
+    impl Timer for ManualTimer {
+        fn sleep(&self, _duration: Duration) -> Sleep {
+            let ticks = self.0.clone();
+            Box::pin(async move {
+                let _ = ticks.recv().await;
+            })
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keepalive_detects_unanswered_ping() -> crate::Result<()> {
+        use smol::net::unix::UnixStream;
+
+        let (stream, bus) = UnixStream::pair()?;
+        let mut conn = Connection::new(stream.clone(), stream);
+        let mut bus = Connection::new(bus.clone(), bus);
+
+        let (ticks, tick_receiver) = smol::channel::unbounded();
+        conn.set_timer(ManualTimer(tick_receiver));
+        conn.enable_keepalive(Duration::from_secs(1));
+        let disconnects = Arc::new(AtomicUsize::new(0));
+        let counter = disconnects.clone();
+        conn.set_disconnect_hook(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+
+        let bus_side = async {
+            let is_ping = |message: &Message| match &message.message_type_param {
+                MessageTypeParam::MethodCall(method_call) => method_call.member.string == "Ping",
+                _ => false,
+            };
+
+            // Answer two pings, then stop answering without closing the connection. Each
+            // interval passes only after the reply was sent, and the connection reads what it
+            // can before it checks the timer.
+            ticks.send(()).await.unwrap();
+            for _ in 0..2 {
+                let ping = bus.read_message().await?;
+                assert!(is_ping(&ping));
+                bus.send_reply(&ping, Body::default()).await?;
+                ticks.send(()).await.unwrap();
+            }
+            let ping = bus.read_message().await?;
+            assert!(is_ping(&ping));
+            ticks.send(()).await.unwrap();
+
+            crate::Result::Ok(bus)
+        };
+
+        let (result, bus) = smol::block_on(smol::future::zip(conn.read_message(), bus_side));
+        let _bus = bus?;
+
+        assert!(matches!(result, Err(crate::Error::Disconnected)));
+        assert_eq!(disconnects.load(Ordering::SeqCst), 1);
+        assert!(conn.pending_replies.is_empty());
+        assert_eq!(conn.keepalive.unwrap().pending_ping, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn disable_keepalive_forgets_pending_ping() {
+        let mut conn = Connection::new(smol::io::empty(), smol::io::sink());
+        let serial = NonZeroU32::new(1).unwrap();
+        conn.enable_keepalive(Duration::from_secs(1));
+        conn.keepalive.as_mut().unwrap().pending_ping = Some(serial);
+        conn.pending_replies.insert(serial);
+
+        conn.disable_keepalive();
+
+        assert!(conn.pending_replies.is_empty());
+        assert!(conn.keepalive.is_none());
+    }
+}
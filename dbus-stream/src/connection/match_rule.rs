@@ -0,0 +1,412 @@
+//! Match rules, which tell the message bus which signals to route to a connection.
+
+use std::fmt;
+use std::time::Duration;
+
+use super::Connection;
+use crate::message_protocol::Message;
+use crate::message_protocol::MessageTypeParam;
+use crate::type_system::types::*;
+
+/// Rule that matches signals, see [Connection::add_match] and [Connection::wait_for_signal].
+///
+/// Fields that are `None` match any value.
+///
+/// Example: `MatchRule::new().interface("org.freedesktop.DBus").member("NameOwnerChanged")`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MatchRule {
+    pub sender: Option<String>,
+    pub interface: Option<String>,
+    pub member: Option<String>,
+    pub path: Option<String>,
+
+    /// Path that matches itself and the paths below it.
+    pub path_namespace: Option<String>,
+
+    /// Bus name that matches a first argument that is the name itself, or a name below it.
+    /// For example `org.example` matches `org.example.Service`, but not `org.examples`.
+    pub arg0_namespace: Option<String>,
+}
+
+impl MatchRule {
+    /// Rule that matches all signals.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn sender(mut self, sender: &str) -> Self {
+        self.sender = Some(sender.to_string());
+        self
+    }
+
+    pub fn interface(mut self, interface: &str) -> Self {
+        self.interface = Some(interface.to_string());
+        self
+    }
+
+    pub fn member(mut self, member: &str) -> Self {
+        self.member = Some(member.to_string());
+        self
+    }
+
+    pub fn path(mut self, path: &str) -> Self {
+        self.path = Some(path.to_string());
+        self
+    }
+
+    pub fn path_namespace(mut self, path_namespace: &str) -> Self {
+        self.path_namespace = Some(path_namespace.to_string());
+        self
+    }
+
+    pub fn arg0_namespace(mut self, arg0_namespace: &str) -> Self {
+        self.arg0_namespace = Some(arg0_namespace.to_string());
+        self
+    }
+
+    /// Whether the message is a signal that matches the rule.
+    ///
+    /// The sender is compared as given, so a rule with a well-known name does not match signals
+    /// from the unique name that owns it.
+    pub fn matches(&self, message: &Message) -> bool {
+        let signal = match &message.message_type_param {
+            MessageTypeParam::Signal(signal) => signal,
+            _ => return false,
+        };
+        let sender = message.sender.as_ref().map(|sender| sender.string.as_str());
+
+        let matches = |rule: &Option<String>, value: Option<&str>| match rule {
+            Some(rule) => value == Some(rule.as_str()),
+            None => true,
+        };
+
+        let path = &signal.path.dbus_string.string;
+        let path_namespace_matches = match &self.path_namespace {
+            Some(namespace) => in_namespace(path, namespace.trim_end_matches('/'), '/'),
+            None => true,
+        };
+
+        let arg0_namespace_matches = match &self.arg0_namespace {
+            Some(namespace) => match message.body.arguments.first() {
+                Some(Type::String(arg0)) => in_namespace(&arg0.string, namespace, '.'),
+                _ => false,
+            },
+            None => true,
+        };
+
+        matches(&self.sender, sender)
+            && matches(&self.interface, Some(&signal.interface.string))
+            && matches(&self.member, Some(&signal.member.string))
+            && matches(&self.path, Some(path))
+            && path_namespace_matches
+            && arg0_namespace_matches
+    }
+}
+
+/// Whether the name is the namespace itself, or below it when split by the separator.
+fn in_namespace(name: &str, namespace: &str, separator: char) -> bool {
+    match name.strip_prefix(namespace) {
+        Some(rest) => rest.is_empty() || rest.starts_with(separator),
+        None => false,
+    }
+}
+
+/// The rule as given to AddMatch, for example
+/// `type='signal',interface='org.example.Interface',member='Changed'`.
+impl fmt::Display for MatchRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "type='signal'")?;
+
+        let keys = [
+            ("sender", &self.sender),
+            ("interface", &self.interface),
+            ("member", &self.member),
+            ("path", &self.path),
+            ("path_namespace", &self.path_namespace),
+            ("arg0namespace", &self.arg0_namespace),
+        ];
+        for (key, value) in keys.iter() {
+            if let Some(value) = value {
+                // Within quotes, an apostrophe is written by ending the quote, escaping it and
+                // starting a new quote.
+                write!(f, ",{}='{}'", key, value.replace('\'', r"'\''"))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Connection {
+    /// Ask the message bus to route signals that match the rule to this connection.
+    pub async fn add_match(&mut self, rule: &MatchRule) -> crate::Result<()> {
+        let rule = rule.to_string();
+        let arguments = vec![Type::from(DBusString::from(rule.as_str()))];
+        self.call_bus_method("AddMatch", arguments).await?;
+
+        self.match_rules.insert(rule);
+
+        Ok(())
+    }
+
+    /// Remove a match rule that was added with [Connection::add_match].
+    pub async fn remove_match(&mut self, rule: &MatchRule) -> crate::Result<()> {
+        let rule = rule.to_string();
+        let arguments = vec![Type::from(DBusString::from(rule.as_str()))];
+        self.call_bus_method("RemoveMatch", arguments).await?;
+
+        self.match_rules.remove(&rule);
+
+        Ok(())
+    }
+
+    /// Add and remove match rules in one go, for example when changing subscriptions.
+    ///
+    /// All rules are added before any are removed, so there is no moment where signals that
+    /// match both an old and a new rule are not routed. Rules in both lists are left as they are.
+    pub async fn update_matches(
+        &mut self,
+        add: &[MatchRule],
+        remove: &[MatchRule],
+    ) -> crate::Result<()> {
+        for rule in add.iter().filter(|rule| !remove.contains(rule)) {
+            self.add_match(rule).await?;
+        }
+
+        for rule in remove.iter().filter(|rule| !add.contains(rule)) {
+            self.remove_match(rule).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Wait for a signal that matches the rule, adding the rule first if it was not added yet.
+    ///
+    /// Messages that were queued while waiting for something else, such as the reply to a method
+    /// call, are checked first. Other messages received in the meantime are queued, and returned
+    /// by the next reads, for example by [Connection::serve] or by the receiving half after
+    /// [Connection::split]. Replies to
+    /// keepalive pings are consumed as usual. If no matching signal arrives within the timeout,
+    /// this fails with [crate::Error::Timeout].
+    pub async fn wait_for_signal(
+        &mut self,
+        rule: MatchRule,
+        timeout: Duration,
+    ) -> crate::Result<Message> {
+        if !self.match_rules.contains(&rule.to_string()) {
+            self.add_match(&rule).await?;
+        }
+
+        if let Some(index) = self.queued.iter().position(|message| rule.matches(message)) {
+            return Ok(self.queued.remove(index).unwrap());
+        }
+
+        let mut deadline = self.timer.sleep(timeout);
+        loop {
+            if !self.wait_readable(&mut deadline).await? {
+                return Err(crate::Error::Timeout);
+            }
+
+            let message = self.read_message_from_stream().await?;
+            if rule.matches(&message) {
+                return Ok(message);
+            }
+            log::debug!("Queueing message that does not match {}", rule);
+            self.queued.push_back(message);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use super::*;
+    use crate::connection::tests::connection_with_replies;
+    use crate::connection::tests::method_return;
+    use crate::connection::tests::method_return_to;
+    use crate::message_protocol::body::Body;
+    use crate::message_protocol::tests::method_call;
+    use crate::message_protocol::Signal;
+
+    /// Marshal a signal from the given sender.
+    fn signal(serial: u32, sender: &str, interface: &str, member: &str) -> crate::Result<Vec<u8>> {
+        let message = Message {
+            flag_no_reply_expected: true,
+            message_type_param: MessageTypeParam::Signal(Signal {
+                path: DBusObjectPath::from("/org/example/Object"),
+                interface: DBusString::from(interface),
+                member: DBusString::from(member),
+            }),
+            sender: Some(DBusString::from(sender)),
+            ..method_call(serial)
+        };
+
+        message.marshal_be()
+    }
+
+    #[test]
+    fn match_rule_to_string() {
+        assert_eq!(MatchRule::new().to_string(), "type='signal'");
+        assert_eq!(
+            MatchRule::new()
+                .sender("org.example.Service")
+                .interface("org.example.Interface")
+                .member("Changed")
+                .path("/org/example/Object")
+                .to_string(),
+            "type='signal',sender='org.example.Service',interface='org.example.Interface',\
+             member='Changed',path='/org/example/Object'"
+        );
+        assert_eq!(
+            MatchRule::new().path("/it's").to_string(),
+            r"type='signal',path='/it'\''s'"
+        );
+        assert_eq!(
+            MatchRule::new()
+                .member("NameOwnerChanged")
+                .path_namespace("/org/example")
+                .arg0_namespace("org.example")
+                .to_string(),
+            "type='signal',member='NameOwnerChanged',path_namespace='/org/example',\
+             arg0namespace='org.example'"
+        );
+        assert_eq!(
+            MatchRule::new().arg0_namespace("it's").to_string(),
+            r"type='signal',arg0namespace='it'\''s'"
+        );
+    }
+
+    #[test]
+    fn match_namespaces() {
+        assert!(in_namespace("/org/example", "/org/example", '/'));
+        assert!(in_namespace("/org/example/Object", "/org/example", '/'));
+        assert!(!in_namespace("/org/examples", "/org/example", '/'));
+        assert!(in_namespace("/org/example", "", '/'));
+        assert!(in_namespace("org.example.Service", "org.example", '.'));
+        assert!(!in_namespace("org.examples", "org.example", '.'));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn update_matches_adds_before_removing() -> crate::Result<()> {
+        use smol::net::unix::UnixStream;
+
+        let old = MatchRule::new().interface("org.example.Old");
+        let new = MatchRule::new().interface("org.example.New");
+        let kept = MatchRule::new().interface("org.example.Kept");
+
+        // The bus end only records the calls, the replies are ready up front.
+        let (stream, bus) = UnixStream::pair()?;
+        let replies = (1..=2)
+            .map(|serial| method_return_to(NonZeroU32::new(serial).unwrap(), Body::default()))
+            .collect::<crate::Result<Vec<_>>>()?;
+        let mut conn = Connection::new(smol::io::Cursor::new(replies.concat()), stream);
+        conn.match_rules.insert(old.to_string());
+
+        smol::block_on(conn.update_matches(&[new.clone(), kept.clone()], &[old.clone(), kept]))?;
+        assert!(conn.match_rules.contains(&new.to_string()));
+        assert!(!conn.match_rules.contains(&old.to_string()));
+        drop(conn);
+
+        let mut bus = Connection::new(bus, smol::io::sink());
+        let calls = smol::block_on(async {
+            let mut calls = Vec::new();
+            for _ in 0..2 {
+                let call = bus.read_message().await?;
+                let member = call.as_method_call().map(|call| call.member.to_string());
+                let (rule,): (String,) = call.body.extract()?;
+                calls.push((member, rule));
+            }
+            crate::Result::Ok(calls)
+        })?;
+
+        assert_eq!(
+            calls,
+            vec![
+                (Some("AddMatch".to_string()), new.to_string()),
+                (Some("RemoveMatch".to_string()), old.to_string()),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn wait_for_signal() -> crate::Result<()> {
+        let mut conn = connection_with_replies(&[
+            // Reply to AddMatch.
+            method_return(Body::default())?,
+            signal(2, ":1.5", "org.example.Interface", "Other")?,
+            signal(3, ":1.6", "org.example.Interface", "Changed")?,
+            signal(4, ":1.5", "org.example.Interface", "Changed")?,
+        ]);
+        let rule = MatchRule::new()
+            .sender(":1.5")
+            .interface("org.example.Interface")
+            .member("Changed");
+
+        let message = smol::block_on(conn.wait_for_signal(rule.clone(), Duration::from_secs(5)))?;
+
+        assert_eq!(message.serial.get(), 4);
+        assert!(conn.match_rules.contains(&rule.to_string()));
+
+        // The other signals were queued, and are received after splitting too.
+        let (_sender, mut receiver) = conn.split();
+        assert_eq!(smol::block_on(receiver.receive())?.serial.get(), 2);
+        assert_eq!(smol::block_on(receiver.receive())?.serial.get(), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn wait_for_signal_queued() -> crate::Result<()> {
+        let mut conn = connection_with_replies(&[
+            signal(1, ":1.5", "org.example.Interface", "Other")?,
+            signal(2, ":1.5", "org.example.Interface", "Changed")?,
+            signal(3, ":1.5", "org.example.Interface", "Other")?,
+        ]);
+        let changed = MatchRule::new()
+            .interface("org.example.Interface")
+            .member("Changed");
+        let other = MatchRule::new()
+            .interface("org.example.Interface")
+            .member("Other");
+        conn.match_rules.insert(changed.to_string());
+        conn.match_rules.insert(other.to_string());
+
+        let message = smol::block_on(conn.wait_for_signal(changed, Duration::from_secs(5)))?;
+        assert_eq!(message.serial.get(), 2);
+
+        // The signal that arrived first is taken from the queue.
+        let message = smol::block_on(conn.wait_for_signal(other, Duration::from_secs(5)))?;
+        assert_eq!(message.serial.get(), 1);
+        assert!(conn.queued.is_empty());
+
+        assert_eq!(smol::block_on(conn.read_message())?.serial.get(), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn wait_for_signal_queued_during_call() -> crate::Result<()> {
+        let mut conn = connection_with_replies(&[
+            signal(2, ":1.5", "org.example.Interface", "Changed")?,
+            // Reply to GetId.
+            method_return(Body::default())?,
+        ]);
+        let rule = MatchRule::new()
+            .interface("org.example.Interface")
+            .member("Changed");
+        conn.match_rules.insert(rule.to_string());
+
+        smol::block_on(conn.call_bus_method("GetId", vec![]))?;
+
+        // The signal arrived before the reply, and is still there.
+        let message = smol::block_on(conn.wait_for_signal(rule, Duration::from_secs(5)))?;
+        assert_eq!(message.serial.get(), 2);
+        assert!(conn.queued.is_empty());
+
+        Ok(())
+    }
+}
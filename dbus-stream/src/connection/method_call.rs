@@ -0,0 +1,215 @@
+//! Calling methods.
+
+use super::Connection;
+use crate::message_protocol::body::Body;
+use crate::message_protocol::names;
+use crate::message_protocol::names::BusName;
+use crate::message_protocol::Message;
+use crate::message_protocol::MessageTypeParam;
+use crate::message_protocol::MethodCall;
+use crate::type_system::types::*;
+
+/// Builder for a method call, created with [Connection::method_call].
+pub struct MethodCallBuilder<'a> {
+    connection: &'a mut Connection,
+    method_call: MethodCall,
+    destination: Option<BusName>,
+    arguments: Vec<Type>,
+    no_reply: bool,
+    no_auto_start: bool,
+    allow_interactive_authorization: bool,
+}
+
+impl Connection {
+    /// Start building a method call.
+    ///
+    /// The path, interface and member are validated immediately.
+    pub fn method_call(
+        &mut self,
+        path: &str,
+        interface: &str,
+        member: &str,
+    ) -> crate::Result<MethodCallBuilder<'_>> {
+        names::validate_object_path(path)?;
+        names::validate_interface_name(interface)?;
+        names::validate_member_name(member)?;
+
+        Ok(MethodCallBuilder {
+            connection: self,
+            method_call: MethodCall {
+                path: DBusObjectPath::from(path),
+                interface: Some(DBusString::from(interface)),
+                member: DBusString::from(member),
+            },
+            destination: None,
+            arguments: Vec::new(),
+            no_reply: false,
+            no_auto_start: false,
+            allow_interactive_authorization: false,
+        })
+    }
+}
+
+impl<'a> MethodCallBuilder<'a> {
+    /// Set the destination, instead of the default destination of the connection.
+    pub fn destination(mut self, destination: BusName) -> Self {
+        self.destination = Some(destination);
+        self
+    }
+
+    /// Append an argument to the body of the method call.
+    pub fn arg<T: Into<Type>>(mut self, argument: T) -> Self {
+        self.arguments.push(argument.into());
+        self
+    }
+
+    /// Set the NO_REPLY_EXPECTED flag, for calls that are sent with [MethodCallBuilder::send].
+    pub fn no_reply(mut self, no_reply: bool) -> Self {
+        self.no_reply = no_reply;
+        self
+    }
+
+    /// Set the NO_AUTO_START flag, so that the bus does not launch the destination if it is not
+    /// running yet.
+    pub fn no_auto_start(mut self, no_auto_start: bool) -> Self {
+        self.no_auto_start = no_auto_start;
+        self
+    }
+
+    /// Set the ALLOW_INTERACTIVE_AUTHORIZATION flag, so that the callee may prompt the user to
+    /// authorize the call, for example through polkit.
+    pub fn allow_interactive_authorization(
+        mut self,
+        allow_interactive_authorization: bool,
+    ) -> Self {
+        self.allow_interactive_authorization = allow_interactive_authorization;
+        self
+    }
+
+    /// Call the method and wait for the reply, see [Connection::call_method_expect_reply].
+    ///
+    /// Fails with [crate::Error::NoDestination] if neither the call nor the connection has a
+    /// destination, and with [crate::Error::NoReplyExpected] if the NO_REPLY_EXPECTED flag is
+    /// set, since the reply may never arrive.
+    pub async fn call(self) -> crate::Result<Message> {
+        if self.no_reply {
+            return Err(crate::Error::NoReplyExpected);
+        }
+
+        let (connection, message) = self.build()?;
+        connection.call_method_expect_reply(&message).await
+    }
+
+    /// Send the method call without waiting for a reply.
+    pub async fn send(self) -> crate::Result<()> {
+        let (connection, message) = self.build()?;
+        connection.send_message(&message).await
+    }
+
+    fn build(self) -> crate::Result<(&'a mut Connection, Message)> {
+        let MethodCallBuilder {
+            connection,
+            method_call,
+            destination,
+            arguments,
+            no_reply,
+            no_auto_start,
+            allow_interactive_authorization,
+        } = self;
+
+        let destination = destination
+            .or_else(|| connection.default_destination.clone())
+            .ok_or(crate::Error::NoDestination)?;
+
+        let mut message = connection.formulate_message(
+            MessageTypeParam::MethodCall(method_call),
+            Some(DBusString::from(destination.as_str())),
+            Body { arguments },
+        )?;
+        message.flag_no_reply_expected = no_reply;
+        message.flag_no_auto_start = no_auto_start;
+        message.flag_allow_interactive_authorization = allow_interactive_authorization;
+
+        Ok((connection, message))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::tests::connection_with_replies;
+
+    #[test]
+    fn default_destination() -> crate::Result<()> {
+        let mut conn = connection_with_replies(&[]);
+
+        let result = conn
+            .method_call("/org/example/Object", "org.example.Interface", "Get")?
+            .build();
+        assert!(matches!(result, Err(crate::Error::NoDestination)));
+
+        conn.set_default_destination(BusName::new("org.example.Service")?);
+        let (conn, message) = conn
+            .method_call("/org/example/Object", "org.example.Interface", "Get")?
+            .arg(1_u32)
+            .build()?;
+
+        // The destination is sent in the DESTINATION header field.
+        let message = crate::unmarshal(&message.marshal_be()?)?;
+        assert_eq!(
+            message.destination,
+            Some(DBusString::from("org.example.Service"))
+        );
+
+        let (_conn, message) = conn
+            .method_call("/org/example/Object", "org.example.Interface", "Get")?
+            .destination(BusName::new("org.example.Other")?)
+            .build()?;
+        assert_eq!(
+            message.destination,
+            Some(DBusString::from("org.example.Other"))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn header_flags() -> crate::Result<()> {
+        let mut conn = connection_with_replies(&[]);
+
+        let (conn, message) = conn
+            .method_call("/org/example/Object", "org.example.Interface", "Get")?
+            .destination(BusName::new("org.example.Service")?)
+            .build()?;
+        assert_eq!(message.marshal_be()?[2], 0);
+
+        let (_conn, message) = conn
+            .method_call("/org/example/Object", "org.example.Interface", "Get")?
+            .destination(BusName::new("org.example.Service")?)
+            .no_reply(true)
+            .no_auto_start(true)
+            .allow_interactive_authorization(true)
+            .build()?;
+        assert_eq!(message.marshal_be()?[2], 0x7);
+
+        Ok(())
+    }
+
+    #[test]
+    fn call_without_reply_expected() -> crate::Result<()> {
+        let mut conn = connection_with_replies(&[]);
+
+        let call = conn
+            .method_call("/org/example/Object", "org.example.Interface", "Set")?
+            .destination(BusName::new("org.example.Service")?)
+            .no_reply(true)
+            .call();
+        let result = smol::block_on(call);
+
+        assert!(matches!(result, Err(crate::Error::NoReplyExpected)));
+        // No serial was used up.
+        assert_eq!(conn.serial, 0);
+
+        Ok(())
+    }
+}
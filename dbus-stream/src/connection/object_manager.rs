@@ -0,0 +1,157 @@
+//! Methods on the `org.freedesktop.DBus.ObjectManager` interface.
+
+use super::Connection;
+use crate::message_protocol::body::Body;
+use crate::message_protocol::MessageTypeParam;
+use crate::message_protocol::MethodCall;
+use crate::type_system::types::*;
+
+/// Objects of an object manager, as returned by [Connection::get_managed_objects].
+///
+/// Each object path is listed with its interfaces, and each interface with its properties.
+pub type ManagedObjects = Vec<(DBusObjectPath, Vec<(String, Vec<(String, Type)>)>)>;
+
+/// Keys and values of a dictionary, which is an array of dict entries.
+pub(super) fn dict_entries(dict: Type) -> crate::Result<Vec<(Type, Type)>> {
+    match dict {
+        Type::Array(array) => array
+            .items
+            .into_iter()
+            .map(|item| match item {
+                Type::DictEntry(DBusDictEntry { key, value }) => Ok((*key, *value)),
+                _ => Err(crate::Error::UnexpectedReply),
+            })
+            .collect(),
+        _ => Err(crate::Error::UnexpectedReply),
+    }
+}
+
+/// Decode the `a{oa{sa{sv}}}` reply to GetManagedObjects.
+fn managed_objects_from_body(body: Body) -> crate::Result<ManagedObjects> {
+    let mut arguments = body.arguments.into_iter();
+    let objects = match (arguments.next(), arguments.next()) {
+        (Some(objects), None) => objects,
+        _ => return Err(crate::Error::UnexpectedReply),
+    };
+
+    dict_entries(objects)?
+        .into_iter()
+        .map(|(path, interfaces)| {
+            let path = match path {
+                Type::ObjectPath(path) => path,
+                _ => return Err(crate::Error::UnexpectedReply),
+            };
+
+            let interfaces = dict_entries(interfaces)?
+                .into_iter()
+                .map(|(interface, properties)| {
+                    let interface = match interface {
+                        Type::String(interface) => interface.string,
+                        _ => return Err(crate::Error::UnexpectedReply),
+                    };
+
+                    let properties = dict_entries(properties)?
+                        .into_iter()
+                        .map(|(name, value)| match (name, value) {
+                            (Type::String(name), Type::Variant(value)) => {
+                                Ok((name.string, *value.variant))
+                            }
+                            _ => Err(crate::Error::UnexpectedReply),
+                        })
+                        .collect::<crate::Result<Vec<_>>>()?;
+
+                    Ok((interface, properties))
+                })
+                .collect::<crate::Result<Vec<_>>>()?;
+
+            Ok((path, interfaces))
+        })
+        .collect()
+}
+
+impl Connection {
+    /// Get all objects below the given path of an object manager, with the properties of all
+    /// their interfaces.
+    pub async fn get_managed_objects(
+        &mut self,
+        destination: &str,
+        path: &str,
+    ) -> crate::Result<ManagedObjects> {
+        let method_call = MethodCall {
+            path: DBusObjectPath::from(path),
+            interface: Some(DBusString::from("org.freedesktop.DBus.ObjectManager")),
+            member: DBusString::from("GetManagedObjects"),
+        };
+
+        let message = self.formulate_message(
+            MessageTypeParam::MethodCall(method_call),
+            Some(DBusString::from(destination)),
+            Body::default(),
+        )?;
+
+        let reply = self.call_method_expect_reply(&message).await?;
+
+        managed_objects_from_body(reply.body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::tests::connection_with_replies;
+    use crate::connection::tests::method_return;
+    use crate::type_system::signature::Signature;
+
+    /// Array with the signature of the first entry.
+    fn dict(entries: Vec<DBusDictEntry>) -> crate::Result<DBusArray> {
+        let mut array = DBusArray::new(entries[0].signature());
+        for entry in entries {
+            array.push(entry)?;
+        }
+        Ok(array)
+    }
+
+    #[test]
+    fn get_managed_objects() -> crate::Result<()> {
+        let properties = dict(vec![
+            DBusDictEntry::new(
+                DBusString::from("Powered"),
+                DBusVariant::new(DBusBoolean::from(true)),
+            ),
+            DBusDictEntry::new(
+                DBusString::from("Name"),
+                DBusVariant::new(DBusString::from("hci0")),
+            ),
+        ])?;
+        let interfaces = dict(vec![DBusDictEntry::new(
+            DBusString::from("org.bluez.Adapter1"),
+            properties,
+        )])?;
+        let objects = dict(vec![DBusDictEntry::new(
+            DBusObjectPath::from("/org/bluez/hci0"),
+            interfaces,
+        )])?;
+        let body = Body {
+            arguments: vec![Type::from(objects)],
+        };
+        let mut conn = connection_with_replies(&[method_return(body)?]);
+
+        let managed_objects = smol::block_on(conn.get_managed_objects("org.bluez", "/"))?;
+
+        assert_eq!(
+            managed_objects,
+            vec![(
+                DBusObjectPath::from("/org/bluez/hci0"),
+                vec![(
+                    "org.bluez.Adapter1".to_string(),
+                    vec![
+                        ("Powered".to_string(), Type::from(DBusBoolean::from(true))),
+                        ("Name".to_string(), Type::from(DBusString::from("hci0"))),
+                    ]
+                )]
+            )]
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,112 @@
+//! Methods on the `org.freedesktop.DBus.Peer` interface, which is implemented by every connection.
+
+use super::Connection;
+use crate::message_protocol::body::Body;
+use crate::message_protocol::Message;
+use crate::message_protocol::MessageTypeParam;
+use crate::message_protocol::MethodCall;
+use crate::type_system::types::*;
+
+impl Connection {
+    /// Method call on the peer interface of the given connection.
+    pub(super) fn peer_method_call(
+        &mut self,
+        destination: &str,
+        path: &str,
+        member: &str,
+    ) -> crate::Result<Message> {
+        let method_call = MethodCall {
+            path: DBusObjectPath::from(path),
+            interface: Some(DBusString::from("org.freedesktop.DBus.Peer")),
+            member: DBusString::from(member),
+        };
+
+        self.formulate_message(
+            MessageTypeParam::MethodCall(method_call),
+            Some(DBusString::from(destination)),
+            Body::default(),
+        )
+    }
+
+    /// Call a method on the peer interface of the given connection.
+    async fn call_peer_method(
+        &mut self,
+        destination: &str,
+        path: &str,
+        member: &str,
+    ) -> crate::Result<Message> {
+        let message = self.peer_method_call(destination, path, member)?;
+
+        self.call_method_expect_reply(&message).await
+    }
+
+    /// Check that the given connection is alive.
+    pub async fn ping(&mut self, destination: &str) -> crate::Result<()> {
+        let reply = self.call_peer_method(destination, "/", "Ping").await?;
+
+        match (&reply.message_type_param, reply.body.arguments.as_slice()) {
+            (MessageTypeParam::MethodReturn(_), []) => Ok(()),
+            _ => Err(crate::Error::UnexpectedReply),
+        }
+    }
+
+    /// Get the machine UUID of the message bus, as a hex-encoded string.
+    pub async fn get_machine_id(&mut self) -> crate::Result<String> {
+        let reply = self
+            .call_peer_method(
+                "org.freedesktop.DBus",
+                "/org/freedesktop/DBus",
+                "GetMachineId",
+            )
+            .await?;
+
+        match reply.body.arguments.as_slice() {
+            [Type::String(machine_id)] => Ok(machine_id.string.clone()),
+            _ => Err(crate::Error::UnexpectedReply),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::tests::connection_with_replies;
+    use crate::connection::tests::method_return;
+
+    #[test]
+    fn ping() -> crate::Result<()> {
+        let mut conn = connection_with_replies(&[method_return(Body::default())?]);
+
+        smol::block_on(conn.ping("org.freedesktop.DBus"))?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn ping_unexpected_body() -> crate::Result<()> {
+        let body = Body {
+            arguments: vec![Type::from(DBusUint32::from(1_u32))],
+        };
+        let mut conn = connection_with_replies(&[method_return(body)?]);
+
+        let result = smol::block_on(conn.ping("org.freedesktop.DBus"));
+        assert!(matches!(result, Err(crate::Error::UnexpectedReply)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn get_machine_id() -> crate::Result<()> {
+        let body = Body {
+            arguments: vec![Type::from(DBusString::from(
+                "0123456789abcdef0123456789abcdef",
+            ))],
+        };
+        let mut conn = connection_with_replies(&[method_return(body)?]);
+
+        let machine_id = smol::block_on(conn.get_machine_id())?;
+        assert_eq!(machine_id, "0123456789abcdef0123456789abcdef");
+
+        Ok(())
+    }
+}
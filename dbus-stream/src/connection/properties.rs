@@ -0,0 +1,120 @@
+//! Signals of the `org.freedesktop.DBus.Properties` interface.
+
+use super::object_manager::dict_entries;
+use super::Connection;
+use crate::message_protocol::body::Body;
+use crate::message_protocol::Message;
+use crate::message_protocol::MessageTypeParam;
+use crate::type_system::types::*;
+
+/// Decoded `PropertiesChanged` signal, see [Connection::decode_properties_changed].
+#[derive(Debug, Clone, PartialEq)]
+pub struct PropertiesChanged {
+    /// Interface whose properties changed.
+    pub interface: String,
+
+    /// Properties that changed, with their new values.
+    pub changed: Vec<(String, Type)>,
+
+    /// Properties that changed, without their new values.
+    pub invalidated: Vec<String>,
+}
+
+/// Decode the `sa{sv}as` body of PropertiesChanged.
+fn properties_changed_from_body(body: &Body) -> crate::Result<PropertiesChanged> {
+    let (interface, changed, invalidated): (String, DBusArray, DBusArray) = body.extract()?;
+
+    let changed = dict_entries(Type::from(changed))?
+        .into_iter()
+        .map(|(name, value)| match (name, value) {
+            (Type::String(name), Type::Variant(value)) => Ok((name.string, *value.variant)),
+            _ => Err(crate::Error::UnexpectedReply),
+        })
+        .collect::<crate::Result<Vec<_>>>()?;
+
+    let invalidated = invalidated
+        .iter_as()
+        .collect::<crate::Result<Vec<String>>>()?;
+
+    Ok(PropertiesChanged {
+        interface,
+        changed,
+        invalidated,
+    })
+}
+
+impl Connection {
+    /// Decode a `org.freedesktop.DBus.Properties.PropertiesChanged` signal.
+    ///
+    /// Returns `None` if the message is a different message, or if its body is not `sa{sv}as`.
+    pub fn decode_properties_changed(message: &Message) -> Option<PropertiesChanged> {
+        let signal = match &message.message_type_param {
+            MessageTypeParam::Signal(signal) => signal,
+            _ => return None,
+        };
+        if signal.interface.string != "org.freedesktop.DBus.Properties"
+            || signal.member.string != "PropertiesChanged"
+        {
+            return None;
+        }
+
+        match properties_changed_from_body(&message.body) {
+            Ok(properties_changed) => Some(properties_changed),
+            Err(err) => {
+                log::debug!("Malformed PropertiesChanged signal: {}", err);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message_protocol::tests::method_call;
+    use crate::message_protocol::Signal;
+    use crate::type_system::unmarshal::unmarshal_message;
+
+    #[test]
+    fn decode_properties_changed() -> crate::Result<()> {
+        let signal = Message {
+            flag_no_reply_expected: true,
+            message_type_param: MessageTypeParam::Signal(Signal {
+                path: DBusObjectPath::from("/org/example/Player"),
+                interface: DBusString::from("org.freedesktop.DBus.Properties"),
+                member: DBusString::from("PropertiesChanged"),
+            }),
+            sender: Some(DBusString::from(":1.5")),
+            body: Body {
+                arguments: vec![
+                    Type::from("org.example.Player"),
+                    Type::from(PropertyDict::new().set("Volume", 50_u32)),
+                    Type::from(vec!["Metadata".to_string()]),
+                ],
+            },
+            ..method_call(1)
+        };
+        let signal = unmarshal_message(&signal.marshal_be()?)?;
+
+        assert_eq!(
+            Connection::decode_properties_changed(&signal),
+            Some(PropertiesChanged {
+                interface: "org.example.Player".to_string(),
+                changed: vec![("Volume".to_string(), Type::from(50_u32))],
+                invalidated: vec!["Metadata".to_string()],
+            })
+        );
+
+        let other = Message {
+            message_type_param: MessageTypeParam::Signal(Signal {
+                path: DBusObjectPath::from("/org/example/Player"),
+                interface: DBusString::from("org.example.Player"),
+                member: DBusString::from("PropertiesChanged"),
+            }),
+            ..signal
+        };
+        assert_eq!(Connection::decode_properties_changed(&other), None);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,215 @@
+//! Serving method calls from other connections.
+
+use super::Connection;
+use crate::message_protocol::body::Body;
+use crate::message_protocol::error_name::ErrorName;
+use crate::message_protocol::Message;
+use crate::message_protocol::MessageTypeParam;
+use crate::message_protocol::MethodReturn;
+use crate::type_system::types::*;
+
+impl Connection {
+    /// Handle incoming method calls until the connection fails, for example with
+    /// [crate::Error::Disconnected].
+    ///
+    /// The handler is called for each method call, and can route it by its interface and member.
+    /// A returned body is sent as the reply. If the handler returns `None`, an
+    /// `org.freedesktop.DBus.Error.UnknownMethod` error is sent instead. No reply is sent to
+    /// calls that do not expect one.
+    ///
+    /// Other messages are ignored.
+    pub async fn serve<F>(&mut self, mut handler: F) -> crate::Result<()>
+    where
+        F: FnMut(&Message) -> Option<Body>,
+    {
+        loop {
+            let message = self.read_message().await?;
+
+            let method_call = match message.as_method_call() {
+                Some(method_call) => method_call,
+                None => continue,
+            };
+
+            let reply = handler(&message);
+
+            if message.flag_no_reply_expected {
+                continue;
+            }
+
+            match reply {
+                Some(body) => self.send_reply(&message, body).await?,
+                None => {
+                    let error_message = format!(
+                        "No such method {} on interface {}",
+                        method_call.member,
+                        method_call.interface.unwrap_or("(none)"),
+                    );
+                    self.send_error(&message, ErrorName::UnknownMethod, &error_message)
+                        .await?
+                }
+            }
+        }
+    }
+
+    /// Send a method return with the given body, in reply to a method call.
+    pub async fn send_reply(&mut self, method_call: &Message, body: Body) -> crate::Result<()> {
+        let reply = self.formulate_message(
+            MessageTypeParam::MethodReturn(MethodReturn {
+                reply_serial: method_call.serial,
+            }),
+            method_call.sender.clone(),
+            body,
+        )?;
+
+        self.send_message(&reply).await
+    }
+
+    /// Send one of the standard errors and a human-readable message, in reply to a method call.
+    pub async fn send_error(
+        &mut self,
+        method_call: &Message,
+        error_name: ErrorName,
+        message: &str,
+    ) -> crate::Result<()> {
+        let reply = self.formulate_message(
+            MessageTypeParam::Error(crate::message_protocol::Error {
+                error_name: DBusString::from(error_name.as_str()),
+                reply_serial: method_call.serial,
+            }),
+            method_call.sender.clone(),
+            Body {
+                arguments: vec![Type::from(DBusString::from(message))],
+            },
+        )?;
+
+        self.send_message(&reply).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use smol::prelude::*;
+
+    use super::*;
+    use crate::message_protocol::MethodCall;
+
+    /// Method call from a peer, without a message bus to fill in the sender.
+    fn method_call(serial: u32, member: &str) -> Message {
+        Message {
+            message_type_param: MessageTypeParam::MethodCall(MethodCall {
+                path: DBusObjectPath::from("/org/example/Object"),
+                interface: Some(DBusString::from("org.example.Interface")),
+                member: DBusString::from(member),
+            }),
+            ..crate::message_protocol::tests::method_call(serial)
+        }
+    }
+
+    fn reply(serial: u32, message_type_param: MessageTypeParam, body: Body) -> Message {
+        Message {
+            message_type_param,
+            body,
+            ..crate::message_protocol::tests::method_call(serial)
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn serve() -> crate::Result<()> {
+        use smol::net::unix::UnixStream;
+
+        let (stream, mut caller) = UnixStream::pair()?;
+        let mut conn = Connection::new(stream.clone(), stream);
+
+        let answer = || Body {
+            arguments: vec![Type::from(DBusUint32::from(42_u32))],
+        };
+
+        smol::block_on(async {
+            caller
+                .write_all(&method_call(7, "Answer").marshal_be()?)
+                .await?;
+            caller
+                .write_all(&method_call(8, "Unknown").marshal_be()?)
+                .await?;
+            caller.shutdown(std::net::Shutdown::Write)?;
+
+            let result = conn
+                .serve(|message| match &message.message_type_param {
+                    MessageTypeParam::MethodCall(method_call)
+                        if method_call.member.string == "Answer" =>
+                    {
+                        Some(answer())
+                    }
+                    _ => None,
+                })
+                .await;
+            assert!(matches!(result, Err(crate::Error::Disconnected)));
+            drop(conn);
+
+            let mut replies = Vec::new();
+            caller.read_to_end(&mut replies).await?;
+
+            let expected = [
+                reply(
+                    1,
+                    MessageTypeParam::MethodReturn(MethodReturn {
+                        reply_serial: NonZeroU32::new(7).unwrap(),
+                    }),
+                    answer(),
+                ),
+                reply(
+                    2,
+                    MessageTypeParam::Error(crate::message_protocol::Error {
+                        error_name: DBusString::from(ErrorName::UnknownMethod.as_str()),
+                        reply_serial: NonZeroU32::new(8).unwrap(),
+                    }),
+                    Body {
+                        arguments: vec![Type::from(DBusString::from(
+                            "No such method Unknown on interface org.example.Interface",
+                        ))],
+                    },
+                ),
+            ];
+            let expected = [expected[0].marshal_be()?, expected[1].marshal_be()?].concat();
+            assert_eq!(replies, expected);
+
+            crate::Result::Ok(())
+        })
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn send_error() -> crate::Result<()> {
+        use smol::net::unix::UnixStream;
+
+        let (stream, mut caller) = UnixStream::pair()?;
+        let mut conn = Connection::new(stream.clone(), stream);
+
+        let error = smol::block_on(async {
+            conn.send_error(
+                &method_call(3, "Method"),
+                ErrorName::InvalidArgs,
+                "Expected a string",
+            )
+            .await?;
+            drop(conn);
+
+            let mut error = Vec::new();
+            caller.read_to_end(&mut error).await?;
+            crate::unmarshal(&error)
+        })?;
+
+        assert_eq!(
+            error.message_type_param,
+            MessageTypeParam::Error(crate::message_protocol::Error {
+                error_name: DBusString::from("org.freedesktop.DBus.Error.InvalidArgs"),
+                reply_serial: NonZeroU32::new(3).unwrap(),
+            })
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,119 @@
+//! Emitting signals.
+
+use super::Connection;
+use crate::message_protocol::body::Body;
+use crate::message_protocol::names;
+use crate::message_protocol::Message;
+use crate::message_protocol::MessageTypeParam;
+use crate::message_protocol::Signal;
+use crate::type_system::types::*;
+
+/// Builder for a signal, created with [Connection::signal].
+pub struct SignalBuilder<'a> {
+    connection: &'a mut Connection,
+    signal: Signal,
+    arguments: Vec<Type>,
+}
+
+impl Connection {
+    /// Start building a signal to broadcast.
+    ///
+    /// The path, interface and member are validated immediately.
+    pub fn signal(
+        &mut self,
+        path: &str,
+        interface: &str,
+        member: &str,
+    ) -> crate::Result<SignalBuilder<'_>> {
+        names::validate_object_path(path)?;
+        names::validate_interface_name(interface)?;
+        names::validate_member_name(member)?;
+
+        Ok(SignalBuilder {
+            connection: self,
+            signal: Signal {
+                path: DBusObjectPath::from(path),
+                interface: DBusString::from(interface),
+                member: DBusString::from(member),
+            },
+            arguments: Vec::new(),
+        })
+    }
+}
+
+impl<'a> SignalBuilder<'a> {
+    /// Append an argument to the body of the signal.
+    pub fn arg<T: Into<Type>>(mut self, argument: T) -> Self {
+        self.arguments.push(argument.into());
+        self
+    }
+
+    /// Send the signal.
+    pub async fn emit(self) -> crate::Result<()> {
+        let (connection, message) = self.build()?;
+        connection.send_message(&message).await
+    }
+
+    fn build(self) -> crate::Result<(&'a mut Connection, Message)> {
+        let mut message = self.connection.formulate_message(
+            MessageTypeParam::Signal(self.signal),
+            None,
+            Body {
+                arguments: self.arguments,
+            },
+        )?;
+        // Signals never get a reply.
+        message.flag_no_reply_expected = true;
+
+        Ok((self.connection, message))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::tests::connection_with_replies;
+    use crate::type_system::signature::SingleCompleteTypeSignature;
+
+    #[test]
+    fn build_signal() -> crate::Result<()> {
+        let mut conn = connection_with_replies(&[]);
+
+        let (_conn, message) = conn
+            .signal("/org/example/Object", "org.example.Interface", "Changed")?
+            .arg(DBusUint32::from(1_u32))
+            .arg(DBusString::from("value"))
+            .build()?;
+
+        let message = crate::unmarshal(&message.marshal_be()?)?;
+        assert!(message.flag_no_reply_expected);
+        assert_eq!(
+            message.message_type_param,
+            MessageTypeParam::Signal(Signal {
+                path: DBusObjectPath::from("/org/example/Object"),
+                interface: DBusString::from("org.example.Interface"),
+                member: DBusString::from("Changed"),
+            })
+        );
+        assert_eq!(
+            message.body.signature(),
+            DBusSignature {
+                vec: vec![
+                    SingleCompleteTypeSignature::DBusUint32,
+                    SingleCompleteTypeSignature::DBusString,
+                ],
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn signal_validates_names() {
+        let mut conn = connection_with_replies(&[]);
+
+        assert!(conn.signal("org/example", "org.example.I", "M").is_err());
+        assert!(conn.signal("/org/example", "example", "M").is_err());
+        assert!(conn.signal("/org/example", "org.example.I", "a.M").is_err());
+    }
+}
@@ -0,0 +1,413 @@
+//! Splitting a connection into halves that send and receive concurrently.
+
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::num::NonZeroU32;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::Weak;
+
+use smol::channel;
+use smol::lock;
+use smol::stream::Stream;
+
+use super::next_serial;
+use super::read_message;
+use super::unix_fds::FdQueue;
+use super::write_message;
+use super::Connection;
+use super::Reader;
+use super::Writer;
+use crate::message_protocol::body::Body;
+use crate::message_protocol::Message;
+use crate::message_protocol::MessageTypeParam;
+use crate::message_protocol::MethodCall;
+use crate::message_protocol::Serial;
+use crate::type_system::marshal::Encoder;
+use crate::type_system::types::*;
+
+/// Serials of method calls that are waiting for a reply, and where to deliver the reply.
+///
+/// Calls that were given up after they were sent have no channel, so that their late reply is
+/// recognized and discarded.
+type PendingReplies = Mutex<HashMap<Serial, Option<channel::Sender<Message>>>>;
+
+/// Sending half of a [Connection], created with [Connection::split].
+///
+/// It can be cloned to send from several tasks.
+#[derive(Clone)]
+pub struct Sender {
+    state: Arc<lock::Mutex<SenderState>>,
+
+    /// Owned by the [Receiver], and gone when it is dropped.
+    pending_replies: Weak<PendingReplies>,
+}
+
+struct SenderState {
+    serial: u32,
+    writer: Writer,
+    encoder: Encoder,
+}
+
+/// Receiving half of a [Connection], created with [Connection::split].
+pub struct Receiver {
+    reader: Reader,
+    max_message_size: usize,
+    received_fds: Option<FdQueue>,
+    pending_replies: Arc<PendingReplies>,
+
+    /// Messages that the [Connection] had queued before it was split.
+    queued: VecDeque<Message>,
+}
+
+/// Item of [Receiver::signals].
+#[derive(Debug)]
+pub enum Incoming {
+    Signal(Message),
+
+    /// The connection was closed, after which no more messages arrive.
+    ///
+    /// This takes the place of the `Disconnected` signal that libdbus synthesizes on the
+    /// `org.freedesktop.DBus.Local` interface.
+    Disconnected,
+}
+
+impl Connection {
+    /// Split into a sending and a receiving half, that can be used from different tasks.
+    pub fn split(self) -> (Sender, Receiver) {
+        let pending_replies = Arc::new(PendingReplies::default());
+
+        let sender = Sender {
+            state: Arc::new(lock::Mutex::new(SenderState {
+                serial: self.serial,
+                writer: self.writer,
+                encoder: self.encoder,
+            })),
+            pending_replies: Arc::downgrade(&pending_replies),
+        };
+        let receiver = Receiver {
+            reader: self.reader,
+            max_message_size: self.max_message_size,
+            received_fds: self.received_fds,
+            pending_replies,
+            queued: self.queued,
+        };
+
+        (sender, receiver)
+    }
+}
+
+impl Sender {
+    /// DBus method call, with reply.
+    ///
+    /// The reply is delivered by [Receiver::receive], so the [Receiver] must be receiving while
+    /// waiting for the reply. If the [Receiver] is dropped, this fails with
+    /// [crate::Error::Disconnected].
+    ///
+    /// An error reply is returned as [crate::Error::ErrorReply].
+    pub async fn call_method(
+        &self,
+        method_call: MethodCall,
+        destination: Option<DBusString>,
+        body: Body,
+    ) -> crate::Result<Message> {
+        let pending_replies = self
+            .pending_replies
+            .upgrade()
+            .ok_or(crate::Error::Disconnected)?;
+        let (reply_sender, reply_receiver) = channel::bounded(1);
+
+        let mut state = self.state.lock().await;
+
+        let serial = {
+            let mut pending_replies = pending_replies.lock().unwrap();
+            let serial = next_serial(&mut state.serial, pending_replies.len(), |serial| {
+                pending_replies.contains_key(&Serial::from(*serial))
+            })?;
+            pending_replies.insert(Serial::from(serial), Some(reply_sender));
+            serial
+        };
+        let mut pending = PendingReply {
+            pending_replies,
+            serial,
+            sent: false,
+        };
+
+        let message = Message {
+            flag_no_reply_expected: false,
+            flag_no_auto_start: false,
+            flag_allow_interactive_authorization: false,
+            serial,
+            message_type_param: MessageTypeParam::MethodCall(method_call),
+            destination,
+            sender: None,
+            unix_fds: None,
+            received_fds: Vec::new(),
+            body,
+        };
+        let SenderState {
+            writer, encoder, ..
+        } = &mut *state;
+        write_message(writer, encoder, &message).await?;
+        pending.sent = true;
+        drop(state);
+
+        // The channel is closed if the receiver is dropped.
+        let reply = reply_receiver
+            .recv()
+            .await
+            .map_err(|_| crate::Error::Disconnected)?;
+
+        drop(pending);
+
+        match &reply.message_type_param {
+            MessageTypeParam::Error(error) => {
+                Err(crate::Error::ErrorReply(error.error_name.string.clone()))
+            }
+            _ => Ok(reply),
+        }
+    }
+}
+
+impl Receiver {
+    /// Receive the next message that is not a reply to a method call made with the [Sender].
+    ///
+    /// Replies are delivered to the waiting [Sender::call_method] instead. Late replies to calls
+    /// whose future was dropped after the call was sent are discarded.
+    pub async fn receive(&mut self) -> crate::Result<Message> {
+        // Queued messages were read before the split, so none of them is a reply to a call made
+        // with the Sender.
+        if let Some(message) = self.queued.pop_front() {
+            return Ok(message);
+        }
+
+        loop {
+            let message = read_message(
+                &mut self.reader,
+                self.max_message_size,
+                self.received_fds.as_ref(),
+            )
+            .await?;
+
+            let reply_sender = message
+                .reply_serial()
+                .and_then(|serial| self.pending_replies.lock().unwrap().remove(&serial));
+
+            match reply_sender {
+                // The call may have been given up while the reply was received, in which case
+                // the reply is discarded.
+                Some(Some(reply_sender)) => {
+                    let _ = reply_sender.try_send(message);
+                }
+                Some(None) => log::debug!("Discarding late reply {}", message.summary()),
+                None => return Ok(message),
+            }
+        }
+    }
+
+    /// Stream of the received signals, that ends with [Incoming::Disconnected] when the
+    /// connection is closed.
+    ///
+    /// Messages that are not signals are discarded. Other errors end the stream too.
+    pub fn signals(self) -> impl Stream<Item = crate::Result<Incoming>> {
+        smol::stream::unfold(Some(self), |receiver| async move {
+            let mut receiver = receiver?;
+            loop {
+                match receiver.receive().await {
+                    Ok(message) => match message.message_type_param {
+                        MessageTypeParam::Signal(_) => {
+                            return Some((Ok(Incoming::Signal(message)), Some(receiver)));
+                        }
+                        _ => log::debug!("Discarding {}", message.summary()),
+                    },
+                    Err(crate::Error::Disconnected) => {
+                        return Some((Ok(Incoming::Disconnected), None));
+                    }
+                    Err(err) => return Some((Err(err), None)),
+                }
+            }
+        })
+    }
+}
+
+impl Drop for Receiver {
+    /// Close the channels of calls that are still waiting, since their replies will never be
+    /// delivered.
+    fn drop(&mut self) {
+        self.pending_replies.lock().unwrap().clear();
+    }
+}
+
+/// Removes the serial of a method call from the pending replies when dropped, also when the
+/// future waiting for the reply is dropped before it completes.
+///
+/// If the call was sent, the serial is kept without a channel until the reply arrives, so that
+/// the reply is discarded.
+struct PendingReply {
+    pending_replies: Arc<PendingReplies>,
+    serial: NonZeroU32,
+    sent: bool,
+}
+
+impl Drop for PendingReply {
+    fn drop(&mut self) {
+        let mut pending_replies = self.pending_replies.lock().unwrap();
+        let serial = Serial::from(self.serial);
+        if !self.sent {
+            pending_replies.remove(&serial);
+        } else if let Some(reply_sender) = pending_replies.get_mut(&serial) {
+            *reply_sender = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn call_method_on_split_connection() -> crate::Result<()> {
+        use smol::net::unix::UnixStream;
+
+        let (stream, bus) = UnixStream::pair()?;
+        let (sender, mut receiver) = Connection::new(stream.clone(), stream).split();
+        let mut bus = Connection::new(bus.clone(), bus);
+
+        let call = sender.call_method(
+            MethodCall {
+                path: DBusObjectPath::from("/org/freedesktop/DBus"),
+                interface: Some(DBusString::from("org.freedesktop.DBus")),
+                member: DBusString::from("GetId"),
+            },
+            Some(DBusString::from("org.freedesktop.DBus")),
+            Body::default(),
+        );
+        let bus_side = async {
+            let call = bus.read_message().await?;
+            let id = Body {
+                arguments: vec![Type::from(DBusString::from("0123456789abcdef"))],
+            };
+            bus.send_reply(&call, id).await?;
+
+            // A signal after the reply, which is not for the sender.
+            bus.signal(
+                "/org/freedesktop/DBus",
+                "org.freedesktop.DBus",
+                "NameAcquired",
+            )?
+            .emit()
+            .await
+        };
+
+        let ((reply, signal), bus_result) = smol::block_on(smol::future::zip(
+            smol::future::zip(call, receiver.receive()),
+            bus_side,
+        ));
+        bus_result?;
+
+        assert_eq!(
+            reply?.body.extract::<(String,)>()?,
+            ("0123456789abcdef".to_string(),)
+        );
+        assert!(matches!(
+            signal?.message_type_param,
+            MessageTypeParam::Signal(_)
+        ));
+        assert!(receiver.pending_replies.lock().unwrap().is_empty());
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn late_reply_is_discarded() -> crate::Result<()> {
+        use smol::net::unix::UnixStream;
+
+        let (stream, bus) = UnixStream::pair()?;
+        let (sender, mut receiver) = Connection::new(stream.clone(), stream).split();
+        let mut bus = Connection::new(bus.clone(), bus);
+
+        // Send the call, then give up waiting for the reply.
+        let mut call = Box::pin(sender.call_method(
+            MethodCall {
+                path: DBusObjectPath::from("/org/freedesktop/DBus"),
+                interface: Some(DBusString::from("org.freedesktop.DBus")),
+                member: DBusString::from("GetId"),
+            },
+            Some(DBusString::from("org.freedesktop.DBus")),
+            Body::default(),
+        ));
+        assert!(smol::block_on(smol::future::poll_once(&mut call)).is_none());
+        drop(call);
+
+        smol::block_on(async {
+            let call = bus.read_message().await?;
+            bus.send_reply(&call, Body::default()).await?;
+            bus.signal(
+                "/org/freedesktop/DBus",
+                "org.freedesktop.DBus",
+                "NameAcquired",
+            )?
+            .emit()
+            .await
+        })?;
+
+        let message = smol::block_on(receiver.receive())?;
+
+        assert!(matches!(
+            message.message_type_param,
+            MessageTypeParam::Signal(_)
+        ));
+        assert!(receiver.pending_replies.lock().unwrap().is_empty());
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn signals_end_with_disconnected() -> crate::Result<()> {
+        use smol::net::unix::UnixStream;
+        use smol::stream::StreamExt;
+
+        let (stream, bus) = UnixStream::pair()?;
+        let (_sender, receiver) = Connection::new(stream.clone(), stream).split();
+        let mut bus = Connection::new(bus.clone(), bus);
+
+        smol::block_on(
+            bus.signal(
+                "/org/freedesktop/DBus",
+                "org.freedesktop.DBus",
+                "NameAcquired",
+            )?
+            .emit(),
+        )?;
+        drop(bus);
+
+        let incoming: Vec<Incoming> = smol::block_on(receiver.signals().try_collect())?;
+
+        assert!(matches!(
+            incoming.as_slice(),
+            [Incoming::Signal(_), Incoming::Disconnected]
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn call_method_without_receiver() {
+        let (sender, receiver) = crate::connection::tests::connection_with_replies(&[]).split();
+        drop(receiver);
+
+        let result = smol::block_on(sender.call_method(
+            MethodCall {
+                path: DBusObjectPath::from("/"),
+                interface: None,
+                member: DBusString::from("Ping"),
+            },
+            None,
+            Body::default(),
+        ));
+        assert!(matches!(result, Err(crate::Error::Disconnected)));
+    }
+}
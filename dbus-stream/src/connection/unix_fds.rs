@@ -0,0 +1,237 @@
+//! Receiving Unix file descriptors, which arrive as ancillary data along with the bytes of a
+//! message.
+
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use crate::message_protocol::UnixFd;
+
+/// File descriptors received on a connection that are not yet claimed by a message.
+///
+/// The reader may read ahead of the message that is being decoded, so file descriptors of the
+/// following messages wait here in the order they were received.
+pub(super) type FdQueue = Arc<Mutex<VecDeque<UnixFd>>>;
+
+/// Take the given number of file descriptors that accompany a message, as declared by its
+/// UNIX_FDS header field.
+pub(super) fn take_fds(queue: &FdQueue, count: u32) -> crate::Result<Vec<UnixFd>> {
+    let count = usize::try_from(count)?;
+    let mut queue = queue.lock().unwrap();
+
+    if queue.len() < count {
+        return Err(crate::Error::MissingUnixFds {
+            expected: count,
+            found: queue.len(),
+        });
+    }
+
+    Ok(queue.drain(..count).collect())
+}
+
+#[cfg(unix)]
+pub(super) use self::reader::FdReader;
+
+#[cfg(unix)]
+mod reader {
+    use std::io;
+    use std::os::unix::io::AsRawFd;
+    use std::os::unix::io::FromRawFd;
+    use std::os::unix::io::OwnedFd;
+    use std::os::unix::io::RawFd;
+    use std::os::unix::net::UnixStream;
+    use std::pin::Pin;
+    use std::sync::Arc;
+    use std::task::Context;
+    use std::task::Poll;
+
+    use nix::sys::socket::recvmsg;
+    use nix::sys::socket::ControlMessageOwned;
+    use nix::sys::socket::MsgFlags;
+    use nix::sys::uio::IoVec;
+    use smol::io::AsyncRead;
+    use smol::Async;
+
+    use super::FdQueue;
+
+    /// Most file descriptors that can be received at once, which is `SCM_MAX_FD` on Linux.
+    const MAX_FDS_PER_READ: usize = 253;
+
+    #[cfg(target_os = "linux")]
+    const RECV_FLAGS: MsgFlags = MsgFlags::MSG_CMSG_CLOEXEC;
+
+    #[cfg(not(target_os = "linux"))]
+    const RECV_FLAGS: MsgFlags = MsgFlags::empty();
+
+    /// Reader of a unix socket that keeps the file descriptors received along with the bytes.
+    pub(in crate::connection) struct FdReader {
+        stream: Arc<Async<UnixStream>>,
+        received_fds: FdQueue,
+    }
+
+    impl FdReader {
+        pub(in crate::connection) fn new(
+            stream: Arc<Async<UnixStream>>,
+            received_fds: FdQueue,
+        ) -> Self {
+            Self {
+                stream,
+                received_fds,
+            }
+        }
+
+        /// Receive bytes into the buffer, and queue the file descriptors that came with them.
+        fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+            let fd = self.stream.get_ref().as_raw_fd();
+            let iov = [IoVec::from_mut_slice(buf)];
+            let mut cmsg_buffer = nix::cmsg_space!([RawFd; MAX_FDS_PER_READ]);
+
+            let msg =
+                recvmsg(fd, &iov, Some(&mut cmsg_buffer), RECV_FLAGS).map_err(|err| {
+                    match err.as_errno() {
+                        Some(errno) => io::Error::from(errno),
+                        None => io::Error::other(err),
+                    }
+                })?;
+
+            let mut received_fds = self.received_fds.lock().unwrap();
+            for cmsg in msg.cmsgs() {
+                if let ControlMessageOwned::ScmRights(fds) = cmsg {
+                    // The received file descriptors are new, so nothing else owns them.
+                    let fds = fds
+                        .into_iter()
+                        .map(|fd| unsafe { OwnedFd::from_raw_fd(fd) });
+                    received_fds.extend(fds);
+                }
+            }
+
+            // File descriptors that did not fit were discarded, so the queue no longer matches
+            // the messages, and the connection can not be used any more.
+            if msg.flags.contains(MsgFlags::MSG_CTRUNC) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Received file descriptors were truncated",
+                ));
+            }
+
+            Ok(msg.bytes)
+        }
+    }
+
+    impl AsyncRead for FdReader {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            loop {
+                match self.recv(buf) {
+                    Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                        match self.stream.poll_readable(cx) {
+                            Poll::Ready(result) => result?,
+                            Poll::Pending => return Poll::Pending,
+                        }
+                    }
+                    result => return Poll::Ready(result),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use super::*;
+    use crate::connection::Connection;
+    use crate::message_protocol::HeaderField;
+    use crate::type_system::marshal::Encoder;
+    use crate::type_system::marshal::Marshal;
+    use crate::type_system::types::*;
+    use crate::type_system::Endianness;
+
+    #[test]
+    fn take_fds_missing() {
+        let queue = FdQueue::default();
+
+        assert!(take_fds(&queue, 0).unwrap().is_empty());
+        assert!(matches!(
+            take_fds(&queue, 1),
+            Err(crate::Error::MissingUnixFds {
+                expected: 1,
+                found: 0
+            })
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn receive_fd() -> crate::Result<()> {
+        use std::io::Read;
+        use std::io::Write;
+        use std::os::unix::io::AsRawFd;
+        use std::os::unix::net::UnixStream;
+
+        use nix::sys::socket::sendmsg;
+        use nix::sys::socket::ControlMessage;
+        use nix::sys::socket::MsgFlags;
+        use nix::sys::uio::IoVec;
+
+        // Method return with the body `h`, which refers to the first file descriptor sent with it.
+        let mut encoder = Encoder::default();
+        encoder.extend_from_array([b'B', 2, 0, 1, 0, 0, 0, 4, 0, 0, 0, 2]);
+        encoder.marshal_header_fields(
+            &[
+                HeaderField::ReplySerial(DBusUint32::from(1_u32)),
+                HeaderField::Signature(DBusSignature::new_from_signature("h")?),
+                HeaderField::UnixFds(DBusUint32::from(1_u32)),
+            ],
+            Endianness::BigEndian,
+        )?;
+        encoder.align(8);
+        encoder.marshal(
+            &Type::from(DBusUnixFileDescriptor::from(0_u32)),
+            Endianness::BigEndian,
+        )?;
+        let marshalled = encoder.finish();
+
+        // Send one end of another socket pair along with the message.
+        let (stream, bus) = UnixStream::pair()?;
+        let (mut other_end, passed) = UnixStream::pair()?;
+        sendmsg(
+            bus.as_raw_fd(),
+            &[IoVec::from_slice(&marshalled)],
+            &[ControlMessage::ScmRights(&[passed.as_raw_fd()])],
+            MsgFlags::empty(),
+            None,
+        )
+        .expect("sendmsg");
+        drop(passed);
+
+        let mut conn = Connection::new_unix(smol::net::unix::UnixStream::try_from(stream)?);
+        // The method return is the reply to a pending call.
+        conn.pending_replies.insert(NonZeroU32::new(1).unwrap());
+        let message = smol::block_on(conn.read_message())?;
+
+        assert_eq!(message.received_fds.len(), 1);
+        let fd = match message.body.arguments.as_slice() {
+            [Type::UnixFileDescriptor(fd)] => fd,
+            arguments => panic!("Unexpected body {:?}", arguments),
+        };
+        let received = message.received_fd(fd).expect("received fd");
+
+        // The received file descriptor is the passed end of the other socket pair.
+        let mut received = std::fs::File::from(received.try_clone()?);
+        received.write_all(b"hello")?;
+        drop(received);
+        drop(message);
+
+        let mut data = Vec::new();
+        other_end.read_to_end(&mut data)?;
+        assert_eq!(data, b"hello");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,484 @@
+//! Introspection data, the XML returned by `org.freedesktop.DBus.Introspectable.Introspect`.
+//!
+//! Spec: https://dbus.freedesktop.org/doc/dbus-specification.html#introspection-format
+
+use nom::branch::alt;
+use nom::bytes::complete::is_not;
+use nom::bytes::complete::tag;
+use nom::bytes::complete::take_until;
+use nom::bytes::complete::take_while1;
+use nom::character::complete::char;
+use nom::character::complete::multispace0;
+use nom::character::complete::multispace1;
+use nom::combinator::all_consuming;
+use nom::combinator::map;
+use nom::combinator::value;
+use nom::error::ErrorKind;
+use nom::multi::many0;
+use nom::sequence::delimited;
+use nom::sequence::preceded;
+use nom::sequence::tuple;
+use nom::Finish;
+use nom::IResult;
+
+use crate::type_system::types::DBusSignature;
+use crate::type_system::unmarshal::MAX_NESTING_DEPTH;
+
+/// Object in the tree of objects, with its interfaces and child nodes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node {
+    /// Path relative to the parent node. The introspected node itself usually has no name.
+    pub name: Option<String>,
+    pub interfaces: Vec<Interface>,
+    pub nodes: Vec<Node>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Interface {
+    pub name: String,
+    pub methods: Vec<Method>,
+    pub signals: Vec<Signal>,
+    pub properties: Vec<Property>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Method {
+    pub name: String,
+    pub args: Vec<Arg>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signal {
+    pub name: String,
+
+    /// Arguments of a signal are always [Direction::Out].
+    pub args: Vec<Arg>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Property {
+    pub name: String,
+    pub signature: DBusSignature,
+    pub access: Access,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Arg {
+    pub name: Option<String>,
+    pub signature: DBusSignature,
+    pub direction: Direction,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    In,
+    Out,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl Method {
+    /// Signature of the body of a call to the method.
+    pub fn in_signature(&self) -> DBusSignature {
+        signature_of_args(&self.args, Direction::In)
+    }
+
+    /// Signature of the body of a reply from the method.
+    pub fn out_signature(&self) -> DBusSignature {
+        signature_of_args(&self.args, Direction::Out)
+    }
+}
+
+impl Signal {
+    /// Signature of the body of the signal.
+    pub fn signature(&self) -> DBusSignature {
+        signature_of_args(&self.args, Direction::Out)
+    }
+}
+
+fn signature_of_args(args: &[Arg], direction: Direction) -> DBusSignature {
+    let vec = args
+        .iter()
+        .filter(|arg| arg.direction == direction)
+        .flat_map(|arg| arg.signature.vec.iter().cloned())
+        .collect();
+
+    DBusSignature { vec }
+}
+
+impl Node {
+    pub fn interface(&self, name: &str) -> Option<&Interface> {
+        self.interfaces
+            .iter()
+            .find(|interface| interface.name == name)
+    }
+}
+
+impl Interface {
+    pub fn method(&self, name: &str) -> Option<&Method> {
+        self.methods.iter().find(|method| method.name == name)
+    }
+
+    pub fn signal(&self, name: &str) -> Option<&Signal> {
+        self.signals.iter().find(|signal| signal.name == name)
+    }
+
+    pub fn property(&self, name: &str) -> Option<&Property> {
+        self.properties
+            .iter()
+            .find(|property| property.name == name)
+    }
+}
+
+/// Parse introspection data.
+///
+/// Annotations and unknown elements are skipped.
+pub fn parse(xml: &str) -> crate::Result<Node> {
+    let (_i, root) = all_consuming(document)(xml).finish().map_err(|err| {
+        let offset = xml.len() - err.input.len();
+        let problem = match err.code {
+            ErrorKind::TooLarge => "elements nested too deeply",
+            _ => "malformed XML",
+        };
+        crate::Error::InvalidIntrospection(format!("{} at byte {}", problem, offset))
+    })?;
+
+    if root.name != "node" {
+        return Err(crate::Error::InvalidIntrospection(format!(
+            "root element is <{}>, expected <node>",
+            root.name
+        )));
+    }
+
+    node(&root)
+}
+
+/// XML element, with its attributes and child elements. Text is not kept.
+#[derive(Debug)]
+struct Element<'a> {
+    name: &'a str,
+    attributes: Vec<(&'a str, String)>,
+    children: Vec<Element<'a>>,
+}
+
+impl<'a> Element<'a> {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn required_attribute(&self, name: &str) -> crate::Result<&str> {
+        self.attribute(name).ok_or_else(|| {
+            crate::Error::InvalidIntrospection(format!(
+                "<{}> without {} attribute",
+                self.name, name
+            ))
+        })
+    }
+
+    fn children_named<'s>(&'s self, name: &'s str) -> impl Iterator<Item = &'s Element<'a>> {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+}
+
+fn node(element: &Element) -> crate::Result<Node> {
+    Ok(Node {
+        name: element.attribute("name").map(str::to_string),
+        interfaces: element
+            .children_named("interface")
+            .map(interface)
+            .collect::<crate::Result<_>>()?,
+        nodes: element
+            .children_named("node")
+            .map(node)
+            .collect::<crate::Result<_>>()?,
+    })
+}
+
+fn interface(element: &Element) -> crate::Result<Interface> {
+    Ok(Interface {
+        name: element.required_attribute("name")?.to_string(),
+        methods: element
+            .children_named("method")
+            .map(|method| {
+                Ok(Method {
+                    name: method.required_attribute("name")?.to_string(),
+                    args: args(method, Direction::In)?,
+                })
+            })
+            .collect::<crate::Result<_>>()?,
+        signals: element
+            .children_named("signal")
+            .map(|signal| {
+                Ok(Signal {
+                    name: signal.required_attribute("name")?.to_string(),
+                    args: args(signal, Direction::Out)?,
+                })
+            })
+            .collect::<crate::Result<_>>()?,
+        properties: element
+            .children_named("property")
+            .map(property)
+            .collect::<crate::Result<_>>()?,
+    })
+}
+
+/// Arguments of a method or signal, with the direction of arguments that do not specify one.
+fn args(element: &Element, default_direction: Direction) -> crate::Result<Vec<Arg>> {
+    element
+        .children_named("arg")
+        .map(|arg| {
+            let direction = match arg.attribute("direction") {
+                None => default_direction,
+                Some("in") => Direction::In,
+                Some("out") => Direction::Out,
+                Some(other) => {
+                    return Err(crate::Error::InvalidIntrospection(format!(
+                        "invalid direction {:?}",
+                        other
+                    )))
+                }
+            };
+
+            Ok(Arg {
+                name: arg.attribute("name").map(str::to_string),
+                signature: DBusSignature::new_from_signature(arg.required_attribute("type")?)?,
+                direction,
+            })
+        })
+        .collect()
+}
+
+fn property(element: &Element) -> crate::Result<Property> {
+    let access = match element.required_attribute("access")? {
+        "read" => Access::Read,
+        "write" => Access::Write,
+        "readwrite" => Access::ReadWrite,
+        other => {
+            return Err(crate::Error::InvalidIntrospection(format!(
+                "invalid access {:?}",
+                other
+            )))
+        }
+    };
+
+    Ok(Property {
+        name: element.required_attribute("name")?.to_string(),
+        signature: DBusSignature::new_from_signature(element.required_attribute("type")?)?,
+        access,
+    })
+}
+
+/// The root element, surrounded by the XML declaration, doctype, comments and whitespace.
+fn document(i: &str) -> IResult<&str, Element<'_>> {
+    delimited(many0(misc), |i| element(i, 0), many0(misc))(i)
+}
+
+/// Markup that is skipped.
+fn misc(i: &str) -> IResult<&str, ()> {
+    alt((
+        value((), multispace1),
+        value((), tuple((tag("<!--"), take_until("-->"), tag("-->")))),
+        value((), tuple((tag("<?"), take_until("?>"), tag("?>")))),
+        value((), tuple((tag("<!DOCTYPE"), is_not(">"), char('>')))),
+    ))(i)
+}
+
+/// Element at the given depth, where the root element is at depth 0.
+///
+/// Introspection data comes from other connections, so the depth is limited like when
+/// unmarshalling, to prevent parsing from overflowing the stack.
+fn element(start: &str, depth: usize) -> IResult<&str, Element<'_>> {
+    let (i, _) = char('<')(start)?;
+    let (i, name) = xml_name(i)?;
+    if depth >= MAX_NESTING_DEPTH {
+        return Err(nom::Err::Failure(nom::error::Error::new(
+            start,
+            ErrorKind::TooLarge,
+        )));
+    }
+    let (i, attributes) = many0(preceded(multispace1, attribute))(i)?;
+    let (i, _) = multispace0(i)?;
+
+    if let Ok((i, _)) = tag::<_, _, nom::error::Error<&str>>("/>")(i) {
+        return Ok((
+            i,
+            Element {
+                name,
+                attributes,
+                children: Vec::new(),
+            },
+        ));
+    }
+
+    let (i, _) = char('>')(i)?;
+    let (i, children) = many0(alt((
+        map(|i| element(i, depth + 1), Some),
+        map(misc, |_| None),
+        map(is_not("<"), |_| None),
+    )))(i)?;
+    let (i, _) = delimited(tag("</"), tag(name), tuple((multispace0, char('>'))))(i)?;
+
+    Ok((
+        i,
+        Element {
+            name,
+            attributes,
+            children: children.into_iter().flatten().collect(),
+        },
+    ))
+}
+
+fn xml_name(i: &str) -> IResult<&str, &str> {
+    take_while1(|c: char| c.is_alphanumeric() || ":_-.".contains(c))(i)
+}
+
+/// Attribute, with its value unescaped.
+fn attribute(i: &str) -> IResult<&str, (&str, String)> {
+    let (i, name) = xml_name(i)?;
+    let (i, _) = tuple((multispace0, char('='), multispace0))(i)?;
+    let (i, value) = alt((
+        delimited(char('"'), take_until("\""), char('"')),
+        delimited(char('\''), take_until("'"), char('\'')),
+    ))(i)?;
+
+    Ok((i, (name, unescape(value))))
+}
+
+/// Replace the predefined XML entities.
+fn unescape(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Start of the introspection data of `org.freedesktop.DBus`, as returned by dbus-daemon.
+    const DBUS_INTROSPECTION: &str = r#"<!DOCTYPE node PUBLIC "-//freedesktop//DTD D-BUS Object Introspection 1.0//EN"
+"http://www.freedesktop.org/standards/dbus/1.0/introspect.dtd">
+<node>
+  <interface name="org.freedesktop.DBus">
+    <method name="Hello">
+      <arg direction="out" type="s"/>
+    </method>
+    <method name="RequestName">
+      <arg direction="in" type="s"/>
+      <arg direction="in" type="u"/>
+      <arg direction="out" type="u"/>
+    </method>
+    <method name="ListNames">
+      <arg direction="out" type="as"/>
+    </method>
+    <method name="GetConnectionCredentials">
+      <arg direction="in" type="s"/>
+      <arg direction="out" type="a{sv}"/>
+    </method>
+    <signal name="NameOwnerChanged">
+      <arg type="s"/>
+      <arg type="s"/>
+      <arg type="s"/>
+    </signal>
+    <property name="Features" type="as" access="read">
+      <annotation name="org.freedesktop.DBus.Property.EmitsChangedSignal" value="const"/>
+    </property>
+  </interface>
+  <interface name="org.freedesktop.DBus.Introspectable">
+    <method name="Introspect">
+      <arg direction="out" type="s"/>
+    </method>
+  </interface>
+  <!-- Child objects -->
+  <node name="org"/>
+</node>
+"#;
+
+    #[test]
+    fn parse_dbus_introspection() -> crate::Result<()> {
+        let node = parse(DBUS_INTROSPECTION)?;
+
+        let dbus = node.interface("org.freedesktop.DBus").expect("interface");
+        let hello = dbus.method("Hello").expect("Hello");
+        assert_eq!(hello.in_signature().to_string(), "");
+        assert_eq!(hello.out_signature().to_string(), "s");
+
+        let request_name = dbus.method("RequestName").expect("RequestName");
+        assert_eq!(request_name.in_signature().to_string(), "su");
+        assert_eq!(request_name.out_signature().to_string(), "u");
+
+        let name_owner_changed = dbus.signal("NameOwnerChanged").expect("signal");
+        assert_eq!(name_owner_changed.signature().to_string(), "sss");
+
+        let features = dbus.property("Features").expect("Features");
+        assert_eq!(features.signature.to_string(), "as");
+        assert_eq!(features.access, Access::Read);
+
+        assert!(node
+            .interface("org.freedesktop.DBus.Introspectable")
+            .is_some());
+        assert_eq!(node.nodes.len(), 1);
+        assert_eq!(node.nodes[0].name.as_deref(), Some("org"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_invalid() {
+        let invalid = |xml| matches!(parse(xml), Err(crate::Error::InvalidIntrospection(_)));
+
+        assert!(invalid("<node>"));
+        assert!(invalid("<node></interface>"));
+        assert!(invalid("<interface name='a.b'/>"));
+        assert!(invalid("<node><interface/></node>"));
+        assert!(invalid(
+            "<node><interface name='a.b'><method name='M'><arg direction='both' type='s'/>\
+             </method></interface></node>"
+        ));
+    }
+
+    #[test]
+    fn parse_nesting_limit() -> crate::Result<()> {
+        let nested = |depth| {
+            format!(
+                "{}{}",
+                "<node name='a'>".repeat(depth),
+                "</node>".repeat(depth)
+            )
+        };
+
+        let mut node = parse(&nested(MAX_NESTING_DEPTH))?;
+        for _ in 1..MAX_NESTING_DEPTH {
+            node = node.nodes.remove(0);
+        }
+        assert!(node.nodes.is_empty());
+
+        assert!(matches!(
+            parse(&nested(MAX_NESTING_DEPTH + 1)),
+            Err(crate::Error::InvalidIntrospection(_))
+        ));
+        // Deep enough to overflow the stack without the limit.
+        assert!(matches!(
+            parse(&nested(1_000_000)),
+            Err(crate::Error::InvalidIntrospection(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn unescape_attribute() {
+        assert_eq!(unescape("&lt;a&gt; &amp;lt;"), "<a> &lt;");
+    }
+}
@@ -1,11 +1,43 @@
 //! DBus Specification:
 //! https://dbus.freedesktop.org/doc/dbus-specification.html#message-protocol-marshaling
 
+pub(crate) mod address;
 pub(crate) mod connection;
+pub mod introspection;
 pub(crate) mod message_protocol;
+pub(crate) mod timer;
 pub(crate) mod type_system;
 
 pub use connection::Connection;
+pub use connection::Credentials;
+pub use connection::Incoming;
+pub use connection::ManagedObjects;
+pub use connection::MatchRule;
+pub use connection::MethodCallBuilder;
+pub use connection::PropertiesChanged;
+pub use connection::Receiver;
+pub use connection::Sender;
+pub use connection::SignalBuilder;
+pub use connection::StartServiceReply;
+pub use message_protocol::body::Body;
+pub use message_protocol::body::FromBody;
+pub use message_protocol::error_name::ErrorName;
+pub use message_protocol::names::BusName;
+pub use message_protocol::IncomingMethodCall;
+pub use message_protocol::Message;
+pub use message_protocol::MessageTypeParam;
+pub use message_protocol::MethodCall;
+pub use message_protocol::MethodReturn;
+pub use message_protocol::Serial;
+pub use message_protocol::Signal;
+pub use timer::SmolTimer;
+pub use timer::Timer;
+#[cfg(feature = "tokio")]
+pub use timer::TokioTimer;
+pub use type_system::signature::Signature;
+pub use type_system::signature::SingleCompleteTypeSignature;
+pub use type_system::types;
+pub use type_system::Endianness;
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -24,10 +56,202 @@ pub enum Error {
     #[error("Parse error")]
     ParseError,
 
+    #[error("Array item has signature {found:?}, but array item type is {expected:?}")]
+    HeterogeneousArray {
+        expected: type_system::signature::SingleCompleteTypeSignature,
+        found: type_system::signature::SingleCompleteTypeSignature,
+    },
+
+    #[error("Body does not match its signature: expected {expected:?}, found {found:?}")]
+    BodySignatureMismatch {
+        expected: type_system::signature::SingleCompleteTypeSignature,
+        found: type_system::signature::SingleCompleteTypeSignature,
+    },
+
+    #[error("All serials are waiting for a reply")]
+    NoFreeSerial,
+
+    #[error("Invalid introspection data: {0}")]
+    InvalidIntrospection(String),
+
+    #[error("Method call has no destination, and no default destination is set")]
+    NoDestination,
+
+    #[error("Method call does not expect a reply, so it must be sent without waiting for one")]
+    NoReplyExpected,
+
+    #[error("Invalid message type: {0}")]
+    InvalidMessageType(u8),
+
+    #[error("Unknown message type: {0}")]
+    UnknownMessageType(u8),
+
+    #[error("Dict entry outside of an array")]
+    DictEntryOutsideArray,
+
+    #[error("Dict entry key is not a basic type: {0:?}")]
+    DictEntryKeyNotBasic(type_system::signature::SingleCompleteTypeSignature),
+
+    #[error("Reserved header flag bits are set: {0:#04x}")]
+    ReservedFlagBitsSet(u8),
+
+    #[error("Header field with code {0} appears more than once")]
+    DuplicateHeaderField(u8),
+
+    #[error("Missing mandatory header field with code {0}")]
+    MissingHeaderField(u8),
+
+    #[error("Reply serial is 0")]
+    InvalidReplySerial,
+
+    #[error("Signature is {0} bytes long, but at most 255 bytes are allowed")]
+    SignatureTooLong(usize),
+
+    #[error("Invalid type code in signature: {:?}", char::from(*.0))]
+    InvalidSignatureChar(u8),
+
+    #[error("String contains a null byte: {0:?}")]
+    StringContainsNul(String),
+
+    #[error("Expected {expected}, found value with signature {found:?}")]
+    UnexpectedType {
+        expected: &'static str,
+        found: type_system::signature::SingleCompleteTypeSignature,
+    },
+
+    #[error("Expected {expected} arguments, found {found}")]
+    UnexpectedArgumentCount { expected: usize, found: usize },
+
+    #[error("Struct has {len} fields, so there is no field {index}")]
+    StructFieldOutOfRange { index: usize, len: usize },
+
+    #[error("Invalid object path: {0}")]
+    InvalidObjectPath(String),
+
+    #[error("Invalid interface name: {0}")]
+    InvalidInterfaceName(String),
+
+    #[error("Invalid member name: {0}")]
+    InvalidMemberName(String),
+
+    #[error("Invalid bus name: {0}")]
+    InvalidBusName(String),
+
+    #[error("Invalid DBus address: {0}")]
+    InvalidAddress(String),
+
     #[error("Failed AUTH")]
     FailedAuth,
+
+    #[error("Server did not accept AUTH command: {0}")]
+    AuthError(String),
+
+    #[error("Connection closed before the AUTH handshake was completed")]
+    AuthHandshakeIncomplete,
+
+    #[error("Malformed line in AUTH protocol: {0:?}")]
+    AuthProtocol(String),
+
+    #[error("Disconnected")]
+    Disconnected,
+
+    #[error("Timed out")]
+    Timeout,
+
+    #[error("Received error reply: {0}")]
+    ErrorReply(String),
+
+    #[error("Unexpected reply")]
+    UnexpectedReply,
+
+    #[error("Reply has unexpected signature: {0}")]
+    UnexpectedReplySignature(type_system::types::DBusSignature),
+
+    #[error("Message of {0} bytes exceeds the maximum message size")]
+    MessageTooLarge(u64),
+
+    #[error("Message declares {expected} file descriptors, but {found} were received")]
+    MissingUnixFds { expected: usize, found: usize },
+
+    #[error("Containers are nested too deeply")]
+    NestingTooDeep,
+
+    #[error("Invariant violated: {0}")]
+    InvariantViolation(&'static str),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
 pub const MAJOR_PROTOCOL_VERSION: u8 = 1;
+
+/// Maximum length in bytes of a message, including header and body, as given by the spec.
+pub const MAX_MESSAGE_SIZE: usize = 134_217_728;
+
+/// Marshal a message in big endian byte order, without sending it anywhere.
+pub fn marshal(message: &Message) -> Result<Vec<u8>> {
+    message.marshal_be()
+}
+
+/// Marshal a signature that is already serialized, for example when proxying, as a `SIGNATURE`
+/// value: a length byte, the signature, and a terminating null byte.
+///
+/// The signature is checked to be a sequence of single complete types.
+///
+/// ```
+/// let marshalled = dbus_stream::marshal_raw_signature(b"a{sv}")?;
+/// assert_eq!(marshalled, b"\x05a{sv}\0");
+///
+/// assert!(dbus_stream::marshal_raw_signature(b"a{vs}").is_err());
+/// # Ok::<(), dbus_stream::Error>(())
+/// ```
+pub fn marshal_raw_signature(signature: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = type_system::marshal::Encoder::default();
+    encoder.marshal_raw_signature(signature)?;
+    Ok(encoder.finish())
+}
+
+/// Unmarshal a complete message, without reading it from a connection.
+pub fn unmarshal(message: &[u8]) -> Result<Message> {
+    type_system::unmarshal::unmarshal_message(message)
+}
+
+/// Unmarshal the message at the start of the data, for example a buffer with several messages.
+///
+/// Returns the message and the number of bytes it takes up.
+pub fn unmarshal_prefix(message: &[u8]) -> Result<(Message, usize)> {
+    type_system::unmarshal::unmarshal_message_prefix(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message_protocol::tests::method_call;
+    use crate::types::*;
+
+    #[test]
+    fn marshal_unmarshal_round_trip() -> Result<()> {
+        let message = Message {
+            flag_no_auto_start: true,
+            message_type_param: MessageTypeParam::MethodCall(MethodCall {
+                path: DBusObjectPath::from("/org/example/Object"),
+                interface: Some(DBusString::from("org.example.Interface")),
+                member: DBusString::from("Method"),
+            }),
+            destination: Some(DBusString::from("org.example.Service")),
+            body: Body {
+                arguments: vec![
+                    Type::from(DBusUint32::from(42_u32)),
+                    Type::from(DBusString::from("hello")),
+                ],
+            },
+            ..method_call(3)
+        };
+
+        let marshalled = marshal(&message)?;
+        let unmarshalled = unmarshal(&marshalled)?;
+
+        assert_eq!(message, unmarshalled);
+
+        Ok(())
+    }
+}
@@ -1,17 +1,21 @@
 pub mod body;
+pub mod error_name;
+pub(crate) mod names;
 
 use std::convert::TryFrom;
+use std::fmt;
 use std::num::NonZeroU32;
 
 use self::body::Body;
-use crate::type_system::marshal::Marshal;
+use crate::type_system::marshal::marshalled_end;
 use crate::type_system::marshal::Encoder;
+use crate::type_system::marshal::Marshal;
 use crate::type_system::signature::HEADER_FIELD_SIGNATURE;
 use crate::type_system::types::*;
 use crate::type_system::Endianness;
 
-#[derive(Debug, Clone)]
-enum HeaderField {
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum HeaderField {
     Path(DBusObjectPath),
     Interface(DBusString),
     Member(DBusString),
@@ -23,6 +27,36 @@ enum HeaderField {
     UnixFds(DBusUint32),
 }
 
+/// File descriptor received along with a message.
+#[cfg(unix)]
+pub type UnixFd = std::os::unix::io::OwnedFd;
+
+/// File descriptors can only be received on unix, so there are none on other platforms.
+#[cfg(not(unix))]
+pub type UnixFd = std::convert::Infallible;
+
+/// Serial of a message, used to correlate replies with the method calls they reply to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Serial(pub NonZeroU32);
+
+impl From<NonZeroU32> for Serial {
+    fn from(serial: NonZeroU32) -> Self {
+        Self(serial)
+    }
+}
+
+impl From<Serial> for NonZeroU32 {
+    fn from(serial: Serial) -> Self {
+        serial.0
+    }
+}
+
+impl fmt::Display for Serial {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
 #[derive(Debug)]
 pub struct Message {
     pub flag_no_reply_expected: bool,
@@ -37,11 +71,27 @@ pub struct Message {
     /// The name of the connection this message is intended for.
     pub destination: Option<DBusString>,
 
+    /// Unique name of the sending connection. Filled in by the message bus.
+    ///
+    /// Normal clients leave it as `None`. If set, it is marshalled, for example for proxying
+    /// messages.
+    pub sender: Option<DBusString>,
+
+    /// Number of Unix file descriptors that accompany the message.
+    ///
+    /// Only decoded from received messages, since sending file descriptors is not supported.
+    pub unix_fds: Option<u32>,
+
+    /// File descriptors received along with the message, when connected over a unix socket.
+    ///
+    /// Values of type `h` in the body are indexes into these, see [Message::received_fd].
+    pub received_fds: Vec<UnixFd>,
+
     /// Body
     pub body: Body,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MessageType {
     MethodCall,
     MethodReturn,
@@ -49,15 +99,15 @@ pub enum MessageType {
     Signal,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub enum MessageTypeParam {
     MethodCall(MethodCall),
-    MethodReturn,
-    Error,
+    MethodReturn(MethodReturn),
+    Error(Error),
     Signal(Signal),
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct MethodCall {
     /// The object to send a call to.
     pub path: DBusObjectPath,
@@ -69,7 +119,40 @@ pub struct MethodCall {
     pub member: DBusString,
 }
 
-#[derive(Debug)]
+/// Method call received by a server, borrowed from a [Message] by [Message::as_method_call].
+#[derive(Debug, PartialEq)]
+pub struct IncomingMethodCall<'a> {
+    /// The object the call is sent to.
+    pub path: &'a str,
+
+    /// The interface the method is invoked on, if given by the caller.
+    pub interface: Option<&'a str>,
+
+    /// The name of the method.
+    pub member: &'a str,
+
+    /// Unique name of the calling connection, filled in by the message bus.
+    pub sender: Option<&'a str>,
+
+    pub body: &'a Body,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct MethodReturn {
+    /// The serial of the method call this is a reply to.
+    pub reply_serial: NonZeroU32,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Error {
+    /// The name of the error that occurred, for example `org.freedesktop.DBus.Error.ServiceUnknown`.
+    pub error_name: DBusString,
+
+    /// The serial of the method call this is a reply to.
+    pub reply_serial: NonZeroU32,
+}
+
+#[derive(Debug, PartialEq)]
 pub struct Signal {
     /// The object to send a call to.
     pub path: DBusObjectPath,
@@ -81,15 +164,26 @@ pub struct Signal {
     pub member: DBusString,
 }
 
-/// Prepare header fields to be marshalled.
-///
-/// Header fields are an Array of Struct(Byte, Variant).
-fn prepare_header_fields<T: IntoIterator<Item = HeaderField>>(header_fields: T) -> DBusArray {
+impl Encoder {
+    /// Marshal header fields.
+    ///
+    /// Header fields are an Array of Struct(Byte, Variant).
+    pub(crate) fn marshal_header_fields(
+        &mut self,
+        header_fields: &[HeaderField],
+        endianness: Endianness,
+    ) -> crate::Result<()> {
+        self.marshal(&header_field_array(header_fields), endianness)
+    }
+}
+
+/// Convert header fields to a DBus Array of Struct of (Byte, Variant).
+fn header_field_array(header_fields: &[HeaderField]) -> DBusArray {
     let mut array = DBusArray::new(HEADER_FIELD_SIGNATURE.clone());
 
     for header_field in header_fields {
         let byte: DBusByte = DBusByte::from(header_field.decimal_code());
-        let variant: DBusVariant = header_field.inner_into_variant();
+        let variant: DBusVariant = header_field.inner_to_variant();
         let header_field_struct = DBusStruct::new(vec![byte.into(), variant.into()]);
 
         array.items.push(Type::from(header_field_struct));
@@ -110,28 +204,68 @@ impl MessageType {
 }
 
 impl Message {
+    /// Marshal message in big endian byte order.
     pub fn marshal_be(&self) -> crate::Result<Vec<u8>> {
-        let endianness = Endianness::BigEndian;
+        self.marshal(Endianness::BigEndian)
+    }
 
-        let marshalled_body: Vec<u8> = self
-            .body
-            .arguments
-            .iter()
-            .try_fold(Encoder::default(), |mut m, arg| {
-                match m.marshal_be(arg) {
-                    Ok(()) => Ok(m),
-                    Err(err) => Err(err),
-                }
-            })?
-            .finish();
+    /// Marshal message in big endian byte order into the given buffer, so that it can be reused
+    /// between messages.
+    ///
+    /// The buffer is cleared first, since alignment is relative to the start of the message.
+    pub fn marshal_be_into(&self, out: &mut Vec<u8>) -> crate::Result<()> {
+        out.clear();
+        out.reserve(self.marshalled_len()?);
+
+        let mut encoder = Encoder {
+            buf: std::mem::take(out),
+        };
+        let result = self.marshal_into(&mut encoder, Endianness::BigEndian);
+        *out = encoder.finish();
+
+        result
+    }
 
-        let mut header: Vec<u8> = Vec::new();
+    /// Marshal message in big endian byte order, and also format the bytes as a hexdump that can
+    /// be logged for debugging.
+    ///
+    /// Each line of the hexdump has the offset, 16 bytes in two groups of 8 to show the 8-byte
+    /// alignment boundaries, and the bytes as ASCII.
+    pub fn marshal_be_debug(&self) -> crate::Result<(Vec<u8>, String)> {
+        let bytes = self.marshal_be()?;
+        let dump = hexdump(&bytes);
+        Ok((bytes, dump))
+    }
+
+    pub fn marshal(&self, endianness: Endianness) -> crate::Result<Vec<u8>> {
+        let mut encoder = Encoder::with_capacity(self.marshalled_len()?);
+        self.marshal_into(&mut encoder, endianness)?;
+        Ok(encoder.finish())
+    }
+
+    /// Marshal message, after checking that the body matches the signature that is sent with it.
+    ///
+    /// See [Body::check_signature].
+    pub fn marshal_checked(&self, endianness: Endianness) -> crate::Result<Vec<u8>> {
+        self.body.check_signature()?;
+        self.marshal(endianness)
+    }
+
+    /// Marshal message into an empty encoder, so that its buffer can be reused between messages.
+    pub(crate) fn marshal_into(
+        &self,
+        encoder: &mut Encoder,
+        endianness: Endianness,
+    ) -> crate::Result<()> {
+        debug_assert!(encoder.buf.is_empty());
 
         // 1st byte: Endianness
-        header.push(endianness.ascii_code());
+        encoder.buf.push(endianness.ascii_code());
 
         // 2nd byte: Message Type
-        header.push(self.message_type_param.message_type().decimal_value());
+        encoder
+            .buf
+            .push(self.message_type_param.message_type().decimal_value());
 
         // 3rd byte: Bitwise OR flags
         let mut flags = 0;
@@ -144,36 +278,70 @@ impl Message {
         if self.flag_allow_interactive_authorization {
             flags |= 0x4;
         }
-        header.push(flags);
+        encoder.buf.push(flags);
 
         // 4th byte: Major protocol version
-        header.push(crate::MAJOR_PROTOCOL_VERSION);
+        encoder.buf.push(crate::MAJOR_PROTOCOL_VERSION);
 
-        // 5th to 8th byte: Length in bytes of message body
-        let length_in_bytes_of_message_body = u32::try_from(marshalled_body.len())?;
-        header.extend_from_slice(&length_in_bytes_of_message_body.to_be_bytes());
+        // 5th to 8th byte: Length in bytes of message body, set when the body has been marshalled.
+        let set_length_in_bytes_of_message_body = encoder.reserve_n_bytes::<4>();
 
         // 9th to 12th byte: Serial
-        header.extend_from_slice(&self.serial.get().to_be_bytes());
+        let serial = self.serial.get();
+        match endianness {
+            Endianness::BigEndian => encoder.extend_from_array(serial.to_be_bytes()),
+            Endianness::LittleEndian => encoder.extend_from_array(serial.to_le_bytes()),
+        }
 
         // Header fields.
-        //
+        let header_fields = self.header_fields();
+
+        // Convert header fields enums to a DBus Array of Struct of (Byte, Variant), and marshal that.
+        encoder.marshal_header_fields(&header_fields, endianness)?;
+
+        // Header must be 8-aligned with null bytes
+        encoder.align(8);
+
+        // Body. Since it starts on an 8-byte boundary, it is aligned as if it started at 0.
+        let start_of_body = encoder.buf.len();
+        self.body.marshal_into(encoder, endianness)?;
+
+        let length_in_bytes_of_message_body = u32::try_from(encoder.buf.len() - start_of_body)?;
+        let length_in_bytes_of_message_body = match endianness {
+            Endianness::BigEndian => length_in_bytes_of_message_body.to_be_bytes(),
+            Endianness::LittleEndian => length_in_bytes_of_message_body.to_le_bytes(),
+        };
+        set_length_in_bytes_of_message_body(encoder, length_in_bytes_of_message_body)?;
+
+        Ok(())
+    }
+
+    /// Header fields to marshal for this message.
+    fn header_fields(&self) -> Vec<HeaderField> {
         // TODO:
         // The way these header fields are first converted to an intermediate layout, requires cloning.
         // To improve performance, either change the intermediary to work with references, or use COW, Rc or something like that.
         let mut header_fields: Vec<HeaderField> = Vec::new();
 
         // Header field: Signature
-        // This _can_ be omitted if the body is empty, but here we  always include it.
-        header_fields.push(HeaderField::Signature(self.body.signature()));
+        // Omitted if the body is empty, like dbus-daemon does.
+        if !self.body.arguments.is_empty() {
+            header_fields.push(HeaderField::Signature(self.body.signature()));
+        }
 
         // Header field: Destination (optional).
         if let Some(destination) = &self.destination {
             header_fields.push(HeaderField::Destination(destination.clone()));
         }
 
+        // Header field: Sender (optional).
+        // Normally filled in by the message bus, but can be set explicitly when proxying.
+        if let Some(sender) = &self.sender {
+            header_fields.push(HeaderField::Sender(sender.clone()));
+        }
+
         // NOTE:
-        // No current handling of header fields: UNIX_FDS, SENDER,
+        // No current handling of header field UNIX_FDS.
 
         // Message type specific header fields
         match &self.message_type_param {
@@ -187,22 +355,212 @@ impl Message {
                     header_fields.push(HeaderField::Interface(interface.clone()));
                 }
             }
-            _ => todo!("Header fields for other message types"),
+            MessageTypeParam::MethodReturn(method_return) => {
+                // Reply serial is mandatory.
+                header_fields.push(HeaderField::ReplySerial(DBusUint32::from(
+                    method_return.reply_serial.get(),
+                )));
+            }
+            MessageTypeParam::Error(error) => {
+                // Error name and reply serial are mandatory.
+                header_fields.push(HeaderField::ErrorName(error.error_name.clone()));
+                header_fields.push(HeaderField::ReplySerial(DBusUint32::from(
+                    error.reply_serial.get(),
+                )));
+            }
+            MessageTypeParam::Signal(signal) => {
+                // Path, Interface and Member are all mandatory.
+                header_fields.push(HeaderField::Path(signal.path.clone()));
+                header_fields.push(HeaderField::Interface(signal.interface.clone()));
+                header_fields.push(HeaderField::Member(signal.member.clone()));
+            }
         };
 
-        let mut header = Encoder { buf: header };
+        header_fields
+    }
 
-        // Convert header fields enums to a DBus Array of Struct of (Byte, Variant), and marshal that.
-        header.marshal_be(&prepare_header_fields(header_fields))?;
+    /// The fields of a method call that a server needs to handle it, or `None` if the message
+    /// is not a method call.
+    pub fn as_method_call(&self) -> Option<IncomingMethodCall<'_>> {
+        match &self.message_type_param {
+            MessageTypeParam::MethodCall(method_call) => Some(IncomingMethodCall {
+                path: &method_call.path.dbus_string.string,
+                interface: method_call
+                    .interface
+                    .as_ref()
+                    .map(|interface| interface.string.as_str()),
+                member: &method_call.member.string,
+                sender: self.sender.as_ref().map(|sender| sender.string.as_str()),
+                body: &self.body,
+            }),
+            _ => None,
+        }
+    }
 
-        // Header must be 8-aligned with null bytes
-        header.align(8);
+    /// The received file descriptor that a value of type `h` in the body refers to.
+    pub fn received_fd(&self, fd: &DBusUnixFileDescriptor) -> Option<&UnixFd> {
+        self.received_fds.get(usize::try_from(fd.u32).ok()?)
+    }
+
+    /// Serial of the method call that this message is a reply to, if it is a reply.
+    pub fn reply_serial(&self) -> Option<Serial> {
+        match &self.message_type_param {
+            MessageTypeParam::MethodReturn(method_return) => {
+                Some(Serial::from(method_return.reply_serial))
+            }
+            MessageTypeParam::Error(error) => Some(Serial::from(error.reply_serial)),
+            MessageTypeParam::MethodCall(_) | MessageTypeParam::Signal(_) => None,
+        }
+    }
+
+    /// Whether a reply to this message is expected, which is only the case for method calls
+    /// without the NO_REPLY_EXPECTED flag.
+    pub fn expects_reply(&self) -> bool {
+        match self.message_type_param {
+            MessageTypeParam::MethodCall(_) => !self.flag_no_reply_expected,
+            _ => false,
+        }
+    }
+
+    /// Length in bytes of the marshalled message, without marshalling it.
+    ///
+    /// This is the same as the length of the result of [Message::marshal], in either byte order.
+    pub fn marshalled_len(&self) -> crate::Result<usize> {
+        // Fixed part of the header, 12 bytes, followed by the header fields.
+        let header_fields = Type::from(header_field_array(&self.header_fields()));
+        let length_of_header = marshalled_end(&header_fields, 12);
+        let length_of_padded_header = length_of_header.div_ceil(8) * 8;
+
+        // The body starts on an 8-byte boundary, so it is aligned as if it started at 0.
+        let length_of_body = self
+            .body
+            .arguments
+            .iter()
+            .fold(0, |offset, arg| marshalled_end(arg, offset));
+        u32::try_from(length_of_body)?;
+
+        Ok(length_of_padded_header + length_of_body)
+    }
+
+    /// One-line description of the message for logging, which is only formatted when displayed.
+    pub(crate) fn summary(&self) -> MessageSummary<'_> {
+        MessageSummary(self)
+    }
+}
+
+/// One-line description of a message, see [Message::summary].
+///
+/// Example: `MethodCall serial=3 path=/org/freedesktop/DBus member=Hello`.
+pub(crate) struct MessageSummary<'a>(&'a Message);
+
+impl fmt::Display for MessageSummary<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = self.0;
+        write!(
+            f,
+            "{:?} serial={}",
+            message.message_type_param.message_type(),
+            message.serial
+        )?;
+
+        match &message.message_type_param {
+            MessageTypeParam::MethodCall(method_call) => {
+                write!(f, " path={}", method_call.path)?;
+                if let Some(interface) = &method_call.interface {
+                    write!(f, " interface={}", interface.string)?;
+                }
+                write!(f, " member={}", method_call.member.string)?;
+            }
+            MessageTypeParam::MethodReturn(method_return) => {
+                write!(f, " reply_serial={}", method_return.reply_serial)?;
+            }
+            MessageTypeParam::Error(error) => {
+                write!(f, " reply_serial={}", error.reply_serial)?;
+                write!(f, " error_name={}", error.error_name.string)?;
+            }
+            MessageTypeParam::Signal(signal) => {
+                write!(f, " path={}", signal.path)?;
+                write!(f, " interface={}", signal.interface.string)?;
+                write!(f, " member={}", signal.member.string)?;
+            }
+        }
 
-        // Finalize marshalled message by appending body.
-        let mut message = header.finish();
-        message.extend(marshalled_body);
-        Ok(message)
+        if !message.body.arguments.is_empty() {
+            write!(f, " signature={}", message.body.signature())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Received file descriptors are compared by number, since they can not be compared otherwise.
+impl PartialEq for Message {
+    fn eq(&self, other: &Self) -> bool {
+        let Message {
+            flag_no_reply_expected,
+            flag_no_auto_start,
+            flag_allow_interactive_authorization,
+            serial,
+            message_type_param,
+            destination,
+            sender,
+            unix_fds,
+            received_fds,
+            body,
+        } = self;
+
+        *flag_no_reply_expected == other.flag_no_reply_expected
+            && *flag_no_auto_start == other.flag_no_auto_start
+            && *flag_allow_interactive_authorization == other.flag_allow_interactive_authorization
+            && *serial == other.serial
+            && *message_type_param == other.message_type_param
+            && *destination == other.destination
+            && *sender == other.sender
+            && *unix_fds == other.unix_fds
+            && same_fds(received_fds, &other.received_fds)
+            && *body == other.body
+    }
+}
+
+#[cfg(unix)]
+fn same_fds(a: &[UnixFd], b: &[UnixFd]) -> bool {
+    use std::os::unix::io::AsRawFd;
+
+    a.iter()
+        .map(AsRawFd::as_raw_fd)
+        .eq(b.iter().map(AsRawFd::as_raw_fd))
+}
+
+#[cfg(not(unix))]
+fn same_fds(_a: &[UnixFd], _b: &[UnixFd]) -> bool {
+    true
+}
+
+/// Format bytes like `hexdump -C`.
+fn hexdump(bytes: &[u8]) -> String {
+    let mut dump = String::new();
+
+    for (line, chunk) in bytes.chunks(16).enumerate() {
+        let mut hex = String::new();
+        for (i, byte) in chunk.iter().enumerate() {
+            if i == 8 {
+                hex.push(' ');
+            }
+            hex.push_str(&format!("{:02x} ", byte));
+        }
+
+        let ascii: String = chunk
+            .iter()
+            .map(|&byte| match byte {
+                b' '..=b'~' => char::from(byte),
+                _ => '.',
+            })
+            .collect();
+
+        dump.push_str(&format!("{:08x}  {:<49} |{}|\n", line * 16, hex, ascii));
     }
+
+    dump
 }
 
 impl HeaderField {
@@ -220,17 +578,17 @@ impl HeaderField {
         }
     }
 
-    fn inner_into_variant(self) -> DBusVariant {
+    fn inner_to_variant(&self) -> DBusVariant {
         match self {
-            Self::Path(inner) => DBusVariant::new(inner),
-            Self::Interface(inner) => DBusVariant::new(inner),
-            Self::Member(inner) => DBusVariant::new(inner),
-            Self::ErrorName(inner) => DBusVariant::new(inner),
-            Self::ReplySerial(inner) => DBusVariant::new(inner),
-            Self::Destination(inner) => DBusVariant::new(inner),
-            Self::Sender(inner) => DBusVariant::new(inner),
-            Self::Signature(inner) => DBusVariant::new(inner),
-            Self::UnixFds(inner) => DBusVariant::new(inner),
+            Self::Path(inner) => DBusVariant::new(inner.clone()),
+            Self::Interface(inner) => DBusVariant::new(inner.clone()),
+            Self::Member(inner) => DBusVariant::new(inner.clone()),
+            Self::ErrorName(inner) => DBusVariant::new(inner.clone()),
+            Self::ReplySerial(inner) => DBusVariant::new(inner.clone()),
+            Self::Destination(inner) => DBusVariant::new(inner.clone()),
+            Self::Sender(inner) => DBusVariant::new(inner.clone()),
+            Self::Signature(inner) => DBusVariant::new(inner.clone()),
+            Self::UnixFds(inner) => DBusVariant::new(inner.clone()),
         }
     }
 }
@@ -239,16 +597,38 @@ impl MessageTypeParam {
     fn message_type(&self) -> MessageType {
         match self {
             MessageTypeParam::MethodCall(_) => MessageType::MethodCall,
-            MessageTypeParam::MethodReturn => MessageType::MethodReturn,
-            MessageTypeParam::Error => MessageType::Error,
+            MessageTypeParam::MethodReturn(_) => MessageType::MethodReturn,
+            MessageTypeParam::Error(_) => MessageType::Error,
             MessageTypeParam::Signal(_) => MessageType::Signal,
         }
     }
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
+    use crate::type_system::signature::SingleCompleteTypeSignature;
+
+    /// Method call without interface, destination or arguments, for tests to adjust with struct
+    /// update syntax.
+    pub(crate) fn method_call(serial: u32) -> Message {
+        Message {
+            flag_no_reply_expected: false,
+            flag_no_auto_start: false,
+            flag_allow_interactive_authorization: false,
+            serial: NonZeroU32::new(serial).unwrap(),
+            message_type_param: MessageTypeParam::MethodCall(MethodCall {
+                path: DBusObjectPath::from("/org/example/Object"),
+                interface: None,
+                member: DBusString::from("Method"),
+            }),
+            destination: None,
+            sender: None,
+            unix_fds: None,
+            received_fds: Vec::new(),
+            body: Body::default(),
+        }
+    }
 
     #[test]
     fn message_marshalling() -> crate::Result<()> {
@@ -258,11 +638,14 @@ mod tests {
             flag_allow_interactive_authorization: true,
             serial: NonZeroU32::new(1).unwrap(),
             message_type_param: MessageTypeParam::MethodCall(MethodCall {
-                path: DBusObjectPath::from("path"),
+                path: DBusObjectPath::from("/path"),
                 interface: Some(DBusString::from("interface")),
                 member: DBusString::from("member"),
             }),
             destination: None,
+            sender: None,
+            unix_fds: None,
+            received_fds: Vec::new(),
             body: Body::default(),
         };
 
@@ -274,4 +657,498 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn message_marshalling_little_endian() -> crate::Result<()> {
+        let message = Message {
+            body: Body {
+                arguments: vec![Type::from(DBusUint32::from(5_u32))],
+            },
+            ..method_call(0x01020304)
+        };
+
+        let marshalled = message.marshal(Endianness::LittleEndian)?;
+
+        // Endianness, message type, flags, major protocol version, body length and serial.
+        assert_eq!(&marshalled[0..12], &[b'l', 1, 0, 1, 4, 0, 0, 0, 4, 3, 2, 1]);
+        // Body is the last 4 bytes.
+        assert_eq!(&marshalled[marshalled.len() - 4..], &[5, 0, 0, 0]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn header_fields_marshalling() -> crate::Result<()> {
+        let header_fields = vec![
+            HeaderField::ReplySerial(DBusUint32::from(7_u32)),
+            HeaderField::Member(DBusString::from("m")),
+        ];
+
+        let mut encoder = Encoder::default();
+        encoder.marshal_header_fields(&header_fields, Endianness::BigEndian)?;
+
+        #[rustfmt::skip]
+        let expected: &[u8] = &[
+            // Length of array data
+            0, 0, 0, 18,
+            // Padding to 8-byte boundary of the first struct
+            0, 0, 0, 0,
+            // Code of REPLY_SERIAL, then variant signature "u", then u32
+            5, 1, b'u', 0, 0, 0, 0, 7,
+            // Code of MEMBER, then variant signature "s", then string
+            3, 1, b's', 0, 0, 0, 0, 1, b'm', 0,
+        ];
+        assert_eq!(encoder.finish(), expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn sender_header_field() -> crate::Result<()> {
+        let message = |sender| Message {
+            message_type_param: MessageTypeParam::Signal(Signal {
+                path: DBusObjectPath::from("/"),
+                interface: DBusString::from("org.example.Interface"),
+                member: DBusString::from("Changed"),
+            }),
+            sender,
+            ..method_call(1)
+        };
+
+        let with_sender = message(Some(DBusString::from(":1.42")));
+        assert!(with_sender
+            .header_fields()
+            .contains(&HeaderField::Sender(DBusString::from(":1.42"))));
+        let unmarshalled =
+            crate::type_system::unmarshal::unmarshal_message(&with_sender.marshal_be()?)?;
+        assert_eq!(unmarshalled.sender, Some(DBusString::from(":1.42")));
+
+        let without_sender = message(None);
+        assert!(!without_sender
+            .header_fields()
+            .iter()
+            .any(|header_field| matches!(header_field, HeaderField::Sender(_))));
+        let unmarshalled =
+            crate::type_system::unmarshal::unmarshal_message(&without_sender.marshal_be()?)?;
+        assert_eq!(unmarshalled.sender, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn signature_header_field_omitted_for_empty_body() -> crate::Result<()> {
+        let message = |arguments| Message {
+            message_type_param: MessageTypeParam::MethodReturn(MethodReturn {
+                reply_serial: NonZeroU32::new(1).unwrap(),
+            }),
+            body: Body { arguments },
+            ..method_call(1)
+        };
+
+        let empty = message(vec![]);
+        assert_eq!(
+            empty.header_fields(),
+            vec![HeaderField::ReplySerial(DBusUint32::from(1_u32))]
+        );
+        let unmarshalled = crate::type_system::unmarshal::unmarshal_message(&empty.marshal_be()?)?;
+        assert_eq!(unmarshalled, empty);
+
+        let not_empty = message(vec![Type::from(DBusByte::from(1))]);
+        assert_eq!(
+            not_empty.header_fields(),
+            vec![
+                HeaderField::Signature(DBusSignature {
+                    vec: vec![SingleCompleteTypeSignature::DBusByte]
+                }),
+                HeaderField::ReplySerial(DBusUint32::from(1_u32)),
+            ]
+        );
+
+        Ok(())
+    }
+
+    /// Header fields of the `Hello` call, in the layout that `dbus-daemon` receives on the wire.
+    /// Each struct after a string needs padding to its 8-byte boundary, which depends on the
+    /// array starting at offset 12 of the message.
+    #[test]
+    fn header_field_array_signature() -> crate::Result<()> {
+        let header_fields = header_field_array(&[HeaderField::Member(DBusString::from("m"))]);
+        let body = Body {
+            arguments: vec![Type::from(header_fields)],
+        };
+        let header_signature = DBusSignature {
+            vec: vec![SingleCompleteTypeSignature::DBusArray(Box::new(
+                HEADER_FIELD_SIGNATURE.clone(),
+            ))],
+        };
+
+        assert_eq!(body.signature().to_string(), "a(yv)");
+        assert_eq!(header_signature.to_string(), "a(yv)");
+        assert_eq!(
+            body.signature(),
+            DBusSignature::new_from_signature("a(yv)")?
+        );
+        body.check_signature()?;
+
+        // An array of dict entries with the same types is a different signature.
+        assert_ne!(
+            body.signature(),
+            DBusSignature::new_from_signature("a{yv}")?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn header_fields_marshalling_matches_dbus_daemon_capture() -> crate::Result<()> {
+        let header_fields = vec![
+            HeaderField::Path(DBusObjectPath::from("/org/freedesktop/DBus")),
+            HeaderField::Destination(DBusString::from("org.freedesktop.DBus")),
+            HeaderField::Member(DBusString::from("Hello")),
+            HeaderField::Interface(DBusString::from("org.freedesktop.DBus")),
+        ];
+
+        // Start after the 12 bytes of the fixed part of the header.
+        let mut encoder = Encoder { buf: vec![0; 12] };
+        encoder.marshal_header_fields(&header_fields, Endianness::LittleEndian)?;
+
+        #[rustfmt::skip]
+        let expected: &[u8] = &[
+            // Length of array data
+            0x6d, 0, 0, 0,
+            // PATH, "o", "/org/freedesktop/DBus", then 2 bytes of padding
+            1, 1, b'o', 0, 21, 0, 0, 0,
+            b'/', b'o', b'r', b'g', b'/', b'f', b'r', b'e', b'e', b'd', b'e', b's', b'k', b't',
+            b'o', b'p', b'/', b'D', b'B', b'u', b's', 0, 0, 0,
+            // DESTINATION, "s", "org.freedesktop.DBus", then 3 bytes of padding
+            6, 1, b's', 0, 20, 0, 0, 0,
+            b'o', b'r', b'g', b'.', b'f', b'r', b'e', b'e', b'd', b'e', b's', b'k', b't', b'o',
+            b'p', b'.', b'D', b'B', b'u', b's', 0, 0, 0, 0,
+            // MEMBER, "s", "Hello", then 2 bytes of padding
+            3, 1, b's', 0, 5, 0, 0, 0,
+            b'H', b'e', b'l', b'l', b'o', 0, 0, 0,
+            // INTERFACE, "s", "org.freedesktop.DBus"
+            2, 1, b's', 0, 20, 0, 0, 0,
+            b'o', b'r', b'g', b'.', b'f', b'r', b'e', b'e', b'd', b'e', b's', b'k', b't', b'o',
+            b'p', b'.', b'D', b'B', b'u', b's', 0,
+        ];
+        assert_eq!(&encoder.finish()[12..], expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn marshalled_len() -> crate::Result<()> {
+        let call = || {
+            MessageTypeParam::MethodCall(MethodCall {
+                path: DBusObjectPath::from("/org/example/Object"),
+                interface: Some(DBusString::from("org.example.Interface")),
+                member: DBusString::from("Method"),
+            })
+        };
+        let message = |message_type_param, arguments| Message {
+            message_type_param,
+            destination: Some(DBusString::from("org.example.Service")),
+            body: Body { arguments },
+            ..method_call(1)
+        };
+
+        let mut large = DBusArray::new(SingleCompleteTypeSignature::DBusUint64);
+        for n in 0..10_000_u64 {
+            large.push(DBusUint64::from(n))?;
+        }
+
+        let mut dict = DBusArray::new(SingleCompleteTypeSignature::DBusDictEntry {
+            key: Box::new(SingleCompleteTypeSignature::DBusString),
+            value: Box::new(SingleCompleteTypeSignature::DBusDouble),
+        });
+        dict.push(DBusDictEntry::new(
+            DBusString::from("key"),
+            DBusDouble::from(4.0),
+        ))?;
+
+        let messages = vec![
+            message(call(), vec![]),
+            message(
+                call(),
+                vec![
+                    Type::from(DBusByte::from(1)),
+                    Type::from(DBusStruct::new(vec![
+                        Type::from(DBusInt16::from(2_i16)),
+                        Type::from(DBusVariant::new(DBusString::from("three"))),
+                    ])),
+                    Type::from(dict),
+                ],
+            ),
+            message(
+                call(),
+                vec![Type::from(DBusByte::from(1)), Type::from(large)],
+            ),
+            message(
+                MessageTypeParam::Signal(Signal {
+                    path: DBusObjectPath::from("/"),
+                    interface: DBusString::from("org.example.Interface"),
+                    member: DBusString::from("Changed"),
+                }),
+                vec![Type::from(DBusSignature {
+                    vec: vec![SingleCompleteTypeSignature::DBusBoolean],
+                })],
+            ),
+        ];
+
+        for message in &messages {
+            assert_eq!(message.marshalled_len()?, message.marshal_be()?.len());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn marshal_into_reused_encoder() -> crate::Result<()> {
+        let message = |member, arguments| Message {
+            message_type_param: MessageTypeParam::MethodCall(MethodCall {
+                path: DBusObjectPath::from("/org/example/Object"),
+                interface: None,
+                member: DBusString::from(member),
+            }),
+            body: Body { arguments },
+            ..method_call(3)
+        };
+        let large = message("Large", vec![Type::from(vec![0_u64; 100])]);
+        let small = message("Small", vec![Type::from(DBusByte::from(1))]);
+
+        let mut encoder = Encoder::with_capacity(16);
+        large.marshal_into(&mut encoder, Endianness::BigEndian)?;
+        assert_eq!(encoder.buf, large.marshal_be()?);
+        let capacity = encoder.buf.capacity();
+
+        encoder.reset();
+        assert_eq!(encoder.buf.capacity(), capacity);
+        small.marshal_into(&mut encoder, Endianness::BigEndian)?;
+        assert_eq!(encoder.buf, small.marshal_be()?);
+        assert_eq!(encoder.buf.capacity(), capacity);
+
+        Ok(())
+    }
+
+    #[test]
+    fn marshal_be_into() -> crate::Result<()> {
+        let message = Message {
+            body: Body {
+                arguments: vec![Type::from(DBusString::from("argument"))],
+            },
+            ..method_call(3)
+        };
+
+        // Leftovers of a previous message are cleared.
+        let mut out = vec![0xff; 3];
+        message.marshal_be_into(&mut out)?;
+        assert_eq!(out, message.marshal_be()?);
+
+        // The buffer is large enough, so it is not reallocated.
+        let ptr = out.as_ptr();
+        message.marshal_be_into(&mut out)?;
+        assert_eq!(out, message.marshal_be()?);
+        assert_eq!(out.as_ptr(), ptr);
+
+        Ok(())
+    }
+
+    #[test]
+    fn expects_reply() {
+        let call = method_call(1);
+        assert!(call.expects_reply());
+
+        let no_reply_expected = Message {
+            flag_no_reply_expected: true,
+            ..call
+        };
+        assert!(!no_reply_expected.expects_reply());
+
+        let signal = Message {
+            flag_no_reply_expected: false,
+            message_type_param: MessageTypeParam::Signal(Signal {
+                path: DBusObjectPath::from("/org/example/Object"),
+                interface: DBusString::from("org.example.Interface"),
+                member: DBusString::from("Changed"),
+            }),
+            ..no_reply_expected
+        };
+        assert!(!signal.expects_reply());
+    }
+
+    #[test]
+    fn reply_serial() -> crate::Result<()> {
+        let call = method_call(7);
+        assert_eq!(call.reply_serial(), None);
+
+        let reply = Message {
+            flag_no_reply_expected: true,
+            serial: NonZeroU32::new(1).unwrap(),
+            message_type_param: MessageTypeParam::MethodReturn(MethodReturn {
+                reply_serial: call.serial,
+            }),
+            ..call
+        };
+        let reply = crate::unmarshal(&reply.marshal_be()?)?;
+
+        assert_eq!(reply.reply_serial(), Some(Serial::from(call.serial)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn as_method_call() -> crate::Result<()> {
+        let message = Message {
+            message_type_param: MessageTypeParam::MethodCall(MethodCall {
+                path: DBusObjectPath::from("/org/example/Object"),
+                interface: Some(DBusString::from("org.example.Interface")),
+                member: DBusString::from("Method"),
+            }),
+            destination: Some(DBusString::from("org.example.Service")),
+            sender: Some(DBusString::from(":1.7")),
+            body: Body {
+                arguments: vec![Type::from(DBusUint32::from(42_u32))],
+            },
+            ..method_call(5)
+        };
+        let message = crate::type_system::unmarshal::unmarshal_message(&message.marshal_be()?)?;
+
+        assert_eq!(
+            message.as_method_call(),
+            Some(IncomingMethodCall {
+                path: "/org/example/Object",
+                interface: Some("org.example.Interface"),
+                member: "Method",
+                sender: Some(":1.7"),
+                body: &Body {
+                    arguments: vec![Type::from(DBusUint32::from(42_u32))],
+                },
+            })
+        );
+
+        let reply = Message {
+            message_type_param: MessageTypeParam::MethodReturn(MethodReturn {
+                reply_serial: NonZeroU32::new(5).unwrap(),
+            }),
+            ..message
+        };
+        assert_eq!(reply.as_method_call(), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn marshal_checked_body_signature_mismatch() -> crate::Result<()> {
+        let message = |arguments| Message {
+            message_type_param: MessageTypeParam::MethodReturn(MethodReturn {
+                reply_serial: NonZeroU32::new(1).unwrap(),
+            }),
+            body: Body { arguments },
+            ..method_call(1)
+        };
+
+        // Declared as an array of u32, but holding a string.
+        let mut inconsistent = DBusArray::new(SingleCompleteTypeSignature::DBusUint32);
+        inconsistent
+            .items
+            .push(Type::from(DBusString::from("not a u32")));
+        let inconsistent = message(vec![Type::from(DBusVariant::new(inconsistent))]);
+        assert!(matches!(
+            inconsistent.marshal_checked(Endianness::BigEndian),
+            Err(crate::Error::BodySignatureMismatch {
+                expected: SingleCompleteTypeSignature::DBusUint32,
+                found: SingleCompleteTypeSignature::DBusString,
+            })
+        ));
+
+        let consistent = message(vec![Type::from(vec![1_u32, 2])]);
+        assert_eq!(
+            consistent.marshal_checked(Endianness::BigEndian)?,
+            consistent.marshal_be()?
+        );
+
+        Ok(())
+    }
+
+    /// Header fields may come in any order, and the message must compare equal regardless.
+    #[test]
+    fn round_trip_equality_with_reordered_header_fields() -> crate::Result<()> {
+        use crate::type_system::unmarshal::unmarshal_message;
+
+        let message = Message {
+            message_type_param: MessageTypeParam::MethodCall(MethodCall {
+                path: DBusObjectPath::from("/org/example/Object"),
+                interface: Some(DBusString::from("org.example.Interface")),
+                member: DBusString::from("Method"),
+            }),
+            destination: Some(DBusString::from("org.example.Service")),
+            body: Body {
+                arguments: vec![
+                    Type::from(DBusUint32::from(42_u32)),
+                    Type::from(DBusString::from("hello")),
+                ],
+            },
+            ..method_call(9)
+        };
+
+        for &endianness in &[Endianness::BigEndian, Endianness::LittleEndian] {
+            let marshalled = message.marshal(endianness)?;
+            assert_eq!(unmarshal_message(&marshalled)?, message);
+
+            // The fixed part of the header, with the header fields in reverse order.
+            let mut encoder = Encoder::default();
+            encoder.buf.extend_from_slice(&marshalled[..12]);
+            let mut header_fields = message.header_fields();
+            header_fields.reverse();
+            encoder.marshal_header_fields(&header_fields, endianness)?;
+            encoder.align(8);
+            for arg in &message.body.arguments {
+                encoder.marshal(arg, endianness)?;
+            }
+            let reordered = encoder.finish();
+
+            assert_ne!(reordered, marshalled);
+            assert_eq!(unmarshal_message(&reordered)?, message);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn summary() {
+        let message = Message {
+            message_type_param: MessageTypeParam::MethodCall(MethodCall {
+                path: DBusObjectPath::from("/org/example/Object"),
+                interface: Some(DBusString::from("org.example.Interface")),
+                member: DBusString::from("Method"),
+            }),
+            destination: Some(DBusString::from("org.example.Service")),
+            body: Body {
+                arguments: vec![
+                    Type::from(DBusUint32::from(42_u32)),
+                    Type::from(DBusString::from("hello")),
+                ],
+            },
+            ..method_call(5)
+        };
+
+        assert_eq!(
+            message.summary().to_string(),
+            "MethodCall serial=5 path=/org/example/Object interface=org.example.Interface \
+             member=Method signature=us"
+        );
+    }
+
+    #[test]
+    fn hexdump_format() {
+        let bytes = b"l\x01\x00\x01\x00\x00\x00\x00\x01\x00\x00\x00hello, world";
+
+        assert_eq!(
+            hexdump(bytes),
+            "00000000  6c 01 00 01 00 00 00 00  01 00 00 00 68 65 6c 6c  |l...........hell|\n\
+             00000010  6f 2c 20 77 6f 72 6c 64                           |o, world|\n"
+        );
+        assert_eq!(hexdump(&[]), "");
+    }
 }
@@ -1,8 +1,13 @@
+use std::convert::TryFrom;
+
+use crate::type_system::marshal::Encoder;
+use crate::type_system::marshal::Marshal;
 use crate::type_system::signature::Signature;
 use crate::type_system::signature::SingleCompleteTypeSignature;
 use crate::type_system::types::*;
+use crate::type_system::Endianness;
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, PartialEq)]
 pub struct Body {
     pub arguments: Vec<Type>,
 }
@@ -15,4 +20,276 @@ impl Body {
 
         DBusSignature { vec }
     }
+
+    /// Check that the marshalled body will match its signature.
+    ///
+    /// The signature is computed from the arguments, but the items of an array are public, so an
+    /// array can be built with items that do not match its item type.
+    pub fn check_signature(&self) -> crate::Result<()> {
+        self.arguments.iter().try_for_each(check_contents)
+    }
+
+    /// Marshal the arguments without a message header, aligned as if the body starts at offset 0.
+    ///
+    /// The result is the same as the body portion of a marshalled message.
+    pub fn marshal(&self, endianness: Endianness) -> crate::Result<Vec<u8>> {
+        let mut encoder = Encoder::default();
+        self.marshal_into(&mut encoder, endianness)?;
+        Ok(encoder.finish())
+    }
+
+    /// Marshal the arguments in big endian byte order, without a message header.
+    pub fn marshal_be(&self) -> crate::Result<Vec<u8>> {
+        self.marshal(Endianness::BigEndian)
+    }
+
+    /// Marshal the arguments, starting at an 8-byte boundary of the encoder.
+    pub(crate) fn marshal_into(
+        &self,
+        encoder: &mut Encoder,
+        endianness: Endianness,
+    ) -> crate::Result<()> {
+        debug_assert_eq!(encoder.buf.len() % 8, 0);
+
+        // At least the fixed size arguments are known to fit, without any padding.
+        let capacity: usize = self
+            .arguments
+            .iter()
+            .filter_map(|arg| arg.signature().fixed_marshalled_size())
+            .sum();
+        encoder.buf.reserve(capacity);
+
+        for arg in &self.arguments {
+            encoder.marshal(arg, endianness)?;
+        }
+
+        Ok(())
+    }
+
+    /// Convert the arguments to a tuple of Rust values.
+    ///
+    /// Example: `let (n, name): (u32, String) = reply.body.extract()?;`
+    pub fn extract<T: FromBody>(&self) -> crate::Result<T> {
+        T::from_body(self.arguments.clone())
+    }
+
+    /// Number of arguments.
+    pub fn len(&self) -> usize {
+        self.arguments.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.arguments.is_empty()
+    }
+
+    /// The argument at the given index, if there are that many arguments.
+    pub fn arg(&self, idx: usize) -> Option<&Type> {
+        self.arguments.get(idx)
+    }
+}
+
+/// Check that the contents of containers match their signatures, recursively.
+fn check_contents(value: &Type) -> crate::Result<()> {
+    match value {
+        Type::Array(array) => array.items.iter().try_for_each(|item| {
+            let found = item.signature();
+            if found != array.item_type {
+                return Err(crate::Error::BodySignatureMismatch {
+                    expected: array.item_type.clone(),
+                    found,
+                });
+            }
+            check_contents(item)
+        }),
+        Type::Struct(dbus_struct) => dbus_struct.fields.iter().try_for_each(check_contents),
+        Type::Variant(variant) => check_contents(&variant.variant),
+        Type::DictEntry(dict_entry) => {
+            check_contents(&dict_entry.key)?;
+            check_contents(&dict_entry.value)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Conversion from the arguments of a body, see [Body::extract].
+pub trait FromBody: Sized {
+    fn from_body(arguments: Vec<Type>) -> crate::Result<Self>;
+
+    /// Whether a body with the given signature can be converted to this type.
+    ///
+    /// Accepts any signature by default, leaving the checks to [FromBody::from_body].
+    fn matches_signature(_signature: &DBusSignature) -> bool {
+        true
+    }
+}
+
+impl FromBody for () {
+    fn from_body(arguments: Vec<Type>) -> crate::Result<Self> {
+        match arguments.len() {
+            0 => Ok(()),
+            found => Err(crate::Error::UnexpectedArgumentCount { expected: 0, found }),
+        }
+    }
+
+    fn matches_signature(signature: &DBusSignature) -> bool {
+        signature.vec.is_empty()
+    }
+}
+
+/// Implement [FromBody] for a tuple where each element can be converted from a [Type].
+macro_rules! impl_from_body_for_tuple {
+    ($count:expr; $($name:ident),+) => {
+        impl<$($name),+> FromBody for ($($name,)+)
+        where
+            $($name: TryFrom<Type, Error = crate::Error> + MatchesSignature),+
+        {
+            fn from_body(arguments: Vec<Type>) -> crate::Result<Self> {
+                if arguments.len() != $count {
+                    return Err(crate::Error::UnexpectedArgumentCount {
+                        expected: $count,
+                        found: arguments.len(),
+                    });
+                }
+
+                let mut arguments = arguments.into_iter();
+
+                // The number of arguments was checked above.
+                Ok(($($name::try_from(arguments.next().unwrap())?,)+))
+            }
+
+            fn matches_signature(signature: &DBusSignature) -> bool {
+                let mut fields = signature.vec.iter();
+
+                signature.vec.len() == $count
+                    $(&& fields.next().map_or(false, $name::matches_signature))+
+            }
+        }
+    };
+}
+
+impl_from_body_for_tuple!(1; A);
+impl_from_body_for_tuple!(2; A, B);
+impl_from_body_for_tuple!(3; A, B, C);
+impl_from_body_for_tuple!(4; A, B, C, D);
+impl_from_body_for_tuple!(5; A, B, C, D, E);
+impl_from_body_for_tuple!(6; A, B, C, D, E, F);
+impl_from_body_for_tuple!(7; A, B, C, D, E, F, G);
+impl_from_body_for_tuple!(8; A, B, C, D, E, F, G, H);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message_protocol::tests::method_call;
+    use crate::message_protocol::Message;
+
+    #[test]
+    fn arguments() {
+        let body = Body {
+            arguments: vec![
+                Type::from(DBusUint32::from(42_u32)),
+                Type::from(DBusString::from("name")),
+                Type::from(DBusBoolean::from(true)),
+            ],
+        };
+
+        assert_eq!(body.len(), 3);
+        assert!(!body.is_empty());
+        assert_eq!(body.arg(1), Some(&Type::from(DBusString::from("name"))));
+        assert_eq!(body.arg(3), None);
+        assert!(Body::default().is_empty());
+    }
+
+    #[test]
+    fn marshal_be() -> crate::Result<()> {
+        let body = Body {
+            arguments: vec![
+                Type::from(DBusByte::from(1)),
+                Type::from(DBusString::from("ab")),
+            ],
+        };
+
+        #[rustfmt::skip]
+        let expected: &[u8] = &[
+            // Byte, then padding to the 4-byte boundary of the string length
+            1, 0, 0, 0,
+            // String
+            0, 0, 0, 2, b'a', b'b', 0,
+        ];
+        assert_eq!(body.marshal_be()?, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn marshal_matches_message_body() -> crate::Result<()> {
+        let body = Body {
+            arguments: vec![
+                Type::from(DBusUint32::from(7_u32)),
+                Type::from(DBusString::from("name")),
+            ],
+        };
+        let marshalled_body = body.marshal(Endianness::BigEndian)?;
+
+        let message = Message {
+            body,
+            ..method_call(1)
+        };
+        let marshalled_message = message.marshal_be()?;
+
+        // The body is at the end of the message, its length is in bytes 4..8 of the header.
+        let body_len = u32::from_be_bytes([
+            marshalled_message[4],
+            marshalled_message[5],
+            marshalled_message[6],
+            marshalled_message[7],
+        ]) as usize;
+        assert_eq!(
+            &marshalled_message[marshalled_message.len() - body_len..],
+            marshalled_body.as_slice()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn extract_pair() -> crate::Result<()> {
+        let body = Body {
+            arguments: vec![
+                Type::from(DBusUint32::from(42_u32)),
+                Type::from(DBusString::from("name")),
+            ],
+        };
+
+        let (n, name): (u32, String) = body.extract()?;
+        assert_eq!(n, 42);
+        assert_eq!(name, "name");
+
+        Ok(())
+    }
+
+    #[test]
+    fn extract_wrong_arity() {
+        let body = Body {
+            arguments: vec![Type::from(DBusUint32::from(42_u32))],
+        };
+
+        let result: crate::Result<(u32, String)> = body.extract();
+        assert!(matches!(
+            result,
+            Err(crate::Error::UnexpectedArgumentCount {
+                expected: 2,
+                found: 1
+            })
+        ));
+    }
+
+    #[test]
+    fn extract_wrong_type() {
+        let body = Body {
+            arguments: vec![Type::from(DBusString::from("name"))],
+        };
+
+        let result: crate::Result<(u32,)> = body.extract();
+        assert!(matches!(result, Err(crate::Error::UnexpectedType { .. })));
+    }
 }
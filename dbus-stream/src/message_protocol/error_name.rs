@@ -0,0 +1,72 @@
+//! Well-known error names, for use in error replies.
+//!
+//! Spec: https://dbus.freedesktop.org/doc/dbus-specification.html#message-protocol-names-error
+
+/// Standard errors defined by the message bus, in the `org.freedesktop.DBus.Error` namespace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorName {
+    /// The method does not exist on the interface.
+    UnknownMethod,
+
+    /// The interface does not exist on the object.
+    UnknownInterface,
+
+    /// The object does not exist.
+    UnknownObject,
+
+    /// The arguments do not match the signature of the method.
+    InvalidArgs,
+
+    /// Generic failure.
+    Failed,
+
+    /// The operation is not supported.
+    NotSupported,
+}
+
+impl ErrorName {
+    /// The full error name, for example `org.freedesktop.DBus.Error.UnknownMethod`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::UnknownMethod => "org.freedesktop.DBus.Error.UnknownMethod",
+            Self::UnknownInterface => "org.freedesktop.DBus.Error.UnknownInterface",
+            Self::UnknownObject => "org.freedesktop.DBus.Error.UnknownObject",
+            Self::InvalidArgs => "org.freedesktop.DBus.Error.InvalidArgs",
+            Self::Failed => "org.freedesktop.DBus.Error.Failed",
+            Self::NotSupported => "org.freedesktop.DBus.Error.NotSupported",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_names() {
+        assert_eq!(
+            ErrorName::UnknownMethod.as_str(),
+            "org.freedesktop.DBus.Error.UnknownMethod"
+        );
+        assert_eq!(
+            ErrorName::UnknownInterface.as_str(),
+            "org.freedesktop.DBus.Error.UnknownInterface"
+        );
+        assert_eq!(
+            ErrorName::UnknownObject.as_str(),
+            "org.freedesktop.DBus.Error.UnknownObject"
+        );
+        assert_eq!(
+            ErrorName::InvalidArgs.as_str(),
+            "org.freedesktop.DBus.Error.InvalidArgs"
+        );
+        assert_eq!(
+            ErrorName::Failed.as_str(),
+            "org.freedesktop.DBus.Error.Failed"
+        );
+        assert_eq!(
+            ErrorName::NotSupported.as_str(),
+            "org.freedesktop.DBus.Error.NotSupported"
+        );
+    }
+}
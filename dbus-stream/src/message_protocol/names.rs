@@ -0,0 +1,182 @@
+//! Validation of names and object paths.
+//!
+//! Spec: https://dbus.freedesktop.org/doc/dbus-specification.html#message-protocol-names
+
+use std::fmt;
+
+/// Names must not exceed the maximum name length of 255 bytes.
+const MAX_NAME_LENGTH: usize = 255;
+
+/// An element of an interface name or a member name: `[A-Za-z_][A-Za-z0-9_]*`.
+fn is_valid_name_element(element: &str) -> bool {
+    let mut chars = element.chars();
+
+    match chars.next() {
+        Some(first) if first.is_ascii_alphabetic() || first == '_' => {}
+        _ => return false,
+    }
+
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Object paths start with `/`, followed by zero or more elements of `[A-Za-z0-9_]+` separated
+/// by `/`. Only the root path `/` may end with `/`.
+pub(crate) fn validate_object_path(path: &str) -> crate::Result<()> {
+    let invalid = || crate::Error::InvalidObjectPath(path.to_string());
+
+    if path == "/" {
+        return Ok(());
+    }
+
+    let elements = path.strip_prefix('/').ok_or_else(invalid)?;
+    let valid = elements.split('/').all(|element| {
+        !element.is_empty()
+            && element
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_')
+    });
+
+    if valid {
+        Ok(())
+    } else {
+        Err(invalid())
+    }
+}
+
+/// Interface names consist of two or more elements separated by `.`.
+pub(crate) fn validate_interface_name(interface: &str) -> crate::Result<()> {
+    let valid = interface.len() <= MAX_NAME_LENGTH
+        && interface.split('.').count() >= 2
+        && interface.split('.').all(is_valid_name_element);
+
+    if valid {
+        Ok(())
+    } else {
+        Err(crate::Error::InvalidInterfaceName(interface.to_string()))
+    }
+}
+
+/// Member names consist of a single element.
+pub(crate) fn validate_member_name(member: &str) -> crate::Result<()> {
+    if member.len() <= MAX_NAME_LENGTH && is_valid_name_element(member) {
+        Ok(())
+    } else {
+        Err(crate::Error::InvalidMemberName(member.to_string()))
+    }
+}
+
+/// A bus name, to address a connection for example as the destination of a method call.
+///
+/// Either a unique name like `:1.23`, or a well-known name like `org.freedesktop.DBus`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BusName(String);
+
+impl BusName {
+    /// Bus names consist of two or more elements of `[A-Za-z0-9_-]+` separated by `.`. Unique
+    /// names start with `:`, and elements of well-known names must not start with a digit.
+    pub fn new(name: &str) -> crate::Result<Self> {
+        let (elements, unique) = match name.strip_prefix(':') {
+            Some(elements) => (elements, true),
+            None => (name, false),
+        };
+
+        let valid_element = |element: &str| {
+            let valid_first = match element.chars().next() {
+                Some(first) => unique || !first.is_ascii_digit(),
+                None => false,
+            };
+
+            valid_first
+                && element
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        };
+
+        let valid = name.len() <= MAX_NAME_LENGTH
+            && elements.split('.').count() >= 2
+            && elements.split('.').all(valid_element);
+
+        if valid {
+            Ok(Self(name.to_string()))
+        } else {
+            Err(crate::Error::InvalidBusName(name.to_string()))
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Whether this is the unique name of a connection, as opposed to a well-known name.
+    pub fn is_unique(&self) -> bool {
+        self.0.starts_with(':')
+    }
+}
+
+impl fmt::Display for BusName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn object_paths() {
+        assert!(validate_object_path("/").is_ok());
+        assert!(validate_object_path("/org/freedesktop/DBus").is_ok());
+        assert!(validate_object_path("/a_b/c1").is_ok());
+
+        assert!(validate_object_path("").is_err());
+        assert!(validate_object_path("org/freedesktop").is_err());
+        assert!(validate_object_path("/org/").is_err());
+        assert!(validate_object_path("/org//freedesktop").is_err());
+        assert!(validate_object_path("/org.freedesktop").is_err());
+    }
+
+    #[test]
+    fn interface_names() {
+        assert!(validate_interface_name("org.freedesktop.DBus").is_ok());
+        assert!(validate_interface_name("a._b").is_ok());
+
+        assert!(validate_interface_name("org").is_err());
+        assert!(validate_interface_name("org..DBus").is_err());
+        assert!(validate_interface_name("org.1freedesktop").is_err());
+        assert!(validate_interface_name("org.free-desktop").is_err());
+    }
+
+    #[test]
+    fn bus_names() -> crate::Result<()> {
+        let well_known = BusName::new("org.freedesktop.DBus")?;
+        assert!(!well_known.is_unique());
+        assert_eq!(
+            BusName::new("org.example-service._1")?.as_str(),
+            "org.example-service._1"
+        );
+
+        let unique = BusName::new(":1.23")?;
+        assert!(unique.is_unique());
+
+        assert!(BusName::new("").is_err());
+        assert!(BusName::new("org").is_err());
+        assert!(BusName::new("org..DBus").is_err());
+        assert!(BusName::new(":1.").is_err());
+        assert!(BusName::new("org.1example").is_err());
+        assert!(BusName::new("org.free desktop").is_err());
+        assert!(BusName::new(&format!("org.{}", "a".repeat(252))).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn member_names() {
+        assert!(validate_member_name("Hello").is_ok());
+        assert!(validate_member_name("_private1").is_ok());
+
+        assert!(validate_member_name("").is_err());
+        assert!(validate_member_name("1Hello").is_err());
+        assert!(validate_member_name("org.Hello").is_err());
+    }
+}
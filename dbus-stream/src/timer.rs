@@ -0,0 +1,41 @@
+//! Timers for timeouts, so that they do not depend on a particular async runtime.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+/// Future that completes when a timer fires.
+pub type Sleep = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Timer used for timeouts on a [crate::Connection].
+///
+/// The connection uses [SmolTimer] unless another timer is set with
+/// [crate::Connection::set_timer].
+pub trait Timer {
+    /// Return a future that completes after the given duration, like `async fn sleep(Duration)`.
+    fn sleep(&self, duration: Duration) -> Sleep;
+}
+
+/// Timer of the smol runtime.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SmolTimer;
+
+impl Timer for SmolTimer {
+    fn sleep(&self, duration: Duration) -> Sleep {
+        Box::pin(async move {
+            smol::Timer::after(duration).await;
+        })
+    }
+}
+
+/// Timer of the tokio runtime, which must be running when the timer is used.
+#[cfg(feature = "tokio")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokioTimer;
+
+#[cfg(feature = "tokio")]
+impl Timer for TokioTimer {
+    fn sleep(&self, duration: Duration) -> Sleep {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
@@ -1,10 +1,29 @@
 use crate::type_system::types::*;
 
+/// Check an invariant that would otherwise lead to marshalling corrupt data.
+///
+/// This is a debug assertion, unless the `strict` feature is enabled, in which case the
+/// enclosing function returns [crate::Error::InvariantViolation].
+macro_rules! check_invariant {
+    ($condition:expr, $message:literal) => {
+        if cfg!(feature = "strict") {
+            if !$condition {
+                return Err(crate::Error::InvariantViolation($message));
+            }
+        } else {
+            debug_assert!($condition, $message);
+        }
+    };
+}
+
 pub mod marshal;
 pub mod signature;
 pub mod types;
 pub mod unmarshal;
 
+#[cfg(test)]
+mod round_trip;
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 pub enum Endianness {
     BigEndian,
@@ -1,7 +1,9 @@
 use std::convert::TryFrom;
 
 use crate::type_system::signature::Signature;
+use crate::type_system::signature::SingleCompleteTypeSignature;
 use crate::type_system::types::*;
+use crate::type_system::Endianness;
 
 #[derive(Debug, Default)]
 pub(crate) struct Encoder {
@@ -9,10 +11,21 @@ pub(crate) struct Encoder {
 }
 
 pub(crate) trait Marshal<T> {
-    fn marshal_be(&mut self, t: &T) -> crate::Result<()>;
+    fn marshal(&mut self, t: &T, endianness: Endianness) -> crate::Result<()>;
 }
 
 impl Encoder {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            buf: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Clear the buffer, keeping its capacity so it can be reused.
+    pub fn reset(&mut self) {
+        self.buf.clear();
+    }
+
     pub fn finish(self) -> Vec<u8> {
         self.buf
     }
@@ -24,15 +37,13 @@ impl Encoder {
             "I don't expect I'll need this method for other alignments"
         );
 
-        while self.buf.len() % alignment != 0 {
+        while !self.buf.len().is_multiple_of(alignment) {
             self.buf.push(0);
         }
     }
 
     pub fn extend_from_array<const N: usize>(&mut self, array: [u8; N]) {
-        // Note: this way of converting the array to an iterator might be deprecated in the future, after [IntoIterator is implemented for arrays](https://github.com/rust-lang/rust/pull/65819).
-        let iter = std::array::IntoIter::new(array);
-        self.buf.extend(iter);
+        self.buf.extend_from_slice(&array);
     }
 
     /// Reserve N bytes and return a closure that can be called to set the bytes later.
@@ -44,135 +55,173 @@ impl Encoder {
     /// The closure when called, will overwrite these same bytes.
     ///
     /// This is intended to help length-value encoding, when the length isn't known up front.
+    ///
+    /// The closure panics if the buffer no longer contains the reserved bytes, for example
+    /// because it was reset in between.
     pub fn reserve_n_bytes<'a, 'b, const N: usize>(
         &'a mut self,
-    ) -> impl FnOnce(&'b mut Encoder, [u8; N]) {
+    ) -> impl FnOnce(&'b mut Encoder, [u8; N]) -> crate::Result<()> {
         let idx = self.buf.len();
 
         self.buf.extend_from_slice(&[0; N]);
 
         let closure = move |encoder: &mut Encoder, values: [u8; N]| {
-            let new_values_iter = std::array::IntoIter::new(values);
-            let range = idx..idx + N;
-
-            // Replace:
-            for old_value in encoder.buf.splice(range, new_values_iter) {
-                // These are the values being evicted from the vec.
-                // These should be zero, that's what we set them to above.
-                debug_assert_eq!(old_value, 0);
-            }
+            let len = encoder.buf.len();
+            let reserved = encoder
+                .buf
+                .get_mut(idx..)
+                .and_then(|rest| rest.get_mut(..N))
+                .unwrap_or_else(|| {
+                    panic!(
+                        "Reserved bytes {}..{} are missing from buffer of length {}",
+                        idx,
+                        idx.saturating_add(N),
+                        len
+                    )
+                });
+
+            // These should be zero, that's what we set them to above.
+            check_invariant!(
+                reserved.iter().all(|&byte| byte == 0),
+                "Reserved bytes were overwritten"
+            );
+
+            reserved.copy_from_slice(&values);
+            Ok(())
         };
 
         closure
     }
 }
 
+/// Offset at which marshalling ends, when starting at the given offset.
+///
+/// Follows the same alignment rules as [Encoder], but without marshalling anything.
+pub(crate) fn marshalled_end(t: &Type, offset: usize) -> usize {
+    let align = |offset: usize, alignment: usize| offset.div_ceil(alignment) * alignment;
+
+    match t {
+        Type::Byte(_) => offset + 1,
+        Type::Int16(_) | Type::Uint16(_) => align(offset, 2) + 2,
+        Type::Boolean(_) | Type::Int32(_) | Type::Uint32(_) | Type::UnixFileDescriptor(_) => {
+            align(offset, 4) + 4
+        }
+        Type::Int64(_) | Type::Uint64(_) | Type::Double(_) => align(offset, 8) + 8,
+        // Length, the string, and the terminating null byte.
+        Type::String(inner) => align(offset, 4) + 4 + inner.string.len() + 1,
+        Type::ObjectPath(inner) => align(offset, 4) + 4 + inner.dbus_string.string.len() + 1,
+        // Length byte, the signature, and the terminating null byte.
+        Type::Signature(inner) => {
+            let length: usize = inner.vec.iter().map(|sig| sig.serialize().len()).sum();
+            offset + 1 + length + 1
+        }
+        Type::Array(inner) => {
+            let offset_first_item =
+                align(align(offset, 4) + 4, inner.item_type.marshalling_boundary());
+            inner.items.iter().fold(offset_first_item, |offset, item| {
+                marshalled_end(item, offset)
+            })
+        }
+        Type::Struct(inner) => inner.fields.iter().fold(align(offset, 8), |offset, field| {
+            marshalled_end(field, offset)
+        }),
+        Type::Variant(inner) => {
+            let length = inner.variant.signature().serialize().len();
+            marshalled_end(&inner.variant, offset + 1 + length + 1)
+        }
+        Type::DictEntry(inner) => {
+            marshalled_end(&inner.value, marshalled_end(&inner.key, align(offset, 8)))
+        }
+    }
+}
+
 impl Marshal<Type> for Encoder {
-    fn marshal_be(&mut self, t: &Type) -> crate::Result<()> {
+    fn marshal(&mut self, t: &Type, endianness: Endianness) -> crate::Result<()> {
         match t {
-            Type::Byte(inner) => self.marshal_be(inner),
-            Type::Boolean(inner) => self.marshal_be(inner),
-            Type::Int16(inner) => self.marshal_be(inner),
-            Type::Uint16(inner) => self.marshal_be(inner),
-            Type::Int32(inner) => self.marshal_be(inner),
-            Type::Uint32(inner) => self.marshal_be(inner),
-            Type::Int64(inner) => self.marshal_be(inner),
-            Type::Uint64(inner) => self.marshal_be(inner),
-            Type::Double(inner) => self.marshal_be(inner),
-            Type::String(inner) => self.marshal_be(inner),
-            Type::ObjectPath(inner) => self.marshal_be(inner),
-            Type::Signature(inner) => self.marshal_be(inner),
-            Type::UnixFileDescriptor(inner) => self.marshal_be(inner),
-            Type::Array(inner) => self.marshal_be(inner),
-            Type::Struct(inner) => self.marshal_be(inner),
-            Type::Variant(inner) => self.marshal_be(inner),
-            Type::DictEntry(inner) => self.marshal_be(inner),
+            Type::Byte(inner) => self.marshal(inner, endianness),
+            Type::Boolean(inner) => self.marshal(inner, endianness),
+            Type::Int16(inner) => self.marshal(inner, endianness),
+            Type::Uint16(inner) => self.marshal(inner, endianness),
+            Type::Int32(inner) => self.marshal(inner, endianness),
+            Type::Uint32(inner) => self.marshal(inner, endianness),
+            Type::Int64(inner) => self.marshal(inner, endianness),
+            Type::Uint64(inner) => self.marshal(inner, endianness),
+            Type::Double(inner) => self.marshal(inner, endianness),
+            Type::String(inner) => self.marshal(inner, endianness),
+            Type::ObjectPath(inner) => self.marshal(inner, endianness),
+            Type::Signature(inner) => self.marshal(inner, endianness),
+            Type::UnixFileDescriptor(inner) => self.marshal(inner, endianness),
+            Type::Array(inner) => self.marshal(inner, endianness),
+            Type::Struct(inner) => self.marshal(inner, endianness),
+            Type::Variant(inner) => self.marshal(inner, endianness),
+            Type::DictEntry(inner) => self.marshal(inner, endianness),
         }
     }
 }
 
 impl Marshal<DBusByte> for Encoder {
-    fn marshal_be(&mut self, t: &DBusByte) -> crate::Result<()> {
+    fn marshal(&mut self, t: &DBusByte, _endianness: Endianness) -> crate::Result<()> {
         self.buf.push(t.u8);
         Ok(())
     }
 }
 
 impl Marshal<DBusBoolean> for Encoder {
-    fn marshal_be(&mut self, t: &DBusBoolean) -> crate::Result<()> {
+    fn marshal(&mut self, t: &DBusBoolean, endianness: Endianness) -> crate::Result<()> {
         let value: u32 = if t.bool { 1 } else { 0 };
-        self.marshal_be(&DBusUint32 { u32: value })
-    }
-}
-
-impl Marshal<DBusInt16> for Encoder {
-    fn marshal_be(&mut self, t: &DBusInt16) -> crate::Result<()> {
-        self.align(2);
-        self.extend_from_array(t.i16.to_be_bytes());
-        Ok(())
+        self.marshal(&DBusUint32 { u32: value }, endianness)
     }
 }
 
-impl Marshal<DBusUint16> for Encoder {
-    fn marshal_be(&mut self, t: &DBusUint16) -> crate::Result<()> {
-        self.align(2);
-        self.extend_from_array(t.u16.to_be_bytes());
-        Ok(())
-    }
-}
-
-impl Marshal<DBusInt32> for Encoder {
-    fn marshal_be(&mut self, t: &DBusInt32) -> crate::Result<()> {
-        self.align(4);
-        self.extend_from_array(t.i32.to_be_bytes());
-        Ok(())
-    }
-}
-
-impl Marshal<DBusUint32> for Encoder {
-    fn marshal_be(&mut self, t: &DBusUint32) -> crate::Result<()> {
-        self.align(4);
-        self.extend_from_array(t.u32.to_be_bytes());
-        Ok(())
-    }
-}
-
-impl Marshal<DBusInt64> for Encoder {
-    fn marshal_be(&mut self, t: &DBusInt64) -> crate::Result<()> {
-        self.align(8);
-        self.extend_from_array(t.i64.to_be_bytes());
-        Ok(())
-    }
-}
-
-impl Marshal<DBusUint64> for Encoder {
-    fn marshal_be(&mut self, t: &DBusUint64) -> crate::Result<()> {
-        self.align(8);
-        self.extend_from_array(t.u64.to_be_bytes());
-        Ok(())
-    }
+/// Implement [Marshal] for the fixed size numeric types, which are aligned to their own size
+/// and written in the byte order given by the [Endianness].
+macro_rules! impl_marshal_fixed {
+    ($name:ident, $field_name:ident, $alignment:expr) => {
+        impl Marshal<$name> for Encoder {
+            fn marshal(&mut self, t: &$name, endianness: Endianness) -> crate::Result<()> {
+                self.align($alignment);
+                match endianness {
+                    Endianness::BigEndian => self.extend_from_array(t.$field_name.to_be_bytes()),
+                    Endianness::LittleEndian => self.extend_from_array(t.$field_name.to_le_bytes()),
+                }
+                Ok(())
+            }
+        }
+    };
 }
 
-impl Marshal<DBusDouble> for Encoder {
-    fn marshal_be(&mut self, t: &DBusDouble) -> crate::Result<()> {
-        self.align(8);
-        self.extend_from_array(t.f64.to_be_bytes());
-        Ok(())
+impl_marshal_fixed!(DBusInt16, i16, 2);
+impl_marshal_fixed!(DBusUint16, u16, 2);
+impl_marshal_fixed!(DBusInt32, i32, 4);
+impl_marshal_fixed!(DBusUint32, u32, 4);
+impl_marshal_fixed!(DBusInt64, i64, 8);
+impl_marshal_fixed!(DBusUint64, u64, 8);
+impl_marshal_fixed!(DBusDouble, f64, 8);
+impl_marshal_fixed!(DBusUnixFileDescriptor, u32, 4);
+
+/// Byte representation of a u32 in the given [Endianness].
+fn u32_to_bytes(value: u32, endianness: Endianness) -> [u8; 4] {
+    match endianness {
+        Endianness::BigEndian => value.to_be_bytes(),
+        Endianness::LittleEndian => value.to_le_bytes(),
     }
 }
 
-impl Marshal<DBusString> for Encoder {
-    fn marshal_be(&mut self, t: &DBusString) -> crate::Result<()> {
-        self.align(4);
+/// Marshal a borrowed string the same way as [DBusString], without allocating one.
+impl Marshal<&str> for Encoder {
+    fn marshal(&mut self, t: &&str, endianness: Endianness) -> crate::Result<()> {
+        // The string is terminated by a null byte, so it can not contain one.
+        if t.contains('\0') {
+            return Err(crate::Error::StringContainsNul(t.to_string()));
+        }
 
         // Length of string (in bytes):
-        let length: usize = t.string.len();
+        let length: usize = t.len();
         let length: u32 = u32::try_from(length)?;
-        let length: [u8; 4] = length.to_be_bytes();
-        self.extend_from_array(length);
+        self.marshal(&DBusUint32::from(length), endianness)?;
 
         // The Rust string is UTF-8, and DBus uses UTF-8 for its strings too.
-        self.buf.extend(t.string.bytes());
+        self.buf.extend(t.bytes());
 
         // Terminating null byte.
         self.buf.push(0x00);
@@ -181,29 +230,40 @@ impl Marshal<DBusString> for Encoder {
     }
 }
 
+impl Marshal<DBusString> for Encoder {
+    fn marshal(&mut self, t: &DBusString, endianness: Endianness) -> crate::Result<()> {
+        self.marshal(&t.string.as_str(), endianness)
+    }
+}
+
 impl Marshal<DBusObjectPath> for Encoder {
-    fn marshal_be(&mut self, t: &DBusObjectPath) -> crate::Result<()> {
+    fn marshal(&mut self, t: &DBusObjectPath, endianness: Endianness) -> crate::Result<()> {
+        // The fields are public, so the path may not have been validated when it was built.
+        crate::message_protocol::names::validate_object_path(&t.dbus_string.string)?;
+
         // Marshalls the same way as DBusString.
-        self.marshal_be(&t.dbus_string)
+        self.marshal(&t.dbus_string, endianness)
     }
 }
 
 impl Marshal<DBusSignature> for Encoder {
-    fn marshal_be(&mut self, t: &DBusSignature) -> crate::Result<()> {
-        // Reserve 1 byte for the length. We don't know the exact length yet.
-        let specify_length = self.reserve_n_bytes::<1>();
+    fn marshal(&mut self, t: &DBusSignature, _endianness: Endianness) -> crate::Result<()> {
+        t.vec
+            .iter()
+            .try_for_each(SingleCompleteTypeSignature::validate)?;
 
-        // Mark the offset, so we know where the items start.
-        let offset_first_item = self.buf.len();
+        let signature: Vec<u8> = t
+            .vec
+            .iter()
+            .flat_map(|single_complete_type_signature| single_complete_type_signature.serialize())
+            .collect();
 
-        // Write the marshalled single complete type signatures into the buffer.
-        for single_complete_type_signature in &t.vec {
-            self.buf.extend(single_complete_type_signature.serialize());
-        }
+        // The length of a signature is limited to 255 bytes, since it is specified in a single byte.
+        let length = u8::try_from(signature.len())
+            .map_err(|_| crate::Error::SignatureTooLong(signature.len()))?;
 
-        // Check what the length is
-        let length = u8::try_from(self.buf.len() - offset_first_item)?;
-        specify_length(self, length.to_be_bytes());
+        self.buf.push(length);
+        self.buf.extend(signature);
 
         // Terminating null byte.
         self.buf.push(0x00);
@@ -212,29 +272,42 @@ impl Marshal<DBusSignature> for Encoder {
     }
 }
 
-impl Marshal<DBusUnixFileDescriptor> for Encoder {
-    fn marshal_be(&mut self, t: &DBusUnixFileDescriptor) -> crate::Result<()> {
-        todo!()
+impl Encoder {
+    /// Marshal a signature that is already serialized, for example `a{sv}`, without parsing it
+    /// into a [DBusSignature] first.
+    ///
+    /// The signature is still checked to be a sequence of single complete types.
+    pub fn marshal_raw_signature(&mut self, sig_bytes: &[u8]) -> crate::Result<()> {
+        DBusSignature::new_from_bytes(sig_bytes)?;
+
+        // The length fits in a byte, since it was checked when parsing.
+        self.buf.push(sig_bytes.len() as u8);
+        self.buf.extend_from_slice(sig_bytes);
+
+        // Terminating null byte.
+        self.buf.push(0x00);
+
+        Ok(())
     }
 }
 
 impl Marshal<DBusVariant> for Encoder {
-    fn marshal_be(&mut self, t: &DBusVariant) -> crate::Result<()> {
+    fn marshal(&mut self, t: &DBusVariant, endianness: Endianness) -> crate::Result<()> {
         // Single Complete Type signature of variant value
         let sig = t.variant.signature();
         let dbus_signature = DBusSignature { vec: vec![sig] };
 
         // Variant signature
-        self.marshal_be(&dbus_signature)?;
+        self.marshal(&dbus_signature, endianness)?;
         // Variant inner type
-        self.marshal_be(&*t.variant)?;
+        self.marshal(&*t.variant, endianness)?;
 
         Ok(())
     }
 }
 
 impl Marshal<DBusArray> for Encoder {
-    fn marshal_be(&mut self, t: &DBusArray) -> crate::Result<()> {
+    fn marshal(&mut self, t: &DBusArray, endianness: Endianness) -> crate::Result<()> {
         // The DBus array is length-value encoded, and the length is 4 aligned:
         self.align(4);
 
@@ -249,24 +322,27 @@ impl Marshal<DBusArray> for Encoder {
 
         // Marshal the items.
         for item in &t.items {
-            debug_assert_eq!(item.signature(), t.item_type, "Sanity check");
-            self.marshal_be(item)?;
+            check_invariant!(
+                item.has_signature(&t.item_type),
+                "Array item does not match the item type"
+            );
+            self.marshal(item, endianness)?;
         }
 
         let array_data_length = u32::try_from(self.buf.len() - offset_first_item)?;
-        specify_length(self, array_data_length.to_be_bytes());
+        specify_length(self, u32_to_bytes(array_data_length, endianness))?;
 
         Ok(())
     }
 }
 
 impl Marshal<DBusStruct> for Encoder {
-    fn marshal_be(&mut self, t: &DBusStruct) -> crate::Result<()> {
+    fn marshal(&mut self, t: &DBusStruct, endianness: Endianness) -> crate::Result<()> {
         // Struct starts on 8-byte boundary regardless of the type of its fields.
         self.align(8);
 
         for field in &t.fields {
-            self.marshal_be(field)?;
+            self.marshal(field, endianness)?;
         }
 
         Ok(())
@@ -274,7 +350,257 @@ impl Marshal<DBusStruct> for Encoder {
 }
 
 impl Marshal<DBusDictEntry> for Encoder {
-    fn marshal_be(&mut self, t: &DBusDictEntry) -> crate::Result<()> {
-        todo!()
+    fn marshal(&mut self, t: &DBusDictEntry, endianness: Endianness) -> crate::Result<()> {
+        // Dict entries are marshalled like structs.
+        self.align(8);
+
+        self.marshal(&*t.key, endianness)?;
+        self.marshal(&*t.value, endianness)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::type_system::signature::SingleCompleteTypeSignature;
+
+    #[test]
+    fn marshal_struct_in_both_byte_orders() -> crate::Result<()> {
+        let dbus_struct = DBusStruct::new(vec![
+            Type::from(DBusByte::from(7)),
+            Type::from(DBusUint32::from(0x01020304_u32)),
+        ]);
+
+        let mut big = Encoder::default();
+        big.marshal(&dbus_struct, Endianness::BigEndian)?;
+        let big = big.finish();
+
+        let mut little = Encoder::default();
+        little.marshal(&dbus_struct, Endianness::LittleEndian)?;
+        let little = little.finish();
+
+        // Byte, then 3 bytes of padding to reach the 4-byte boundary of the u32.
+        assert_eq!(big, &[7, 0, 0, 0, 1, 2, 3, 4]);
+        assert_eq!(little, &[7, 0, 0, 0, 4, 3, 2, 1]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn marshal_empty_array_of_u64() -> crate::Result<()> {
+        let array = DBusArray::new(SingleCompleteTypeSignature::DBusUint64);
+
+        let mut encoder = Encoder::default();
+        encoder.marshal(&array, Endianness::BigEndian)?;
+
+        // Length is zero, but the padding to the 8-byte boundary of the item type is still
+        // present.
+        assert_eq!(encoder.finish(), &[0, 0, 0, 0, 0, 0, 0, 0]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn marshal_empty_array_of_u64_after_byte() -> crate::Result<()> {
+        let array = DBusArray::new(SingleCompleteTypeSignature::DBusUint64);
+
+        let mut encoder = Encoder::default();
+        encoder.marshal(&DBusByte::from(1), Endianness::BigEndian)?;
+        encoder.marshal(&array, Endianness::BigEndian)?;
+
+        // Byte, padding to the 4-byte boundary of the length, and the length itself.
+        // The items would start at offset 8, which is already 8-aligned.
+        assert_eq!(encoder.finish(), &[1, 0, 0, 0, 0, 0, 0, 0]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn marshal_signature_with_bare_dict_entry() {
+        let signature = DBusSignature {
+            vec: vec![SingleCompleteTypeSignature::DBusStruct {
+                fields: vec![SingleCompleteTypeSignature::DBusDictEntry {
+                    key: Box::new(SingleCompleteTypeSignature::DBusString),
+                    value: Box::new(SingleCompleteTypeSignature::DBusVariant),
+                }],
+            }],
+        };
+        let mut encoder = Encoder::default();
+
+        let result = encoder.marshal(&signature, Endianness::BigEndian);
+
+        assert!(matches!(result, Err(crate::Error::DictEntryOutsideArray)));
+    }
+
+    #[test]
+    fn marshal_signature_too_long() {
+        let signature = DBusSignature {
+            vec: vec![SingleCompleteTypeSignature::DBusByte; 256],
+        };
+
+        let mut encoder = Encoder::default();
+        let result = encoder.marshal(&signature, Endianness::BigEndian);
+
+        assert!(matches!(result, Err(crate::Error::SignatureTooLong(256))));
+    }
+
+    #[test]
+    fn reserve_n_bytes() -> crate::Result<()> {
+        let mut encoder = Encoder::default();
+        encoder.extend_from_array([1, 2]);
+        let set_length = encoder.reserve_n_bytes::<4>();
+        encoder.extend_from_array([3, 4, 5]);
+
+        set_length(&mut encoder, [6, 7, 8, 9])?;
+
+        assert_eq!(encoder.buf, [1, 2, 6, 7, 8, 9, 3, 4, 5]);
+
+        Ok(())
+    }
+
+    /// Rejected with and without the strict feature, since the message bus would reject it.
+    #[test]
+    fn marshal_dict_entry_key_not_basic() {
+        let signature = DBusSignature {
+            vec: vec![SingleCompleteTypeSignature::DBusArray(Box::new(
+                SingleCompleteTypeSignature::DBusDictEntry {
+                    key: Box::new(SingleCompleteTypeSignature::DBusStruct {
+                        fields: vec![SingleCompleteTypeSignature::DBusByte],
+                    }),
+                    value: Box::new(SingleCompleteTypeSignature::DBusString),
+                },
+            ))],
+        };
+        let mut encoder = Encoder::default();
+
+        let result = encoder.marshal(&signature, Endianness::BigEndian);
+
+        assert!(matches!(
+            result,
+            Err(crate::Error::DictEntryKeyNotBasic(
+                SingleCompleteTypeSignature::DBusStruct { .. }
+            ))
+        ));
+    }
+
+    #[cfg(feature = "strict")]
+    #[test]
+    fn marshal_heterogeneous_array_strict() {
+        let array = DBusArray {
+            item_type: SingleCompleteTypeSignature::DBusUint32,
+            items: vec![
+                Type::from(DBusUint32::from(1_u32)),
+                Type::from(DBusString::from("two")),
+            ],
+        };
+        let mut encoder = Encoder::default();
+
+        let result = encoder.marshal(&array, Endianness::BigEndian);
+
+        assert!(matches!(result, Err(crate::Error::InvariantViolation(_))));
+    }
+
+    #[test]
+    fn marshal_raw_signature() -> crate::Result<()> {
+        let mut raw = Encoder::default();
+        raw.marshal_raw_signature(b"a{sv}")?;
+
+        let mut parsed = Encoder::default();
+        parsed.marshal(
+            &DBusSignature::new_from_signature("a{sv}")?,
+            Endianness::BigEndian,
+        )?;
+
+        assert_eq!(raw.buf, parsed.buf);
+        assert_eq!(raw.buf, b"\x05a{sv}\x00");
+
+        Ok(())
+    }
+
+    #[test]
+    fn marshal_raw_signature_invalid() {
+        let mut encoder = Encoder::default();
+
+        assert!(encoder.marshal_raw_signature(b"a{vs}").is_err());
+        assert!(encoder.marshal_raw_signature(b"(s").is_err());
+        assert!(encoder.buf.is_empty());
+    }
+
+    #[test]
+    fn marshal_borrowed_str_like_owned() -> crate::Result<()> {
+        for endianness in [Endianness::BigEndian, Endianness::LittleEndian] {
+            // Start after a byte, so that the length needs padding.
+            let mut borrowed = Encoder::default();
+            borrowed.marshal(&DBusByte::from(1), endianness)?;
+            borrowed.marshal(&"/org/example", endianness)?;
+
+            let mut owned = Encoder::default();
+            owned.marshal(&DBusByte::from(1), endianness)?;
+            owned.marshal(&DBusString::from("/org/example"), endianness)?;
+
+            let mut object_path = Encoder::default();
+            object_path.marshal(&DBusByte::from(1), endianness)?;
+            object_path.marshal(&DBusObjectPath::from("/org/example"), endianness)?;
+
+            assert_eq!(borrowed.buf, owned.buf);
+            assert_eq!(borrowed.buf, object_path.buf);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn marshal_invalid_object_path() {
+        let mut encoder = Encoder::default();
+        let path = DBusObjectPath {
+            dbus_string: DBusString::from("org/example/"),
+        };
+
+        assert!(matches!(
+            encoder.marshal(&path, Endianness::BigEndian),
+            Err(crate::Error::InvalidObjectPath(_))
+        ));
+    }
+
+    #[test]
+    fn marshal_string_containing_nul() {
+        let mut encoder = Encoder::default();
+
+        assert!(matches!(
+            encoder.marshal(&"a\0b", Endianness::BigEndian),
+            Err(crate::Error::StringContainsNul(_))
+        ));
+        assert!(matches!(
+            encoder.marshal(&DBusString::from("a\0b"), Endianness::BigEndian),
+            Err(crate::Error::StringContainsNul(_))
+        ));
+    }
+
+    #[test]
+    fn marshal_large_array_of_nested_structs() -> crate::Result<()> {
+        let item_type = SingleCompleteTypeSignature::new_from_signature("(u(sai))")?;
+        let mut array = DBusArray::new(item_type.clone());
+        for n in 0..10_000_u32 {
+            array.push(DBusStruct::new(vec![
+                Type::from(DBusUint32::from(n)),
+                Type::from(DBusStruct::new(vec![
+                    Type::from(DBusString::from(n.to_string())),
+                    Type::from(vec![n as i32, -(n as i32)]),
+                ])),
+            ]))?;
+        }
+        let array = Type::from(array);
+
+        let mut encoder = Encoder::default();
+        encoder.marshal(&array, Endianness::LittleEndian)?;
+        let unmarshalled = array
+            .signature()
+            .unmarshal(&encoder.finish(), Endianness::LittleEndian)?;
+
+        assert_eq!(unmarshalled, array);
+
+        Ok(())
     }
 }
@@ -0,0 +1,155 @@
+//! Round-trip tests: unmarshalling a marshalled value must give back the original value, in both
+//! byte orders.
+
+use super::marshal::Encoder;
+use super::marshal::Marshal;
+use super::signature::Signature;
+use super::signature::SingleCompleteTypeSignature;
+use super::types::*;
+use super::Endianness;
+
+fn assert_round_trip<T: Into<Type>>(value: T) {
+    let value: Type = value.into();
+
+    for &endianness in &[Endianness::BigEndian, Endianness::LittleEndian] {
+        let mut encoder = Encoder::default();
+        encoder.marshal(&value, endianness).unwrap();
+        let marshalled = encoder.finish();
+
+        let unmarshalled = value
+            .signature()
+            .unmarshal(&marshalled, endianness)
+            .unwrap();
+
+        assert_eq!(value, unmarshalled);
+    }
+}
+
+/// Doubles are compared by their bits, so that NaN and negative zero are checked too.
+fn assert_double_round_trip(value: f64) {
+    let value = DBusDouble::from(value);
+
+    for &endianness in &[Endianness::BigEndian, Endianness::LittleEndian] {
+        let mut encoder = Encoder::default();
+        encoder.marshal(&value, endianness).unwrap();
+        let marshalled = encoder.finish();
+
+        let unmarshalled = SingleCompleteTypeSignature::DBusDouble
+            .unmarshal(&marshalled, endianness)
+            .unwrap();
+
+        match unmarshalled {
+            Type::Double(unmarshalled) => assert!(value.bitwise_eq(&unmarshalled)),
+            other => panic!("Expected a double, got {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn byte() {
+    assert_round_trip(DBusByte::from(0));
+    assert_round_trip(DBusByte::from(255));
+}
+
+#[test]
+fn boolean() {
+    assert_round_trip(DBusBoolean::from(true));
+    assert_round_trip(DBusBoolean::from(false));
+}
+
+#[test]
+fn integers() {
+    assert_round_trip(DBusInt16::from(i16::MIN));
+    assert_round_trip(DBusUint16::from(u16::MAX));
+    assert_round_trip(DBusInt32::from(i32::MIN));
+    assert_round_trip(DBusUint32::from(u32::MAX));
+    assert_round_trip(DBusInt64::from(i64::MIN));
+    assert_round_trip(DBusUint64::from(u64::MAX));
+}
+
+#[test]
+fn double() {
+    assert_double_round_trip(3.5);
+    assert_double_round_trip(f64::MIN_POSITIVE);
+    assert_double_round_trip(f64::NAN);
+    assert_double_round_trip(0.0);
+    assert_double_round_trip(-0.0);
+}
+
+#[test]
+fn unix_file_descriptor() {
+    assert_round_trip(DBusUnixFileDescriptor::from(0_u32));
+    assert_round_trip(DBusStruct::new(vec![
+        Type::from(DBusByte::from(1)),
+        Type::from(DBusUnixFileDescriptor::from(3_u32)),
+    ]));
+}
+
+#[test]
+fn string() {
+    assert_round_trip(DBusString::from(""));
+    assert_round_trip(DBusString::from("hello"));
+    assert_round_trip(DBusString::from("æøå"));
+}
+
+#[test]
+fn object_path() {
+    assert_round_trip(DBusObjectPath::from("/org/freedesktop/DBus"));
+}
+
+#[test]
+fn array_of_u32() {
+    assert_round_trip(DBusArray {
+        item_type: SingleCompleteTypeSignature::DBusUint32,
+        items: vec![
+            Type::from(DBusUint32::from(1_u32)),
+            Type::from(DBusUint32::from(2_u32)),
+            Type::from(DBusUint32::from(3_u32)),
+        ],
+    });
+}
+
+#[test]
+fn empty_array_of_u64() {
+    assert_round_trip(DBusArray::new(SingleCompleteTypeSignature::DBusUint64));
+}
+
+#[test]
+fn r#struct() {
+    assert_round_trip(DBusStruct::new(vec![
+        Type::from(DBusUint32::from(42_u32)),
+        Type::from(DBusString::from("hello")),
+    ]));
+}
+
+#[test]
+fn variant() {
+    assert_round_trip(DBusVariant::new(DBusUint32::from(42_u32)));
+}
+
+#[test]
+fn nested_variant() {
+    let nested = DBusVariant::new(DBusVariant::new(DBusUint32::from(42_u32)));
+
+    assert_round_trip(nested.clone());
+    // The u32 must be aligned also when the nested variant starts at an odd offset.
+    assert_round_trip(DBusStruct::new(vec![
+        Type::from(DBusByte::from(1)),
+        Type::from(nested),
+    ]));
+}
+
+#[test]
+fn dict() {
+    let mut dict = DBusArray::new(SingleCompleteTypeSignature::DBusDictEntry {
+        key: Box::new(SingleCompleteTypeSignature::DBusString),
+        value: Box::new(SingleCompleteTypeSignature::DBusVariant),
+    });
+    dict.push(DBusDictEntry::new(
+        DBusString::from("ProcessID"),
+        DBusVariant::new(DBusUint32::from(1234_u32)),
+    ))
+    .unwrap();
+
+    assert_round_trip(dict);
+}
@@ -14,7 +14,34 @@ lazy_static::lazy_static! {
 }
 
 /// Signature for a "Single Complete Type".
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd)]
+///
+/// The ordering is structural, not semantic: signatures are ordered by variant in the order
+/// they are declared here, and then by their contents. It is useful for keys in ordered maps,
+/// but says nothing about how the types relate in DBus.
+///
+/// ```
+/// use std::convert::TryFrom;
+///
+/// use dbus_stream::types::*;
+/// use dbus_stream::Signature;
+/// use dbus_stream::SingleCompleteTypeSignature;
+///
+/// let mut array = DBusArray::new(SingleCompleteTypeSignature::DBusUint32);
+/// array.push(DBusUint32::from(1_u32))?;
+/// assert_eq!(
+///     Type::from(array).signature(),
+///     SingleCompleteTypeSignature::new_from_signature("au")?
+/// );
+///
+/// match String::try_from(Type::from(2_u32)) {
+///     Err(dbus_stream::Error::UnexpectedType { found, .. }) => {
+///         assert_eq!(found, SingleCompleteTypeSignature::DBusUint32)
+///     }
+///     _ => unreachable!(),
+/// }
+/// # Ok::<(), dbus_stream::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum SingleCompleteTypeSignature {
     DBusByte,
     DBusBoolean,
@@ -90,6 +117,51 @@ impl SingleCompleteTypeSignature {
         }
     }
 
+    /// Length in bytes of a marshalled value of this type, if it is the same for all values.
+    ///
+    /// Padding depends on the position of the value, so it is not included.
+    pub fn fixed_marshalled_size(&self) -> Option<usize> {
+        match self {
+            Self::DBusByte => Some(1),
+            Self::DBusBoolean => Some(4),
+            Self::DBusInt16 => Some(2),
+            Self::DBusUint16 => Some(2),
+            Self::DBusInt32 => Some(4),
+            Self::DBusUint32 => Some(4),
+            Self::DBusInt64 => Some(8),
+            Self::DBusUint64 => Some(8),
+            Self::DBusDouble => Some(8),
+            Self::DBusUnixFileDescriptor => Some(4),
+            Self::DBusString => None,
+            Self::DBusObjectPath => None,
+            Self::DBusSignature => None,
+            Self::DBusArray(_) => None,
+            Self::DBusStruct { fields: _ } => None,
+            Self::DBusVariant => None,
+            Self::DBusDictEntry { key: _, value: _ } => None,
+        }
+    }
+
+    /// Check that dict entries only appear as the item type of arrays, which is the only place
+    /// they are allowed, and that their keys are basic types.
+    pub fn validate(&self) -> crate::Result<()> {
+        match self {
+            Self::DBusArray(item_type) => match &**item_type {
+                Self::DBusDictEntry { key, value } => {
+                    if !key.is_basic_type() {
+                        return Err(crate::Error::DictEntryKeyNotBasic((**key).clone()));
+                    }
+                    key.validate()?;
+                    value.validate()
+                }
+                item_type => item_type.validate(),
+            },
+            Self::DBusStruct { fields } => fields.iter().try_for_each(Self::validate),
+            Self::DBusDictEntry { .. } => Err(crate::Error::DictEntryOutsideArray),
+            _ => Ok(()),
+        }
+    }
+
     /// Return the signature as an ASCII string.
     ///
     /// For marshalling and transmitting on the wire, LV encoding must be taken into
@@ -155,12 +227,10 @@ impl SingleCompleteTypeSignature {
                 vec![b'v']
             }
             Self::DBusDictEntry { key, value } => {
-                // Assert that key is basic type.
-                // Maybe this should be more than a debug assertion, not sure.
-                debug_assert!(key.is_basic_type());
-
+                // The key is not checked here, marshalling calls validate first, which rejects
+                // keys that are not basic types.
+                // Only the dict entry itself, the enclosing array adds the `a`.
                 let mut v = Vec::new();
-                v.push(b'a');
                 v.push(b'{');
                 v.extend(key.serialize());
                 v.extend(value.serialize());
@@ -170,3 +240,145 @@ impl SingleCompleteTypeSignature {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_dict_entry_placement() -> crate::Result<()> {
+        let dict_entry = SingleCompleteTypeSignature::DBusDictEntry {
+            key: Box::new(SingleCompleteTypeSignature::DBusString),
+            value: Box::new(SingleCompleteTypeSignature::DBusVariant),
+        };
+
+        SingleCompleteTypeSignature::DBusArray(Box::new(dict_entry.clone())).validate()?;
+
+        let bare = SingleCompleteTypeSignature::DBusStruct {
+            fields: vec![dict_entry.clone()],
+        };
+        assert!(matches!(
+            bare.validate(),
+            Err(crate::Error::DictEntryOutsideArray)
+        ));
+
+        let nested = SingleCompleteTypeSignature::DBusArray(Box::new(
+            SingleCompleteTypeSignature::DBusDictEntry {
+                key: Box::new(SingleCompleteTypeSignature::DBusString),
+                value: Box::new(dict_entry),
+            },
+        ));
+        assert!(matches!(
+            nested.validate(),
+            Err(crate::Error::DictEntryOutsideArray)
+        ));
+
+        let variant_key = SingleCompleteTypeSignature::DBusArray(Box::new(
+            SingleCompleteTypeSignature::DBusDictEntry {
+                key: Box::new(SingleCompleteTypeSignature::DBusVariant),
+                value: Box::new(SingleCompleteTypeSignature::DBusString),
+            },
+        ));
+        assert!(matches!(
+            variant_key.validate(),
+            Err(crate::Error::DictEntryKeyNotBasic(
+                SingleCompleteTypeSignature::DBusVariant
+            ))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn sort_signatures() {
+        let array_of = |inner| SingleCompleteTypeSignature::DBusArray(Box::new(inner));
+        let struct_of = |fields| SingleCompleteTypeSignature::DBusStruct { fields };
+
+        let mut signatures = vec![
+            SingleCompleteTypeSignature::DBusVariant,
+            struct_of(vec![SingleCompleteTypeSignature::DBusString]),
+            array_of(SingleCompleteTypeSignature::DBusString),
+            SingleCompleteTypeSignature::DBusString,
+            struct_of(vec![SingleCompleteTypeSignature::DBusByte]),
+            array_of(SingleCompleteTypeSignature::DBusByte),
+            SingleCompleteTypeSignature::DBusByte,
+        ];
+        signatures.sort();
+
+        assert_eq!(
+            signatures,
+            vec![
+                SingleCompleteTypeSignature::DBusByte,
+                SingleCompleteTypeSignature::DBusString,
+                array_of(SingleCompleteTypeSignature::DBusByte),
+                array_of(SingleCompleteTypeSignature::DBusString),
+                struct_of(vec![SingleCompleteTypeSignature::DBusByte]),
+                struct_of(vec![SingleCompleteTypeSignature::DBusString]),
+                SingleCompleteTypeSignature::DBusVariant,
+            ]
+        );
+    }
+
+    #[test]
+    fn fixed_marshalled_size() {
+        use SingleCompleteTypeSignature::*;
+
+        assert_eq!(DBusByte.fixed_marshalled_size(), Some(1));
+        assert_eq!(DBusBoolean.fixed_marshalled_size(), Some(4));
+        assert_eq!(DBusInt16.fixed_marshalled_size(), Some(2));
+        assert_eq!(DBusUint32.fixed_marshalled_size(), Some(4));
+        assert_eq!(DBusDouble.fixed_marshalled_size(), Some(8));
+
+        assert_eq!(DBusString.fixed_marshalled_size(), None);
+        assert_eq!(DBusVariant.fixed_marshalled_size(), None);
+        assert_eq!(DBusArray(Box::new(DBusByte)).fixed_marshalled_size(), None);
+        assert_eq!(
+            DBusStruct {
+                fields: vec![DBusUint32]
+            }
+            .fixed_marshalled_size(),
+            None
+        );
+    }
+
+    #[test]
+    fn has_signature_agrees_with_signature() -> crate::Result<()> {
+        use crate::type_system::types::*;
+
+        let mut array = DBusArray::new(SingleCompleteTypeSignature::new_from_signature("(ys)")?);
+        array.push(DBusStruct::new(vec![
+            Type::from(DBusByte::from(1)),
+            Type::from(DBusString::from("a")),
+        ]))?;
+        let values = vec![
+            Type::from(DBusUint32::from(1_u32)),
+            Type::from(DBusVariant::new(DBusString::from("a"))),
+            Type::from(array),
+            Type::from(DBusStruct::new(vec![
+                Type::from(DBusInt16::from(1_i16)),
+                Type::from(DBusStruct::new(vec![Type::from(DBusDouble::from(1.0))])),
+            ])),
+            Type::from(DBusDictEntry::new(
+                DBusString::from("key"),
+                DBusVariant::new(DBusBoolean::from(true)),
+            )),
+        ];
+
+        for value in &values {
+            for other in &values {
+                assert_eq!(
+                    value.has_signature(&other.signature()),
+                    value.signature() == other.signature()
+                );
+            }
+        }
+
+        // A struct with fewer fields than the signature.
+        let dbus_struct = Type::from(DBusStruct::new(vec![Type::from(DBusByte::from(1))]));
+        assert!(
+            !dbus_struct.has_signature(&SingleCompleteTypeSignature::new_from_signature("(yy)")?)
+        );
+
+        Ok(())
+    }
+}
@@ -33,6 +33,46 @@ impl Signature for Type {
     }
 }
 
+impl Type {
+    /// Whether this value has the given signature, like `self.signature() == *signature`.
+    ///
+    /// The signature of the value is not built, so this does not allocate. The items of arrays are
+    /// not visited, since they are expected to have the item type of the array.
+    pub fn has_signature(&self, signature: &SingleCompleteTypeSignature) -> bool {
+        use SingleCompleteTypeSignature as S;
+
+        match (self, signature) {
+            (Type::Byte(_), S::DBusByte)
+            | (Type::Boolean(_), S::DBusBoolean)
+            | (Type::Int16(_), S::DBusInt16)
+            | (Type::Uint16(_), S::DBusUint16)
+            | (Type::Int32(_), S::DBusInt32)
+            | (Type::Uint32(_), S::DBusUint32)
+            | (Type::Int64(_), S::DBusInt64)
+            | (Type::Uint64(_), S::DBusUint64)
+            | (Type::Double(_), S::DBusDouble)
+            | (Type::String(_), S::DBusString)
+            | (Type::ObjectPath(_), S::DBusObjectPath)
+            | (Type::Signature(_), S::DBusSignature)
+            | (Type::UnixFileDescriptor(_), S::DBusUnixFileDescriptor)
+            | (Type::Variant(_), S::DBusVariant) => true,
+            (Type::Array(array), S::DBusArray(item_type)) => array.item_type == **item_type,
+            (Type::Struct(dbus_struct), S::DBusStruct { fields }) => {
+                dbus_struct.fields.len() == fields.len()
+                    && dbus_struct
+                        .fields
+                        .iter()
+                        .zip(fields)
+                        .all(|(field, signature)| field.has_signature(signature))
+            }
+            (Type::DictEntry(dict_entry), S::DBusDictEntry { key, value }) => {
+                dict_entry.key.has_signature(key) && dict_entry.value.has_signature(value)
+            }
+            _ => false,
+        }
+    }
+}
+
 
 /// Implement [Signature] for simple types.
 macro_rules! impl_to_signature {
@@ -77,7 +117,7 @@ impl Signature for DBusStruct {
 impl Signature for DBusDictEntry {
     fn signature(&self) -> SingleCompleteTypeSignature {
         SingleCompleteTypeSignature::DBusDictEntry {
-            key: Box::new(self.key.clone()),
+            key: Box::new(self.key.signature()),
             value: Box::new(self.value.signature()),
         }
     }
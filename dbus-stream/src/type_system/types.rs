@@ -1,3 +1,7 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
 use super::signature::*;
 
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
@@ -70,7 +74,7 @@ basic_type!(
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
 pub struct DBusDictEntry {
     /// Key must be a basic type, not a container type.
-    pub key: SingleCompleteTypeSignature,
+    pub key: Box<Type>,
 
     pub value: Box<Type>,
 }
@@ -78,6 +82,10 @@ pub struct DBusDictEntry {
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
 pub struct DBusArray {
     pub item_type: SingleCompleteTypeSignature,
+
+    /// Items must all have the signature `item_type`.
+    ///
+    /// Prefer [DBusArray::push], which checks this.
     pub items: Vec<Type>,
 }
 
@@ -86,6 +94,10 @@ pub struct DBusStruct {
     pub fields: Vec<Type>,
 }
 
+/// A value together with its signature.
+///
+/// The value may itself be a variant. Such nested variants are unusual but legal, and are
+/// marshalled as a variant with signature `v`, whose value is the inner variant.
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
 pub struct DBusVariant {
     pub variant: Box<Type>,
@@ -97,6 +109,98 @@ impl DBusStruct {
             fields: fields.into(),
         }
     }
+
+    /// Start building a struct field by field.
+    ///
+    /// Example: `DBusStruct::builder().field(DBusUint32::from(42_u32)).build()`.
+    pub fn builder() -> DBusStructBuilder {
+        DBusStructBuilder::default()
+    }
+
+    /// Convert the field at the given index to a Rust value.
+    pub fn field_as<T>(&self, index: usize) -> crate::Result<T>
+    where
+        T: TryFrom<Type, Error = crate::Error>,
+    {
+        let field = self
+            .fields
+            .get(index)
+            .ok_or(crate::Error::StructFieldOutOfRange {
+                index,
+                len: self.fields.len(),
+            })?;
+
+        T::try_from(field.clone())
+    }
+}
+
+/// Builder for a [DBusStruct], created with [DBusStruct::builder].
+#[derive(Debug, Default)]
+pub struct DBusStructBuilder {
+    fields: Vec<Type>,
+}
+
+impl DBusStructBuilder {
+    /// Append a field.
+    pub fn field<T: Into<Type>>(mut self, field: T) -> Self {
+        self.fields.push(field.into());
+        self
+    }
+
+    pub fn build(self) -> DBusStruct {
+        DBusStruct::new(self.fields)
+    }
+}
+
+/// Builder for the `a{sv}` dictionaries used for properties, for example by
+/// `org.freedesktop.DBus.Properties.GetAll` and `PropertiesChanged`.
+///
+/// Example: `PropertyDict::new().set("Volume", 50_u32).set("Muted", true).into_array()`.
+#[derive(Debug, Clone, Default)]
+pub struct PropertyDict {
+    properties: Vec<(String, Type)>,
+}
+
+impl PropertyDict {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set a property, wrapping the value in a variant. A property that is set again is replaced.
+    pub fn set<T: Into<Type>>(mut self, name: &str, value: T) -> Self {
+        let value = value.into();
+        match self.properties.iter_mut().find(|(key, _)| key == name) {
+            Some((_, existing)) => *existing = value,
+            None => self.properties.push((name.to_string(), value)),
+        }
+        self
+    }
+
+    /// Array of `{sv}` dict entries, in the order the properties were first set.
+    pub fn into_array(self) -> DBusArray {
+        let item_type = SingleCompleteTypeSignature::DBusDictEntry {
+            key: Box::new(SingleCompleteTypeSignature::DBusString),
+            value: Box::new(SingleCompleteTypeSignature::DBusVariant),
+        };
+        let items = self
+            .properties
+            .into_iter()
+            .map(|(name, value)| {
+                Type::from(DBusDictEntry::new(
+                    DBusString::from(name),
+                    DBusVariant::new(value),
+                ))
+            })
+            .collect();
+
+        DBusArray { item_type, items }
+    }
+}
+
+impl From<PropertyDict> for Type {
+    fn from(dict: PropertyDict) -> Self {
+        Type::from(dict.into_array())
+    }
 }
 
 impl DBusVariant {
@@ -107,6 +211,35 @@ impl DBusVariant {
     }
 }
 
+impl DBusDictEntry {
+    pub fn new<K: Into<Type>, V: Into<Type>>(key: K, value: V) -> Self {
+        Self {
+            key: Box::new(key.into()),
+            value: Box::new(value.into()),
+        }
+    }
+}
+
+impl DBusDouble {
+    /// Compare the raw bits of the values, so that NaN equals NaN with the same bit pattern, and
+    /// `0.0` does not equal `-0.0`.
+    ///
+    /// This is meant for tests and hashing. [PartialEq] compares according to IEEE 754 instead.
+    pub fn bitwise_eq(&self, other: &Self) -> bool {
+        self.f64.to_bits() == other.f64.to_bits()
+    }
+}
+
+impl DBusObjectPath {
+    /// Create an object path, checking that it is valid.
+    pub fn new<T: Into<String>>(path: T) -> crate::Result<Self> {
+        let path = path.into();
+        crate::message_protocol::names::validate_object_path(&path)?;
+
+        Ok(Self::from(path))
+    }
+}
+
 impl DBusArray {
     pub fn new(item_type: SingleCompleteTypeSignature) -> Self {
         Self {
@@ -114,6 +247,51 @@ impl DBusArray {
             items: Vec::new(),
         }
     }
+
+    /// Signature of the items of the array.
+    pub fn item_signature(&self) -> &SingleCompleteTypeSignature {
+        &self.item_type
+    }
+
+    /// Number of items in the array.
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Remove all items, keeping the item type.
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+
+    /// Append an item, checking that its signature matches the item type of the array.
+    pub fn push<T: Into<Type>>(&mut self, item: T) -> crate::Result<()> {
+        let item: Type = item.into();
+
+        if !item.has_signature(&self.item_type) {
+            return Err(crate::Error::HeterogeneousArray {
+                expected: self.item_type.clone(),
+                found: item.signature(),
+            });
+        }
+
+        self.items.push(item);
+
+        Ok(())
+    }
+
+    /// Iterate over the items, converting each of them to a Rust value.
+    ///
+    /// Example: `let numbers: Vec<u32> = array.iter_as().collect::<crate::Result<_>>()?;`
+    pub fn iter_as<T>(&self) -> impl Iterator<Item = crate::Result<T>> + '_
+    where
+        T: TryFrom<Type, Error = crate::Error>,
+    {
+        self.items.iter().map(|item| T::try_from(item.clone()))
+    }
 }
 
 
@@ -144,3 +322,553 @@ impl_from_type_variant!(DBusArray, Array);
 impl_from_type_variant!(DBusStruct, Struct);
 impl_from_type_variant!(DBusVariant, Variant);
 impl_from_type_variant!(DBusDictEntry, DictEntry);
+
+/// Macro to convert a native type to a [Type], or directly to a [DBusVariant] holding it.
+macro_rules! impl_from_native {
+    ($native:ty, $dbus_type:ident) => {
+        impl From<$native> for Type {
+            fn from(value: $native) -> Self {
+                Type::from($dbus_type::from(value))
+            }
+        }
+
+        impl From<$native> for DBusVariant {
+            fn from(value: $native) -> Self {
+                DBusVariant::new(value)
+            }
+        }
+    };
+}
+
+impl_from_native!(u8, DBusByte);
+impl_from_native!(bool, DBusBoolean);
+impl_from_native!(i16, DBusInt16);
+impl_from_native!(u16, DBusUint16);
+impl_from_native!(i32, DBusInt32);
+impl_from_native!(u32, DBusUint32);
+impl_from_native!(i64, DBusInt64);
+impl_from_native!(u64, DBusUint64);
+impl_from_native!(f64, DBusDouble);
+impl_from_native!(String, DBusString);
+impl_from_native!(&str, DBusString);
+
+macro_rules! impl_from_vec {
+    ($native:ty, $name:ident, $signature:ident) => {
+        impl From<Vec<$native>> for DBusArray {
+            fn from(items: Vec<$native>) -> Self {
+                Self {
+                    item_type: SingleCompleteTypeSignature::$signature,
+                    items: items
+                        .into_iter()
+                        .map(|item| Type::from($name::from(item)))
+                        .collect(),
+                }
+            }
+        }
+
+        impl From<Vec<$native>> for Type {
+            fn from(items: Vec<$native>) -> Type {
+                Type::Array(DBusArray::from(items))
+            }
+        }
+    };
+}
+
+impl_from_vec!(u8, DBusByte, DBusByte);
+impl_from_vec!(bool, DBusBoolean, DBusBoolean);
+impl_from_vec!(i16, DBusInt16, DBusInt16);
+impl_from_vec!(u16, DBusUint16, DBusUint16);
+impl_from_vec!(i32, DBusInt32, DBusInt32);
+impl_from_vec!(u32, DBusUint32, DBusUint32);
+impl_from_vec!(i64, DBusInt64, DBusInt64);
+impl_from_vec!(u64, DBusUint64, DBusUint64);
+impl_from_vec!(f64, DBusDouble, DBusDouble);
+impl_from_vec!(String, DBusString, DBusString);
+
+impl From<&[u8]> for DBusArray {
+    fn from(bytes: &[u8]) -> Self {
+        Self::from(bytes.to_vec())
+    }
+}
+
+impl From<&[u8]> for Type {
+    fn from(bytes: &[u8]) -> Type {
+        Type::Array(DBusArray::from(bytes))
+    }
+}
+
+/// Macro to get the inner value of a [Type], failing if it is a different type.
+macro_rules! impl_try_from_type {
+    ($name:ty, $type_variant:ident, $expected:expr, |$inner:ident| $value:expr) => {
+        impl TryFrom<Type> for $name {
+            type Error = crate::Error;
+
+            fn try_from(x: Type) -> crate::Result<Self> {
+                match x {
+                    Type::$type_variant($inner) => Ok($value),
+                    other => Err(crate::Error::UnexpectedType {
+                        expected: $expected,
+                        found: other.signature(),
+                    }),
+                }
+            }
+        }
+    };
+}
+
+impl_try_from_type!(u8, Byte, "byte", |inner| inner.u8);
+impl_try_from_type!(bool, Boolean, "boolean", |inner| inner.bool);
+impl_try_from_type!(i16, Int16, "int16", |inner| inner.i16);
+impl_try_from_type!(u16, Uint16, "uint16", |inner| inner.u16);
+impl_try_from_type!(i32, Int32, "int32", |inner| inner.i32);
+impl_try_from_type!(u32, Uint32, "uint32", |inner| inner.u32);
+impl_try_from_type!(i64, Int64, "int64", |inner| inner.i64);
+impl_try_from_type!(u64, Uint64, "uint64", |inner| inner.u64);
+impl_try_from_type!(f64, Double, "double", |inner| inner.f64);
+impl_try_from_type!(String, String, "string", |inner| inner.string);
+impl_try_from_type!(DBusObjectPath, ObjectPath, "object path", |inner| inner);
+impl_try_from_type!(DBusSignature, Signature, "signature", |inner| inner);
+impl_try_from_type!(DBusArray, Array, "array", |inner| inner);
+impl_try_from_type!(DBusStruct, Struct, "struct", |inner| inner);
+impl_try_from_type!(DBusVariant, Variant, "variant", |inner| inner);
+impl_try_from_type!(DBusDictEntry, DictEntry, "dict entry", |inner| inner);
+
+/// Rust types that can be converted from a [Type], and know which signatures they accept.
+pub trait MatchesSignature {
+    /// Whether values with the given signature can be converted to this type.
+    fn matches_signature(signature: &SingleCompleteTypeSignature) -> bool;
+}
+
+/// Macro to implement [MatchesSignature] for the types that [impl_try_from_type] converts to.
+macro_rules! impl_matches_signature {
+    ($name:ty, $signature:pat) => {
+        impl MatchesSignature for $name {
+            fn matches_signature(signature: &SingleCompleteTypeSignature) -> bool {
+                use SingleCompleteTypeSignature as S;
+
+                matches!(signature, $signature)
+            }
+        }
+    };
+}
+
+impl_matches_signature!(u8, S::DBusByte);
+impl_matches_signature!(bool, S::DBusBoolean);
+impl_matches_signature!(i16, S::DBusInt16);
+impl_matches_signature!(u16, S::DBusUint16);
+impl_matches_signature!(i32, S::DBusInt32);
+impl_matches_signature!(u32, S::DBusUint32);
+impl_matches_signature!(i64, S::DBusInt64);
+impl_matches_signature!(u64, S::DBusUint64);
+impl_matches_signature!(f64, S::DBusDouble);
+impl_matches_signature!(String, S::DBusString);
+impl_matches_signature!(DBusObjectPath, S::DBusObjectPath);
+impl_matches_signature!(DBusSignature, S::DBusSignature);
+impl_matches_signature!(DBusArray, S::DBusArray(_));
+impl_matches_signature!(DBusStruct, S::DBusStruct { .. });
+impl_matches_signature!(DBusVariant, S::DBusVariant);
+impl_matches_signature!(DBusDictEntry, S::DBusDictEntry { .. });
+
+/// Human-readable rendering of a value, for logging and eyeballing unmarshalled replies.
+///
+/// Example: `Struct { 42, "hello", [1, 2, 3] }`.
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Byte(inner) => write!(f, "{}", inner.u8),
+            Type::Boolean(inner) => write!(f, "{}", inner.bool),
+            Type::Int16(inner) => write!(f, "{}", inner.i16),
+            Type::Uint16(inner) => write!(f, "{}", inner.u16),
+            Type::Int32(inner) => write!(f, "{}", inner.i32),
+            Type::Uint32(inner) => write!(f, "{}", inner.u32),
+            Type::Int64(inner) => write!(f, "{}", inner.i64),
+            Type::Uint64(inner) => write!(f, "{}", inner.u64),
+            Type::Double(inner) => write!(f, "{}", inner.f64),
+            Type::String(inner) => write!(f, "{:?}", inner.string),
+            Type::ObjectPath(inner) => write!(f, "{}", inner),
+            Type::Signature(inner) => write!(f, "{}", inner),
+            Type::UnixFileDescriptor(inner) => write!(f, "{}", inner.u32),
+            Type::Array(inner) => write!(f, "{}", inner),
+            Type::Struct(inner) => write!(f, "{}", inner),
+            Type::Variant(inner) => write!(f, "{}", inner),
+            Type::DictEntry(inner) => write!(f, "{}", inner),
+        }
+    }
+}
+
+/// Write items separated by a comma.
+fn write_separated(f: &mut fmt::Formatter<'_>, items: &[Type]) -> fmt::Result {
+    for (idx, item) in items.iter().enumerate() {
+        if idx > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", item)?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for DBusObjectPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.dbus_string.string)
+    }
+}
+
+/// Renders the concatenated ASCII signature, for example `a{sv}s`.
+impl fmt::Display for DBusSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ascii: Vec<u8> = self.vec.iter().flat_map(|sig| sig.serialize()).collect();
+        write!(f, "{}", String::from_utf8_lossy(&ascii))
+    }
+}
+
+impl fmt::Display for DBusArray {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[")?;
+        write_separated(f, &self.items)?;
+        write!(f, "]")
+    }
+}
+
+impl fmt::Display for DBusStruct {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Struct {{ ")?;
+        write_separated(f, &self.fields)?;
+        write!(f, " }}")
+    }
+}
+
+impl fmt::Display for DBusVariant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Variant({})", self.variant)
+    }
+}
+
+impl fmt::Display for DBusDictEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} => {}", self.key, self.value)
+    }
+}
+
+impl FromStr for DBusObjectPath {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> crate::Result<Self> {
+        Self::new(s)
+    }
+}
+
+impl FromStr for DBusSignature {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> crate::Result<Self> {
+        Self::new_from_signature(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn array_push_matching_item() -> crate::Result<()> {
+        let mut array = DBusArray::new(SingleCompleteTypeSignature::DBusUint32);
+        array.push(DBusUint32::from(1_u32))?;
+        array.push(DBusUint32::from(2_u32))?;
+
+        assert_eq!(array.items.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn array_push_mismatching_item() {
+        let mut array = DBusArray::new(SingleCompleteTypeSignature::DBusUint32);
+        let result = array.push(DBusString::from("hello"));
+
+        assert!(matches!(
+            result,
+            Err(crate::Error::HeterogeneousArray {
+                expected: SingleCompleteTypeSignature::DBusUint32,
+                found: SingleCompleteTypeSignature::DBusString,
+            })
+        ));
+        assert!(array.items.is_empty());
+    }
+
+    #[test]
+    fn display_basic_values() {
+        let dbus_struct = DBusStruct::new(vec![
+            Type::from(DBusUint32::from(42_u32)),
+            Type::from(DBusString::from("hello")),
+            Type::from(DBusArray {
+                item_type: SingleCompleteTypeSignature::DBusInt32,
+                items: vec![
+                    Type::from(DBusInt32::from(1)),
+                    Type::from(DBusInt32::from(2)),
+                    Type::from(DBusInt32::from(3)),
+                ],
+            }),
+        ]);
+
+        assert_eq!(
+            Type::from(dbus_struct).to_string(),
+            r#"Struct { 42, "hello", [1, 2, 3] }"#
+        );
+    }
+
+    #[test]
+    fn display_struct_of_dict() {
+        let dict = DBusArray {
+            item_type: SingleCompleteTypeSignature::DBusDictEntry {
+                key: Box::new(SingleCompleteTypeSignature::DBusString),
+                value: Box::new(SingleCompleteTypeSignature::DBusVariant),
+            },
+            items: vec![
+                Type::from(DBusDictEntry::new(
+                    DBusString::from("Volume"),
+                    DBusVariant::new(DBusUint32::from(50_u32)),
+                )),
+                Type::from(DBusDictEntry::new(
+                    DBusString::from("Path"),
+                    DBusVariant::new(DBusObjectPath::from("/org/example")),
+                )),
+            ],
+        };
+        let dbus_struct = DBusStruct::new(vec![Type::from(dict)]);
+
+        assert_eq!(
+            Type::from(dbus_struct).to_string(),
+            r#"Struct { ["Volume" => Variant(50), "Path" => Variant(/org/example)] }"#
+        );
+    }
+
+    #[test]
+    fn array_from_vec() {
+        let array_of = |inner| SingleCompleteTypeSignature::DBusArray(Box::new(inner));
+
+        let array = Type::from(vec![1_u32, 2, 3]);
+        assert_eq!(
+            array.signature(),
+            array_of(SingleCompleteTypeSignature::DBusUint32)
+        );
+        assert_eq!(array.to_string(), "[1, 2, 3]");
+
+        assert_eq!(
+            Type::from(vec!["a".to_string(), "b".to_string()]).signature(),
+            array_of(SingleCompleteTypeSignature::DBusString)
+        );
+        assert_eq!(
+            Type::from(&b"bytes"[..]).signature(),
+            array_of(SingleCompleteTypeSignature::DBusByte)
+        );
+
+        // The item type is known even without items.
+        assert_eq!(
+            DBusArray::from(Vec::<u64>::new()).item_type,
+            SingleCompleteTypeSignature::DBusUint64
+        );
+    }
+
+    #[test]
+    fn array_iter_as() -> crate::Result<()> {
+        let mut array = DBusArray::from(vec![1_u32, 2, 3]);
+
+        let numbers = array.iter_as::<u32>().collect::<crate::Result<Vec<_>>>()?;
+        assert_eq!(numbers, vec![1, 2, 3]);
+
+        // Bypass the item type check of `push`.
+        array.items.push(Type::from(DBusString::from("four")));
+
+        let mut iter = array.iter_as::<u32>().skip(3);
+        assert!(matches!(
+            iter.next(),
+            Some(Err(crate::Error::UnexpectedType {
+                expected: "uint32",
+                found: SingleCompleteTypeSignature::DBusString,
+            }))
+        ));
+        assert!(iter.next().is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn double_bitwise_eq() {
+        let nan = DBusDouble::from(f64::NAN);
+        assert!(nan.bitwise_eq(&nan.clone()));
+        assert_ne!(nan, nan.clone());
+
+        let zero = DBusDouble::from(0.0);
+        let negative_zero = DBusDouble::from(-0.0);
+        assert!(!zero.bitwise_eq(&negative_zero));
+        assert_eq!(zero, negative_zero);
+    }
+
+    #[test]
+    fn parse_object_path() -> crate::Result<()> {
+        let path: DBusObjectPath = "/org/foo".parse()?;
+
+        assert_eq!(path, DBusObjectPath::from("/org/foo"));
+        assert_eq!(path.to_string(), "/org/foo");
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_object_path_invalid() {
+        assert!(matches!(
+            "org/foo".parse::<DBusObjectPath>(),
+            Err(crate::Error::InvalidObjectPath(_))
+        ));
+        assert!("/org/foo/".parse::<DBusObjectPath>().is_err());
+        assert!("/org//foo".parse::<DBusObjectPath>().is_err());
+    }
+
+    #[test]
+    fn parse_signature() -> crate::Result<()> {
+        let signature: DBusSignature = "a{sv}s".parse()?;
+
+        assert_eq!(
+            signature.vec,
+            vec![
+                SingleCompleteTypeSignature::DBusArray(Box::new(
+                    SingleCompleteTypeSignature::DBusDictEntry {
+                        key: Box::new(SingleCompleteTypeSignature::DBusString),
+                        value: Box::new(SingleCompleteTypeSignature::DBusVariant),
+                    }
+                )),
+                SingleCompleteTypeSignature::DBusString,
+            ]
+        );
+        assert_eq!(signature.to_string(), "a{sv}s");
+        assert_eq!("".parse::<DBusSignature>()?.vec, vec![]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_signature_invalid() {
+        assert!("a".parse::<DBusSignature>().is_err());
+        assert!("{sv}".parse::<DBusSignature>().is_err());
+        assert!("(i".parse::<DBusSignature>().is_err());
+        assert!(matches!(
+            "i".repeat(256).parse::<DBusSignature>(),
+            Err(crate::Error::SignatureTooLong(256))
+        ));
+    }
+
+    #[test]
+    fn array_accessors() {
+        let mut array = DBusArray::from(vec![1_u32, 2, 3]);
+        assert_eq!(array.len(), 3);
+        assert!(!array.is_empty());
+        assert_eq!(
+            array.item_signature(),
+            &SingleCompleteTypeSignature::DBusUint32
+        );
+
+        array.clear();
+        assert_eq!(array.len(), 0);
+        assert!(array.is_empty());
+        assert_eq!(
+            array.item_signature(),
+            &SingleCompleteTypeSignature::DBusUint32
+        );
+    }
+
+    #[test]
+    fn struct_builder_and_field_as() -> crate::Result<()> {
+        let dbus_struct = DBusStruct::builder()
+            .field(DBusUint32::from(42_u32))
+            .field(DBusString::from("name"))
+            .build();
+
+        assert_eq!(
+            dbus_struct.signature(),
+            SingleCompleteTypeSignature::DBusStruct {
+                fields: vec![
+                    SingleCompleteTypeSignature::DBusUint32,
+                    SingleCompleteTypeSignature::DBusString,
+                ]
+            }
+        );
+        assert_eq!(dbus_struct.field_as::<u32>(0)?, 42);
+        assert_eq!(dbus_struct.field_as::<String>(1)?, "name");
+        assert!(matches!(
+            dbus_struct.field_as::<String>(0),
+            Err(crate::Error::UnexpectedType { .. })
+        ));
+        assert!(matches!(
+            dbus_struct.field_as::<u32>(2),
+            Err(crate::Error::StructFieldOutOfRange { index: 2, len: 2 })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn variant_from_native() -> crate::Result<()> {
+        assert_eq!(
+            DBusVariant::from(1_u32),
+            DBusVariant::new(DBusUint32::from(1_u32))
+        );
+
+        let dict = PropertyDict::new()
+            .set("a", 1_u32)
+            .set("b", "x")
+            .set("c", true)
+            .into_array();
+        let expected = [
+            ("a", SingleCompleteTypeSignature::DBusUint32),
+            ("b", SingleCompleteTypeSignature::DBusString),
+            ("c", SingleCompleteTypeSignature::DBusBoolean),
+        ];
+        for (entry, (name, signature)) in dict.items.into_iter().zip(expected.iter()) {
+            let entry = DBusDictEntry::try_from(entry)?;
+            assert_eq!(String::try_from(*entry.key)?, *name);
+            assert_eq!(
+                DBusVariant::try_from(*entry.value)?.variant.signature(),
+                *signature
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn property_dict() -> crate::Result<()> {
+        use crate::type_system::marshal::Encoder;
+        use crate::type_system::marshal::Marshal;
+        use crate::type_system::Endianness;
+
+        let dict = Type::from(
+            PropertyDict::new()
+                .set("Volume", 50_u32)
+                .set("Muted", false)
+                .set("Muted", true)
+                .into_array(),
+        );
+        assert_eq!(
+            dict.signature(),
+            SingleCompleteTypeSignature::new_from_signature("a{sv}")?
+        );
+
+        let mut encoder = Encoder::default();
+        encoder.marshal(&dict, Endianness::LittleEndian)?;
+        let unmarshalled = dict
+            .signature()
+            .unmarshal(&encoder.finish(), Endianness::LittleEndian)?;
+        assert_eq!(unmarshalled, dict);
+
+        let entries = DBusArray::try_from(unmarshalled)?.items;
+        assert_eq!(entries.len(), 2);
+        let expected = [("Volume", Type::from(50_u32)), ("Muted", Type::from(true))];
+        for (entry, (name, value)) in entries.into_iter().zip(expected.iter()) {
+            let entry = DBusDictEntry::try_from(entry)?;
+            assert_eq!(String::try_from(*entry.key)?, *name);
+            assert_eq!(DBusVariant::try_from(*entry.value)?.variant.as_ref(), value);
+        }
+
+        Ok(())
+    }
+}
@@ -1,3 +1,5 @@
+use std::convert::TryFrom;
+use std::num::NonZeroU32;
 use std::ops::Deref;
 
 use nom::branch::alt;
@@ -8,6 +10,10 @@ use nom::combinator::map_opt;
 use nom::combinator::map_res;
 use nom::combinator::value;
 use nom::combinator::map_parser;
+use nom::combinator::verify;
+use nom::error::ErrorKind;
+use nom::error::FromExternalError;
+use nom::error::ParseError;
 use nom::multi::many0;
 use nom::multi::many1;
 use nom::number::complete::be_f64;
@@ -18,19 +24,30 @@ use nom::number::complete::be_u16;
 use nom::number::complete::be_u32;
 use nom::number::complete::be_u64;
 use nom::number::complete::be_u8;
+use nom::number::complete::le_f64;
+use nom::number::complete::le_i16;
+use nom::number::complete::le_i32;
+use nom::number::complete::le_i64;
+use nom::number::complete::le_u16;
 use nom::number::complete::le_u32;
-use nom::sequence::delimited;
+use nom::number::complete::le_u64;
 use nom::sequence::pair;
-use nom::sequence::preceded;
+use nom::sequence::terminated;
 use nom::Finish;
-use nom::IResult;
+use nom::InputLength;
 
 use super::signature::SingleCompleteTypeSignature;
 use super::signature::HEADER_FIELD_SIGNATURE;
 use super::types::*;
 use super::Endianness;
+use crate::message_protocol::body::Body;
+use crate::message_protocol::HeaderField;
 use crate::message_protocol::Message;
 use crate::message_protocol::MessageType;
+use crate::message_protocol::MessageTypeParam;
+use crate::message_protocol::MethodCall;
+use crate::message_protocol::MethodReturn;
+use crate::message_protocol::Signal;
 
 pub mod input;
 pub mod parsers;
@@ -39,6 +56,60 @@ use parsers::complete::skip_null_byte;
 
 use self::input::I;
 
+/// Error from the unmarshalling parsers.
+#[derive(Debug, PartialEq)]
+pub enum UnmarshalError<I> {
+    /// The input is not valid.
+    Invalid(I, ErrorKind),
+
+    /// A signature contains a byte that is not a type code.
+    InvalidSignatureChar(u8),
+
+    /// Containers are nested more than [MAX_NESTING_DEPTH] levels deep.
+    NestingTooDeep,
+}
+
+impl<I> ParseError<I> for UnmarshalError<I> {
+    fn from_error_kind(input: I, kind: ErrorKind) -> Self {
+        Self::Invalid(input, kind)
+    }
+
+    fn append(_input: I, _kind: ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+
+impl<I, E> FromExternalError<I, E> for UnmarshalError<I> {
+    fn from_external_error(input: I, kind: ErrorKind, _e: E) -> Self {
+        Self::Invalid(input, kind)
+    }
+}
+
+impl<I> From<UnmarshalError<I>> for crate::Error {
+    fn from(err: UnmarshalError<I>) -> Self {
+        match err {
+            UnmarshalError::Invalid(_, _) => crate::Error::ParseError,
+            UnmarshalError::InvalidSignatureChar(c) => crate::Error::InvalidSignatureChar(c),
+            UnmarshalError::NestingTooDeep => crate::Error::NestingTooDeep,
+        }
+    }
+}
+
+type IResult<I, O> = nom::IResult<I, O, UnmarshalError<I>>;
+
+/// Maximum number of nested containers, both in signatures and in values, where variants can
+/// nest without limit. This prevents unmarshalling from overflowing the stack.
+pub const MAX_NESTING_DEPTH: usize = 64;
+
+/// The depth inside one more container, failing without backtracking if that is too deep.
+fn nested<I>(depth: usize) -> Result<usize, nom::Err<UnmarshalError<I>>> {
+    if depth < MAX_NESTING_DEPTH {
+        Ok(depth + 1)
+    } else {
+        Err(nom::Err::Failure(UnmarshalError::NestingTooDeep))
+    }
+}
+
 trait Alignment {
     fn alignment() -> usize;
 }
@@ -71,99 +142,149 @@ impl_alignment!(DBusStruct, 8);
 impl_alignment!(DBusVariant, 1);
 impl_alignment!(DBusDictEntry, 8);
 
-impl DBusSignature {
+/// Fail without backtracking if the next byte of a signature is not a type code, so that a
+/// bogus type code is reported as such rather than as a generic parse error.
+fn parse_invalid_type_code<'a>(i: I<'a>) -> IResult<I<'a>, SingleCompleteTypeSignature> {
+    match i.data.first() {
+        Some(c) if !b"ybnqiuxtdsoghav(){}".contains(c) => {
+            Err(nom::Err::Failure(UnmarshalError::InvalidSignatureChar(*c)))
+        }
+        _ => Err(nom::Err::Error(UnmarshalError::Invalid(i, ErrorKind::Alt))),
+    }
+}
 
-    /// TODO - right now it doesn't look at the leading byte indicating the length. Should it?
-    /// it also doesn't look for the terminating null byte, which I feel it probably should!
-    fn unmarshal_be<'a>(i: I<'a>) -> IResult<I<'a>, Self> {
-        fn parse_basic_type<'a>(i: I<'a>) -> IResult<I<'a>, SingleCompleteTypeSignature> {
-            let parse_byte = value(SingleCompleteTypeSignature::DBusByte, tag(b"y"));
-            let parse_boolean = value(SingleCompleteTypeSignature::DBusBoolean, tag(b"b"));
-            let parse_int16 = value(SingleCompleteTypeSignature::DBusInt16, tag(b"n"));
-            let parse_uint16 = value(SingleCompleteTypeSignature::DBusUint16, tag(b"q"));
-            let parse_int32 = value(SingleCompleteTypeSignature::DBusInt32, tag(b"i"));
-            let parse_uint32 = value(SingleCompleteTypeSignature::DBusUint32, tag(b"u"));
-            let parse_int64 = value(SingleCompleteTypeSignature::DBusInt64, tag(b"x"));
-            let parse_uint64 = value(SingleCompleteTypeSignature::DBusUint64, tag(b"t"));
-            let parse_double = value(SingleCompleteTypeSignature::DBusDouble, tag(b"d"));
-            let parse_string = value(SingleCompleteTypeSignature::DBusString, tag(b"s"));
-            let parse_objectpath = value(SingleCompleteTypeSignature::DBusObjectPath, tag(b"o"));
-            let parse_unixfiledescriptor = value(
-                SingleCompleteTypeSignature::DBusUnixFileDescriptor,
-                tag(b"h"),
-            );
+fn parse_basic_type<'a>(i: I<'a>) -> IResult<I<'a>, SingleCompleteTypeSignature> {
+    let parse_byte = value(SingleCompleteTypeSignature::DBusByte, tag(b"y"));
+    let parse_boolean = value(SingleCompleteTypeSignature::DBusBoolean, tag(b"b"));
+    let parse_int16 = value(SingleCompleteTypeSignature::DBusInt16, tag(b"n"));
+    let parse_uint16 = value(SingleCompleteTypeSignature::DBusUint16, tag(b"q"));
+    let parse_int32 = value(SingleCompleteTypeSignature::DBusInt32, tag(b"i"));
+    let parse_uint32 = value(SingleCompleteTypeSignature::DBusUint32, tag(b"u"));
+    let parse_int64 = value(SingleCompleteTypeSignature::DBusInt64, tag(b"x"));
+    let parse_uint64 = value(SingleCompleteTypeSignature::DBusUint64, tag(b"t"));
+    let parse_double = value(SingleCompleteTypeSignature::DBusDouble, tag(b"d"));
+    let parse_string = value(SingleCompleteTypeSignature::DBusString, tag(b"s"));
+    let parse_objectpath = value(SingleCompleteTypeSignature::DBusObjectPath, tag(b"o"));
+    let parse_signature = value(SingleCompleteTypeSignature::DBusSignature, tag(b"g"));
+    let parse_unixfiledescriptor = value(
+        SingleCompleteTypeSignature::DBusUnixFileDescriptor,
+        tag(b"h"),
+    );
 
-            alt((
-                parse_byte,
-                parse_boolean,
-                parse_int16,
-                parse_uint16,
-                parse_int32,
-                parse_uint32,
-                parse_int64,
-                parse_uint64,
-                parse_double,
-                parse_string,
-                parse_objectpath,
-                parse_unixfiledescriptor,
-            ))(i)
-        }
+    alt((
+        parse_byte,
+        parse_boolean,
+        parse_int16,
+        parse_uint16,
+        parse_int32,
+        parse_uint32,
+        parse_int64,
+        parse_uint64,
+        parse_double,
+        parse_string,
+        parse_objectpath,
+        parse_signature,
+        parse_unixfiledescriptor,
+    ))(i)
+}
 
-        fn parse_struct<'a>(i: I<'a>) -> IResult<I<'a>, SingleCompleteTypeSignature> {
-            let tag = tag::<&[u8], I, nom::error::Error<I>>;
-            let (i, fields) = delimited(
-                tag(b"("),
-                many1(parse_single_complete_type_except_dictentry),
-                tag(b")"),
-            )(i)?;
+fn parse_struct<'a>(i: I<'a>, depth: usize) -> IResult<I<'a>, SingleCompleteTypeSignature> {
+    let tag = tag::<&[u8], I, UnmarshalError<I>>;
+    let (i, _) = tag(b"(")(i)?;
+    let depth = nested(depth)?;
+    let (i, fields) = terminated(
+        many1(|i| parse_single_complete_type_except_dictentry(i, depth)),
+        tag(b")"),
+    )(i)?;
 
-            Ok((i, SingleCompleteTypeSignature::DBusStruct { fields }))
-        }
+    Ok((i, SingleCompleteTypeSignature::DBusStruct { fields }))
+}
 
-        fn parse_variant<'a>(i: I<'a>) -> IResult<I<'a>, SingleCompleteTypeSignature> {
-            value(SingleCompleteTypeSignature::DBusVariant, tag(b"v"))(i)
-        }
+fn parse_variant<'a>(i: I<'a>) -> IResult<I<'a>, SingleCompleteTypeSignature> {
+    value(SingleCompleteTypeSignature::DBusVariant, tag(b"v"))(i)
+}
 
-        fn parse_single_complete_type_except_dictentry<'a>(
-            i: I<'a>,
-        ) -> IResult<I<'a>, SingleCompleteTypeSignature> {
-            alt((parse_basic_type, parse_struct, parse_variant, parse_array))(i)
-        }
+fn parse_single_complete_type_except_dictentry<'a>(
+    i: I<'a>,
+    depth: usize,
+) -> IResult<I<'a>, SingleCompleteTypeSignature> {
+    alt((
+        parse_basic_type,
+        |i| parse_struct(i, depth),
+        parse_variant,
+        |i| parse_array(i, depth),
+        parse_invalid_type_code,
+    ))(i)
+}
 
-        fn parse_array<'a>(i: I<'a>) -> IResult<I<'a>, SingleCompleteTypeSignature> {
-            preceded(
-                tag(b"a"),
-                alt((
-                    parse_basic_type,
-                    parse_struct,
-                    parse_variant,
-                    parse_array,
-                    parse_dict_entry,
-                )),
-            )(i)
-        }
+/// Parse complete types at the top level of a signature, that is not nested in containers.
+fn parse_top_level<'a>(i: I<'a>) -> IResult<I<'a>, SingleCompleteTypeSignature> {
+    parse_single_complete_type_except_dictentry(i, 0)
+}
 
-        fn parse_dict_entry<'a>(i: I<'a>) -> IResult<I<'a>, SingleCompleteTypeSignature> {
-            let tag = tag::<&[u8], I, nom::error::Error<I>>;
-            let (i, (key, value)) = delimited(
-                tag(b"{"),
-                pair(
-                    parse_basic_type,
-                    parse_single_complete_type_except_dictentry,
-                ),
-                tag(b"}"),
-            )(i)?;
-
-            let dict_entry = SingleCompleteTypeSignature::DBusDictEntry {
-                key: Box::new(key),
-                value: Box::new(value),
-            };
-
-            Ok((i, dict_entry))
+fn parse_array<'a>(i: I<'a>, depth: usize) -> IResult<I<'a>, SingleCompleteTypeSignature> {
+    let (i, _) = tag(b"a")(i)?;
+    let depth = nested(depth)?;
+    let (i, item_type) = alt((
+        parse_basic_type,
+        |i| parse_struct(i, depth),
+        parse_variant,
+        |i| parse_array(i, depth),
+        |i| parse_dict_entry(i, depth),
+        parse_invalid_type_code,
+    ))(i)?;
+
+    Ok((
+        i,
+        SingleCompleteTypeSignature::DBusArray(Box::new(item_type)),
+    ))
+}
+
+fn parse_dict_entry<'a>(i: I<'a>, depth: usize) -> IResult<I<'a>, SingleCompleteTypeSignature> {
+    let tag = tag::<&[u8], I, UnmarshalError<I>>;
+    let (i, _) = tag(b"{")(i)?;
+    let depth = nested(depth)?;
+    let (i, (key, value)) = terminated(
+        pair(parse_basic_type, |i| {
+            parse_single_complete_type_except_dictentry(i, depth)
+        }),
+        tag(b"}"),
+    )(i)?;
+
+    let dict_entry = SingleCompleteTypeSignature::DBusDictEntry {
+        key: Box::new(key),
+        value: Box::new(value),
+    };
+
+    Ok((i, dict_entry))
+}
+
+impl DBusSignature {
+    /// Parse an ASCII signature of zero or more complete types, for example `a{sv}s`.
+    pub fn new_from_signature(signature: &str) -> crate::Result<Self> {
+        Self::new_from_bytes(signature.as_bytes())
+    }
+
+    /// Parse a signature like [DBusSignature::new_from_signature], given as bytes.
+    pub fn new_from_bytes(signature: &[u8]) -> crate::Result<Self> {
+        if signature.len() > u8::MAX as usize {
+            return Err(crate::Error::SignatureTooLong(signature.len()));
         }
 
+        let (_i, vec) = all_consuming(many0(parse_top_level))(I::new(signature)).finish()?;
+
+        Ok(Self { vec })
+    }
+
+    /// The signature is prefixed by a single byte indicating its length, and is followed by a
+    /// terminating null byte.
+    fn unmarshal<'a>(i: I<'a>) -> IResult<I<'a>, Self> {
+        let (i, length) = be_u8(i)?;
+
         let (i, single_complete_type_signatures): (I, Vec<SingleCompleteTypeSignature>) =
-            many0(parse_single_complete_type_except_dictentry)(i)?;
+            map_parser(take(length), all_consuming(many0(parse_top_level)))(i)?;
+
+        let i = skip_null_byte(i)?;
 
         let dbus_signature = DBusSignature {
             vec: single_complete_type_signatures,
@@ -173,17 +294,170 @@ impl DBusSignature {
     }
 }
 
+/// Header of a message, before interpreting the header fields.
+struct RawHeader {
+    endianness: Endianness,
+    /// Not yet interpreted, because unknown message types must be ignored rather than fail to parse.
+    message_type: u8,
+    flag_no_reply_expected: bool,
+    flag_no_auto_start: bool,
+    flag_allow_interactive_authorization: bool,
+    /// Flag bits that the specification does not define.
+    reserved_flag_bits: u8,
+    length_in_bytes_of_message_body: u32,
+    serial: u32,
+    header_fields: DBusArray,
+}
 
-/// Unmarshal a DBus message (consisting of header and body),
+/// Unmarshal a DBus message (consisting of header and body).
 pub fn unmarshal_message(message: &[u8]) -> crate::Result<Message> {
-    let (_i, message) = all_consuming(unmarshal_message_inner)(I::new(message))
-        .finish()
-        .map_err(|_err| crate::Error::ParseError)?;
+    let (unmarshalled, consumed) = unmarshal_message_prefix(message)?;
+
+    // Trailing bytes are not part of this message.
+    if consumed != message.len() {
+        return Err(crate::Error::ParseError);
+    }
 
-    Ok(message)
+    Ok(unmarshalled)
 }
 
-fn unmarshal_message_inner<'i>(i: I<'i>) -> IResult<I<'i>, Message> {
+/// Unmarshal the message at the start of the data, which may be followed by more messages.
+///
+/// Returns the message and the number of bytes it takes up.
+pub fn unmarshal_message_prefix(message: &[u8]) -> crate::Result<(Message, usize)> {
+    let (i, header) = unmarshal_header(I::new(message)).finish()?;
+
+    if header.reserved_flag_bits != 0 {
+        return Err(crate::Error::ReservedFlagBitsSet(header.reserved_flag_bits));
+    }
+
+    // Separate the header fields.
+    let mut path: Option<DBusObjectPath> = None;
+    let mut interface: Option<DBusString> = None;
+    let mut member: Option<DBusString> = None;
+    let mut error_name: Option<DBusString> = None;
+    let mut reply_serial: Option<NonZeroU32> = None;
+    let mut destination: Option<DBusString> = None;
+    let mut sender: Option<DBusString> = None;
+    let mut signature: Option<DBusSignature> = None;
+    let mut unix_fds: Option<u32> = None;
+
+    for header_field in decode_header_fields(header.header_fields)? {
+        match header_field {
+            HeaderField::Path(inner) => path = Some(inner),
+            HeaderField::Interface(inner) => interface = Some(inner),
+            HeaderField::Member(inner) => member = Some(inner),
+            HeaderField::ErrorName(inner) => error_name = Some(inner),
+            HeaderField::ReplySerial(inner) => {
+                let inner = NonZeroU32::new(inner.u32).ok_or(crate::Error::InvalidReplySerial)?;
+                reply_serial = Some(inner)
+            }
+            HeaderField::Destination(inner) => destination = Some(inner),
+            HeaderField::Sender(inner) => sender = Some(inner),
+            HeaderField::Signature(inner) => signature = Some(inner),
+            // The file descriptors themselves are not received yet, only their number.
+            HeaderField::UnixFds(inner) => unix_fds = Some(inner.u32),
+        }
+    }
+
+    let message_type_param = match MessageType::from_decimal_value(header.message_type)? {
+        MessageType::MethodCall => MessageTypeParam::MethodCall(MethodCall {
+            path: path.ok_or(crate::Error::MissingHeaderField(1))?,
+            interface,
+            member: member.ok_or(crate::Error::MissingHeaderField(3))?,
+        }),
+        MessageType::MethodReturn => MessageTypeParam::MethodReturn(MethodReturn {
+            reply_serial: reply_serial.ok_or(crate::Error::MissingHeaderField(5))?,
+        }),
+        MessageType::Error => MessageTypeParam::Error(crate::message_protocol::Error {
+            error_name: error_name.ok_or(crate::Error::MissingHeaderField(4))?,
+            reply_serial: reply_serial.ok_or(crate::Error::MissingHeaderField(5))?,
+        }),
+        MessageType::Signal => MessageTypeParam::Signal(Signal {
+            path: path.ok_or(crate::Error::MissingHeaderField(1))?,
+            interface: interface.ok_or(crate::Error::MissingHeaderField(2))?,
+            member: member.ok_or(crate::Error::MissingHeaderField(3))?,
+        }),
+    };
+
+    // Unmarshal body according to the signature header field. No signature means no body.
+    let signature = signature.unwrap_or(DBusSignature { vec: vec![] });
+    let length_in_bytes_of_message_body = header.length_in_bytes_of_message_body;
+    let endianness = header.endianness;
+    let (i, arguments) =
+        unmarshal_body(i, length_in_bytes_of_message_body, &signature, endianness).finish()?;
+    let consumed = message.len() - i.input_len();
+
+    let serial = NonZeroU32::new(header.serial).ok_or(crate::Error::ParseError)?;
+
+    let message = Message {
+        flag_no_reply_expected: header.flag_no_reply_expected,
+        flag_no_auto_start: header.flag_no_auto_start,
+        flag_allow_interactive_authorization: header.flag_allow_interactive_authorization,
+        serial,
+        message_type_param,
+        destination,
+        sender,
+        unix_fds,
+        received_fds: Vec::new(),
+        body: Body { arguments },
+    };
+
+    Ok((message, consumed))
+}
+
+/// Decode the unmarshalled header field array, which is `a(yv)`.
+///
+/// Unknown header fields are skipped. Known header fields may appear at most once.
+pub(crate) fn decode_header_fields(array: DBusArray) -> crate::Result<Vec<HeaderField>> {
+    let mut header_fields: Vec<HeaderField> = Vec::with_capacity(array.items.len());
+
+    // Indexed by header field code.
+    let mut seen = [false; 10];
+
+    for item in array.items {
+        let mut fields = match item {
+            Type::Struct(dbus_struct) => dbus_struct.fields.into_iter(),
+            _ => return Err(crate::Error::ParseError),
+        };
+        let (code, variant) = match (fields.next(), fields.next(), fields.next()) {
+            (Some(Type::Byte(code)), Some(Type::Variant(variant)), None) => {
+                (code.u8, variant.variant)
+            }
+            _ => return Err(crate::Error::ParseError),
+        };
+
+        // The signature embedded in the variant has already been used to unmarshal the value,
+        // so it only needs to be checked against the header field code here.
+        let header_field = match (code, *variant) {
+            (1, Type::ObjectPath(inner)) => HeaderField::Path(inner),
+            (2, Type::String(inner)) => HeaderField::Interface(inner),
+            (3, Type::String(inner)) => HeaderField::Member(inner),
+            (4, Type::String(inner)) => HeaderField::ErrorName(inner),
+            (5, Type::Uint32(inner)) => HeaderField::ReplySerial(inner),
+            (6, Type::String(inner)) => HeaderField::Destination(inner),
+            (7, Type::String(inner)) => HeaderField::Sender(inner),
+            (8, Type::Signature(inner)) => HeaderField::Signature(inner),
+            (9, Type::Uint32(inner)) => HeaderField::UnixFds(inner),
+            // Known header field, but with a value of the wrong type.
+            (1..=9, _) => return Err(crate::Error::ParseError),
+            // Unknown header fields must be accepted and ignored.
+            _ => continue,
+        };
+
+        if seen[usize::from(code)] {
+            return Err(crate::Error::DuplicateHeaderField(code));
+        }
+        seen[usize::from(code)] = true;
+
+        header_fields.push(header_field);
+    }
+
+    Ok(header_fields)
+}
+
+/// Unmarshal the header, including the padding that follows it.
+fn unmarshal_header<'i>(i: I<'i>) -> IResult<I<'i>, RawHeader> {
     // 1st byte: Endianness
     let (i, endianness) = map_opt(be_u8, |value| match value {
         b'B' => Some(Endianness::BigEndian),
@@ -197,16 +471,17 @@ fn unmarshal_message_inner<'i>(i: I<'i>) -> IResult<I<'i>, Message> {
     };
 
     // 2nd byte: Message type
-    let (i, message_type) = MessageType::unmarshal(i)?;
+    let (i, message_type) = be_u8(i)?;
 
     // 3rd byte: Header flags
     let (i, flag_bitfield) = be_u8(i)?;
     let flag_no_reply_expected: bool = 0x1 & flag_bitfield == 0x1;
     let flag_no_auto_start: bool = 0x2 & flag_bitfield == 0x2;
     let flag_allow_interactive_authorization: bool = 0x4 & flag_bitfield == 0x4;
+    let reserved_flag_bits = flag_bitfield & !0x7;
 
     // 4th byte: Major protocol version
-    let (i, major_protocol_version) = tag(&[crate::MAJOR_PROTOCOL_VERSION])(i)?;
+    let (i, _major_protocol_version) = tag(&[crate::MAJOR_PROTOCOL_VERSION])(i)?;
 
     // 5th-8th byte: Length in bytes of the message body.
     let (i, length_in_bytes_of_message_body) = parse_u32(i)?;
@@ -215,32 +490,84 @@ fn unmarshal_message_inner<'i>(i: I<'i>) -> IResult<I<'i>, Message> {
     let (i, serial) = parse_u32(i)?;
 
     // Unmarshal header fields
-    let (i, header_field_array) = match endianness {
-        Endianness::BigEndian => {
-            SingleCompleteTypeSignature::DBusArray(Box::new(HEADER_FIELD_SIGNATURE.clone()))
-                .unmarshal_inner(i, endianness)?
-        }
-        Endianness::LittleEndian => todo!(),
-    };
+    let (i, header_fields) =
+        SingleCompleteTypeSignature::DBusArray(Box::new(HEADER_FIELD_SIGNATURE.clone()))
+            .unmarshal_inner(i, endianness)?;
     // Unpack unmarshalled type.
-    let header_field_array: DBusArray = match header_field_array {
+    let header_fields: DBusArray = match header_fields {
         Type::Array(dbus_array) => dbus_array,
         _ => unreachable!(),
     };
 
-    todo!("Separate the header fields and package them in a nicer way?");
+    // The header ends with padding to an 8-byte boundary.
+    let i = i.advance_to_boundary(8)?;
 
-    todo!("Unmarshal body");
+    let header = RawHeader {
+        endianness,
+        message_type,
+        flag_no_reply_expected,
+        flag_no_auto_start,
+        flag_allow_interactive_authorization,
+        reserved_flag_bits,
+        length_in_bytes_of_message_body,
+        serial,
+        header_fields,
+    };
 
-    todo!("Define return type");
+    Ok((i, header))
 }
 
-macro_rules! impl_unmarshal_be {
-    ($name:ident, $parser:ident) => {
+/// Unmarshal the arguments of a body with the given signature.
+fn unmarshal_body<'i>(
+    i: I<'i>,
+    length_in_bytes: u32,
+    signature: &DBusSignature,
+    endianness: Endianness,
+) -> IResult<I<'i>, Vec<Type>> {
+    map_parser(
+        take(length_in_bytes),
+        all_consuming(|mut i| {
+            let mut arguments: Vec<Type> = Vec::with_capacity(signature.vec.len());
+            for single_complete_type_signature in &signature.vec {
+                let (rest, argument) =
+                    single_complete_type_signature.unmarshal_inner(i, endianness)?;
+                i = rest;
+                arguments.push(argument);
+            }
+            Ok((i, arguments))
+        }),
+    )(i)
+}
+
+impl Body {
+    /// Unmarshal the arguments of a body with the given signature, for example the signature
+    /// from the SIGNATURE header field.
+    ///
+    /// In a message, the body starts on an 8-byte boundary, so alignment is counted from the start
+    /// of the data.
+    pub fn unmarshal(
+        data: &[u8],
+        signature: &DBusSignature,
+        endianness: Endianness,
+    ) -> crate::Result<Body> {
+        let length = u32::try_from(data.len())?;
+        let (_i, arguments) =
+            all_consuming(|i| unmarshal_body(i, length, signature, endianness))(I::new(data))
+                .finish()?;
+
+        Ok(Body { arguments })
+    }
+}
+
+macro_rules! impl_unmarshal {
+    ($name:ident, $be_parser:ident, $le_parser:ident) => {
         impl $name {
-            fn unmarshal_be<'a>(i: I<'a>) -> IResult<I<'a>, Self> {
+            fn unmarshal<'a>(i: I<'a>, endianness: Endianness) -> IResult<I<'a>, Self> {
                 let i = i.advance_to_boundary(Self::alignment())?;
-                let (i, value) = $parser(i)?;
+                let (i, value) = match endianness {
+                    Endianness::BigEndian => $be_parser(i)?,
+                    Endianness::LittleEndian => $le_parser(i)?,
+                };
                 let unmarshalled: Self = Self::from(value);
                 Ok((i, unmarshalled))
             }
@@ -248,98 +575,162 @@ macro_rules! impl_unmarshal_be {
     };
 }
 
-impl_unmarshal_be!(DBusByte, be_u8);
-impl_unmarshal_be!(DBusInt16, be_i16);
-impl_unmarshal_be!(DBusInt32, be_i32);
-impl_unmarshal_be!(DBusInt64, be_i64);
-impl_unmarshal_be!(DBusUint16, be_u16);
-impl_unmarshal_be!(DBusUint32, be_u32);
-impl_unmarshal_be!(DBusUint64, be_u64);
-impl_unmarshal_be!(DBusDouble, be_f64);
+impl_unmarshal!(DBusByte, be_u8, be_u8);
+impl_unmarshal!(DBusInt16, be_i16, le_i16);
+impl_unmarshal!(DBusInt32, be_i32, le_i32);
+impl_unmarshal!(DBusInt64, be_i64, le_i64);
+impl_unmarshal!(DBusUint16, be_u16, le_u16);
+impl_unmarshal!(DBusUint32, be_u32, le_u32);
+impl_unmarshal!(DBusUint64, be_u64, le_u64);
+impl_unmarshal!(DBusDouble, be_f64, le_f64);
+impl_unmarshal!(DBusUnixFileDescriptor, be_u32, le_u32);
 
 impl DBusBoolean {
-    fn unmarshal_be<'a>(i: I<'a>) -> IResult<I<'a>, Self> {
-        let i = i.advance_to_boundary(Self::alignment())?;
-
+    fn unmarshal<'a>(i: I<'a>, endianness: Endianness) -> IResult<I<'a>, Self> {
         // The boolean is contained in a u32, but only 0 or 1 are valid values.
-        let (i, boolean): (I, bool) = map_opt(be_u32, |value| match value {
-            0 => Some(false),
-            1 => Some(true),
-            _ => None,
-        })(i)?;
+        let (i, boolean): (I, bool) = map_opt(
+            |i| DBusUint32::unmarshal(i, endianness),
+            |value| match value.u32 {
+                0 => Some(false),
+                1 => Some(true),
+                _ => None,
+            },
+        )(i)?;
 
         let unmarshalled: Self = Self { bool: boolean };
         Ok((i, unmarshalled))
     }
 }
 
+/// Decode the items of an array of fixed size numbers in one pass, instead of parsing them one by
+/// one.
+///
+/// Returns `None` for other item types, and if the data is not a whole number of items, in which
+/// case the items are parsed one by one instead.
+fn decode_fixed_size_items(
+    data: &[u8],
+    item_type: &SingleCompleteTypeSignature,
+    endianness: Endianness,
+) -> Option<Vec<Type>> {
+    macro_rules! decode {
+        ($dbus_type:ident, $native:ty) => {{
+            const SIZE: usize = std::mem::size_of::<$native>();
+            if data.len() % SIZE != 0 {
+                return None;
+            }
+
+            data.chunks_exact(SIZE)
+                .map(|chunk| {
+                    let bytes = <[u8; SIZE]>::try_from(chunk).unwrap();
+                    let value = match endianness {
+                        Endianness::BigEndian => <$native>::from_be_bytes(bytes),
+                        Endianness::LittleEndian => <$native>::from_le_bytes(bytes),
+                    };
+                    Type::from($dbus_type::from(value))
+                })
+                .collect()
+        }};
+    }
+
+    // Items of these types are as large as their alignment, so there is no padding between them.
+    // Booleans are left out, since they must be checked to be 0 or 1.
+    let items = match item_type {
+        SingleCompleteTypeSignature::DBusByte => data
+            .iter()
+            .map(|&byte| Type::from(DBusByte::from(byte)))
+            .collect(),
+        SingleCompleteTypeSignature::DBusInt16 => decode!(DBusInt16, i16),
+        SingleCompleteTypeSignature::DBusUint16 => decode!(DBusUint16, u16),
+        SingleCompleteTypeSignature::DBusInt32 => decode!(DBusInt32, i32),
+        SingleCompleteTypeSignature::DBusUint32 => decode!(DBusUint32, u32),
+        SingleCompleteTypeSignature::DBusInt64 => decode!(DBusInt64, i64),
+        SingleCompleteTypeSignature::DBusUint64 => decode!(DBusUint64, u64),
+        SingleCompleteTypeSignature::DBusDouble => decode!(DBusDouble, f64),
+        SingleCompleteTypeSignature::DBusUnixFileDescriptor => {
+            decode!(DBusUnixFileDescriptor, u32)
+        }
+        _ => return None,
+    };
+
+    Some(items)
+}
+
 impl MessageType {
-    fn unmarshal<'a>(i: I<'a>) -> IResult<I<'a>, Self> {
-        todo!()
-        //         map_opt(be_u8, |value| match value {
-        //             1 => Some(MessageType::MethodCall),
-        //             2 => Some(MessageType::MethodReturn),
-        //             3 => Some(MessageType::Error),
-        //             4 => Some(MessageType::Signal),
-        //             _ => None,
-        //         })(i)
+    /// Message type 0 is invalid. Other unknown message types are reported as
+    /// [crate::Error::UnknownMessageType], so that they can be ignored.
+    fn from_decimal_value(value: u8) -> crate::Result<Self> {
+        match value {
+            0 => Err(crate::Error::InvalidMessageType(value)),
+            1 => Ok(MessageType::MethodCall),
+            2 => Ok(MessageType::MethodReturn),
+            3 => Ok(MessageType::Error),
+            4 => Ok(MessageType::Signal),
+            _ => Err(crate::Error::UnknownMessageType(value)),
+        }
     }
 }
 
 impl SingleCompleteTypeSignature {
+    /// Parse the ASCII signature of a single complete type, for example `a{sv}`.
+    pub fn new_from_signature(signature: &str) -> crate::Result<Self> {
+        let (_i, single_complete_type_signature) =
+            all_consuming(parse_top_level)(I::new(signature.as_bytes())).finish()?;
+
+        Ok(single_complete_type_signature)
+    }
+
     pub fn unmarshal<'i>(&self, i: &'i [u8], endianness: Endianness) -> crate::Result<Type> {
-        let (_i, type_) = all_consuming(|i| self.unmarshal_inner(i, endianness))(I::new(i))
-            .finish()
-            .map_err(|_err| crate::Error::ParseError)?;
+        let (_i, type_) =
+            all_consuming(|i| self.unmarshal_inner(i, endianness))(I::new(i)).finish()?;
         Ok(type_)
     }
 
     fn unmarshal_inner<'i>(&self, i: I<'i>, endianness: Endianness) -> IResult<I<'i>, Type> {
-        if endianness == Endianness::LittleEndian {
-            todo!();
-        }
+        self.unmarshal_nested(i, endianness, 0)
+    }
 
+    /// Unmarshal a value inside the given number of containers.
+    fn unmarshal_nested<'i>(
+        &self,
+        i: I<'i>,
+        endianness: Endianness,
+        depth: usize,
+    ) -> IResult<I<'i>, Type> {
         let (i, type_): (I<'i>, Type) = match self {
             Self::DBusByte => {
-                let (i, inner) = DBusByte::unmarshal_be(i)?;
+                let (i, inner) = DBusByte::unmarshal(i, endianness)?;
                 (i, Type::from(inner))
             }
             Self::DBusBoolean => {
-                let i = i.advance_to_boundary(DBusBoolean::alignment())?;
-                // The boolean is contained in a u32, but only 0 or 1 are valid values.
-                let (i, boolean): (I, bool) = map_opt(be_u32, |value| match value {
-                    0 => Some(false),
-                    1 => Some(true),
-                    _ => None,
-                })(i)?;
-                (i, Type::from(DBusBoolean { bool: boolean }))
+                let (i, inner) = DBusBoolean::unmarshal(i, endianness)?;
+                (i, Type::from(inner))
             }
             Self::DBusInt16 => {
-                let (i, inner) = DBusInt16::unmarshal_be(i)?;
+                let (i, inner) = DBusInt16::unmarshal(i, endianness)?;
                 (i, Type::from(inner))
             }
             Self::DBusUint16 => {
-                let (i, inner) = DBusUint16::unmarshal_be(i)?;
+                let (i, inner) = DBusUint16::unmarshal(i, endianness)?;
                 (i, Type::from(inner))
             }
             Self::DBusInt32 => {
-                let (i, inner) = DBusInt32::unmarshal_be(i)?;
+                let (i, inner) = DBusInt32::unmarshal(i, endianness)?;
                 (i, Type::from(inner))
             }
             Self::DBusUint32 => {
-                let (i, inner) = DBusUint32::unmarshal_be(i)?;
+                let (i, inner) = DBusUint32::unmarshal(i, endianness)?;
                 (i, Type::from(inner))
             }
             Self::DBusInt64 => {
-                let (i, inner) = DBusInt64::unmarshal_be(i)?;
+                let (i, inner) = DBusInt64::unmarshal(i, endianness)?;
                 (i, Type::from(inner))
             }
             Self::DBusUint64 => {
-                let (i, inner) = DBusUint64::unmarshal_be(i)?;
+                let (i, inner) = DBusUint64::unmarshal(i, endianness)?;
                 (i, Type::from(inner))
             }
             Self::DBusDouble => {
-                let (i, inner) = DBusDouble::unmarshal_be(i)?;
+                let (i, inner) = DBusDouble::unmarshal(i, endianness)?;
                 (i, Type::from(inner))
             }
             Self::DBusString => {
@@ -351,39 +742,78 @@ impl SingleCompleteTypeSignature {
                 let dbus_object_path = DBusObjectPath::from(dbus_string);
                 (i, Type::from(dbus_object_path))
             }
-            Self::DBusSignature => match endianness {
-                Endianness::BigEndian => {
-                    let (i, sig) = DBusSignature::unmarshal_be(i)?;
-                    (i, Type::from(sig))
-                }
-                Endianness::LittleEndian => {
-                    todo!()
-                }
-            },
+            Self::DBusSignature => {
+                // Signatures consist of single bytes, so they are the same in both byte orders.
+                let (i, sig) = DBusSignature::unmarshal(i)?;
+                (i, Type::from(sig))
+            }
             Self::DBusArray(item_type) => {
+                let depth = nested(depth)?;
                 let i = i.advance_to_boundary(DBusArray::alignment())?;
                 let (i, length_of_array_data_in_bytes): (I, u32) = match endianness {
                     Endianness::BigEndian => be_u32,
                     Endianness::LittleEndian => le_u32,
                 }(i)?;
                 let i = i.advance_to_boundary(item_type.marshalling_boundary())?;
-                let (i, items): (I, Vec<Type>) = map_parser(take(length_of_array_data_in_bytes), all_consuming(many0(|i| item_type.unmarshal_inner(i, endianness))))(i)?;
+                let (i, data) = take(length_of_array_data_in_bytes)(i)?;
+                let items = match decode_fixed_size_items(data.data, item_type, endianness) {
+                    Some(items) => items,
+                    None => {
+                        let parse_item = |i| item_type.unmarshal_nested(i, endianness, depth);
+                        let (_data, items) = all_consuming(many0(parse_item))(data)?;
+                        items
+                    }
+                };
 
                 let dbus_array = DBusArray { item_type: item_type.deref().clone(), items };
 
                 (i, Type::from(dbus_array))
             }
             Self::DBusStruct { fields } => {
-                todo!();
+                let depth = nested(depth)?;
+                // Struct starts on 8-byte boundary regardless of the type of its fields.
+                let mut i = i.advance_to_boundary(DBusStruct::alignment())?;
+
+                let mut unmarshalled_fields: Vec<Type> = Vec::with_capacity(fields.len());
+                for field in fields {
+                    let (rest, unmarshalled_field) =
+                        field.unmarshal_nested(i, endianness, depth)?;
+                    i = rest;
+                    unmarshalled_fields.push(unmarshalled_field);
+                }
+
+                (i, Type::from(DBusStruct::new(unmarshalled_fields)))
             }
             Self::DBusVariant => {
-                todo!();
+                let depth = nested(depth)?;
+                // The variant starts with the signature of its value, which must be exactly one
+                // single complete type.
+                let (i, mut signature) = verify(DBusSignature::unmarshal, |signature| {
+                    signature.vec.len() == 1
+                })(i)?;
+                let single_complete_type_signature = signature.vec.remove(0);
+
+                let (i, inner) =
+                    single_complete_type_signature.unmarshal_nested(i, endianness, depth)?;
+
+                (i, Type::from(DBusVariant::new(inner)))
             }
             Self::DBusUnixFileDescriptor => {
-                todo!();
+                let (i, inner) = DBusUnixFileDescriptor::unmarshal(i, endianness)?;
+                (i, Type::from(inner))
             }
             Self::DBusDictEntry { key, value } => {
-                todo!();
+                let depth = nested(depth)?;
+                // Dict entries are unmarshalled like structs.
+                let i = i.advance_to_boundary(DBusDictEntry::alignment())?;
+
+                let (i, unmarshalled_key) = key.unmarshal_nested(i, endianness, depth)?;
+                let (i, unmarshalled_value) = value.unmarshal_nested(i, endianness, depth)?;
+
+                (
+                    i,
+                    Type::from(DBusDictEntry::new(unmarshalled_key, unmarshalled_value)),
+                )
             }
         };
 
@@ -393,14 +823,13 @@ impl SingleCompleteTypeSignature {
 
 impl DBusString {
     fn unmarshal<'i>(i: I<'i>, endianness: Endianness) -> IResult<I<'i>, Self> {
-        if endianness == Endianness::LittleEndian {
-            todo!();
-        }
-
         let i = i.advance_to_boundary(Self::alignment())?;
 
         // The first 4 bytes encode the string's length in bytes, excluding its terminating null.
-        let (i, length): (I, u32) = be_u32(i)?;
+        let (i, length): (I, u32) = match endianness {
+            Endianness::BigEndian => be_u32,
+            Endianness::LittleEndian => le_u32,
+        }(i)?;
 
         // Now we know the length in bytes of the string that follows.
         // DBus strings are UTF-8 encoded, so we need to decode the bytes we take as UTF-8
@@ -422,6 +851,64 @@ impl DBusString {
 mod tests {
     use super::*;
 
+    #[test]
+    fn unmarshal_array_of_object_paths() -> crate::Result<()> {
+        #[rustfmt::skip]
+        let bytes: &[u8] = &[
+            // Length of array data
+            0, 0, 0, 17,
+            // "/a", then padding to the 4-byte boundary of the next length
+            0, 0, 0, 2, b'/', b'a', 0, 0,
+            // "/b/c"
+            0, 0, 0, 4, b'/', b'b', b'/', b'c', 0,
+        ];
+
+        let array = SingleCompleteTypeSignature::new_from_signature("ao")?
+            .unmarshal(bytes, Endianness::BigEndian)?;
+
+        assert_eq!(
+            array,
+            Type::from(DBusArray {
+                item_type: SingleCompleteTypeSignature::DBusObjectPath,
+                items: vec![
+                    Type::from(DBusObjectPath::from("/a")),
+                    Type::from(DBusObjectPath::from("/b/c")),
+                ],
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn unmarshal_array_of_signatures() -> crate::Result<()> {
+        #[rustfmt::skip]
+        let bytes: &[u8] = &[
+            // Length of array data
+            0, 0, 0, 10,
+            // "s", signatures need no alignment
+            1, b's', 0,
+            // "a{sv}"
+            5, b'a', b'{', b's', b'v', b'}', 0,
+        ];
+
+        let array = SingleCompleteTypeSignature::new_from_signature("ag")?
+            .unmarshal(bytes, Endianness::BigEndian)?;
+
+        assert_eq!(
+            array,
+            Type::from(DBusArray {
+                item_type: SingleCompleteTypeSignature::DBusSignature,
+                items: vec![
+                    Type::from(DBusSignature::new_from_signature("s")?),
+                    Type::from(DBusSignature::new_from_signature("a{sv}")?),
+                ],
+            })
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn unmarshal_array_of_bytes() {
         let a: [u8; 7] = [0, 0, 0, 3, 15, 16, 17];
@@ -446,11 +933,164 @@ mod tests {
         }
     }
 
+    #[test]
+    fn decode_fixed_size_items_like_parsing_each_item() -> crate::Result<()> {
+        use crate::type_system::marshal::Encoder;
+        use crate::type_system::marshal::Marshal;
+
+        let item_type = SingleCompleteTypeSignature::DBusUint64;
+        let values: Vec<u64> = (0..1000).map(|n| n * 0x0101_0101_0101).collect();
+
+        for &endianness in &[Endianness::BigEndian, Endianness::LittleEndian] {
+            let mut encoder = Encoder::default();
+            for &value in &values {
+                encoder.marshal(&DBusUint64::from(value), endianness)?;
+            }
+            let data = encoder.finish();
+
+            let decoded = decode_fixed_size_items(&data, &item_type, endianness).unwrap();
+            let (_i, parsed) =
+                all_consuming(many0(|i| item_type.unmarshal_inner(i, endianness)))(I::new(&data))
+                    .finish()?;
+
+            assert_eq!(decoded, parsed);
+            assert_eq!(
+                decoded[999],
+                Type::from(DBusUint64::from(999_u64 * 0x0101_0101_0101))
+            );
+        }
+
+        // Not a whole number of items.
+        assert_eq!(
+            decode_fixed_size_items(&[0; 12], &item_type, Endianness::BigEndian),
+            None
+        );
+        let array = [0, 0, 0, 12, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        assert!(SingleCompleteTypeSignature::DBusArray(Box::new(item_type))
+            .unmarshal(&array, Endianness::BigEndian)
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn signature_nesting_too_deep() -> crate::Result<()> {
+        let deepest = format!("{}y", "a".repeat(MAX_NESTING_DEPTH));
+        DBusSignature::new_from_signature(&deepest)?;
+
+        let too_deep = format!("{}y", "a".repeat(100));
+        assert!(matches!(
+            DBusSignature::new_from_signature(&too_deep),
+            Err(crate::Error::NestingTooDeep)
+        ));
+        assert!(matches!(
+            SingleCompleteTypeSignature::new_from_signature(&too_deep),
+            Err(crate::Error::NestingTooDeep)
+        ));
+
+        // The same signature marshalled as a value.
+        let mut marshalled = vec![too_deep.len() as u8];
+        marshalled.extend_from_slice(too_deep.as_bytes());
+        marshalled.push(0);
+        assert!(matches!(
+            SingleCompleteTypeSignature::DBusSignature
+                .unmarshal(&marshalled, Endianness::BigEndian),
+            Err(crate::Error::NestingTooDeep)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn variant_nesting_too_deep() {
+        // Each variant contains another variant, until a byte at the bottom.
+        let nested_variants = |depth: usize| {
+            let mut marshalled = b"\x01v\x00".repeat(depth - 1);
+            marshalled.extend_from_slice(&[1, b'y', 0, 42]);
+            marshalled
+        };
+
+        assert!(SingleCompleteTypeSignature::DBusVariant
+            .unmarshal(&nested_variants(MAX_NESTING_DEPTH), Endianness::BigEndian)
+            .is_ok());
+        assert!(matches!(
+            SingleCompleteTypeSignature::DBusVariant
+                .unmarshal(&nested_variants(100), Endianness::BigEndian),
+            Err(crate::Error::NestingTooDeep)
+        ));
+    }
+
+    #[test]
+    fn new_from_signature() -> crate::Result<()> {
+        use SingleCompleteTypeSignature::*;
+
+        assert_eq!(
+            SingleCompleteTypeSignature::new_from_signature("i")?,
+            DBusInt32
+        );
+        assert_eq!(
+            SingleCompleteTypeSignature::new_from_signature("ai")?,
+            DBusArray(Box::new(DBusInt32))
+        );
+        assert_eq!(
+            SingleCompleteTypeSignature::new_from_signature("(is)")?,
+            DBusStruct {
+                fields: vec![DBusInt32, DBusString]
+            }
+        );
+        assert_eq!(
+            SingleCompleteTypeSignature::new_from_signature("a{sv}")?,
+            DBusArray(Box::new(DBusDictEntry {
+                key: Box::new(DBusString),
+                value: Box::new(DBusVariant),
+            }))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn new_from_signature_round_trips_serialize() -> crate::Result<()> {
+        for signature in &["g", "aai", "(ya(yv))", "a{sa{sv}}"] {
+            let parsed = SingleCompleteTypeSignature::new_from_signature(signature)?;
+            assert_eq!(parsed.serialize(), signature.as_bytes());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn new_from_signature_invalid() {
+        // Not a single complete type.
+        assert!(SingleCompleteTypeSignature::new_from_signature("").is_err());
+        assert!(SingleCompleteTypeSignature::new_from_signature("ii").is_err());
+        // Dict entry outside of an array.
+        assert!(SingleCompleteTypeSignature::new_from_signature("{sv}").is_err());
+        // Unbalanced.
+        assert!(SingleCompleteTypeSignature::new_from_signature("(i").is_err());
+    }
+
+    #[test]
+    fn invalid_signature_char() {
+        assert!(matches!(
+            SingleCompleteTypeSignature::new_from_signature("a{sz}"),
+            Err(crate::Error::InvalidSignatureChar(b'z'))
+        ));
+        assert!(matches!(
+            DBusSignature::new_from_signature("siz"),
+            Err(crate::Error::InvalidSignatureChar(b'z'))
+        ));
+
+        let a: [u8; 4] = [2, b'u', b'z', 0];
+        let error = nom::Err::Failure(UnmarshalError::InvalidSignatureChar(b'z'));
+        assert_eq!(DBusSignature::unmarshal(I::new(&a)), Err(error));
+    }
+
     #[test]
     fn unmarshal_basic_signature() {
-        let a: [u8; 9] = [b'y', b'b', b'n', b'q', b'i', b'u', b'x', b't', b'd'];
+        let a: [u8; 11] = [9, b'y', b'b', b'n', b'q', b'i', b'u', b'x', b't', b'd', 0];
 
-        let (i, x) = DBusSignature::unmarshal_be(I::new(&a)).unwrap();
+        let (i, x) = DBusSignature::unmarshal(I::new(&a)).unwrap();
 
         assert_eq!(x.vec.len(), 9);
         assert_eq!(x.vec[0], SingleCompleteTypeSignature::DBusByte);
@@ -463,4 +1103,364 @@ mod tests {
         assert_eq!(x.vec[7], SingleCompleteTypeSignature::DBusUint64);
         assert_eq!(x.vec[8], SingleCompleteTypeSignature::DBusDouble);
     }
+
+    #[test]
+    fn unmarshal_message_sender() -> crate::Result<()> {
+        use crate::type_system::marshal::Encoder;
+
+        let mut encoder = Encoder::default();
+        // Signal without body, serial 7.
+        encoder.extend_from_array([b'B', 4, 0, 1, 0, 0, 0, 0, 0, 0, 0, 7]);
+        encoder.marshal_header_fields(
+            &[
+                HeaderField::Path(DBusObjectPath::from("/org/freedesktop/DBus")),
+                HeaderField::Interface(DBusString::from("org.freedesktop.DBus")),
+                HeaderField::Member(DBusString::from("NameAcquired")),
+                HeaderField::Sender(DBusString::from("org.freedesktop.DBus")),
+            ],
+            Endianness::BigEndian,
+        )?;
+        encoder.align(8);
+
+        let message = unmarshal_message(&encoder.finish())?;
+
+        assert_eq!(message.serial.get(), 7);
+        assert_eq!(
+            message.sender,
+            Some(DBusString::from("org.freedesktop.DBus"))
+        );
+        match message.message_type_param {
+            MessageTypeParam::Signal(signal) => {
+                assert_eq!(signal.interface, DBusString::from("org.freedesktop.DBus"));
+                assert_eq!(signal.member, DBusString::from("NameAcquired"));
+            }
+            _ => panic!("expected a signal"),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn unmarshal_message_unix_fds() -> crate::Result<()> {
+        use crate::type_system::marshal::Encoder;
+
+        let mut encoder = Encoder::default();
+        // Method return without body, serial 2.
+        encoder.extend_from_array([b'B', 2, 0, 1, 0, 0, 0, 0, 0, 0, 0, 2]);
+        encoder.marshal_header_fields(
+            &[
+                HeaderField::ReplySerial(DBusUint32::from(1_u32)),
+                HeaderField::UnixFds(DBusUint32::from(2_u32)),
+            ],
+            Endianness::BigEndian,
+        )?;
+        encoder.align(8);
+
+        let message = unmarshal_message(&encoder.finish())?;
+
+        assert_eq!(message.unix_fds, Some(2));
+
+        Ok(())
+    }
+
+    #[test]
+    fn unmarshal_message_invalid_struct_body() -> crate::Result<()> {
+        use crate::type_system::marshal::Encoder;
+
+        let mut encoder = Encoder::default();
+        // Method call with a body of 8 bytes, serial 1.
+        encoder.extend_from_array([b'B', 1, 0, 1, 0, 0, 0, 8, 0, 0, 0, 1]);
+        encoder.marshal_header_fields(
+            &[
+                HeaderField::Path(DBusObjectPath::from("/")),
+                HeaderField::Member(DBusString::from("Method")),
+                HeaderField::Signature(DBusSignature {
+                    vec: vec![SingleCompleteTypeSignature::DBusStruct {
+                        fields: vec![
+                            SingleCompleteTypeSignature::DBusUint32,
+                            SingleCompleteTypeSignature::DBusBoolean,
+                        ],
+                    }],
+                }),
+            ],
+            Endianness::BigEndian,
+        )?;
+        encoder.align(8);
+        // The struct's boolean field is neither 0 nor 1.
+        encoder.extend_from_array([0, 0, 0, 7, 0, 0, 0, 2]);
+
+        assert!(matches!(
+            unmarshal_message(&encoder.finish()),
+            Err(crate::Error::ParseError)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn unmarshal_message_reply_serial_zero() -> crate::Result<()> {
+        use crate::type_system::marshal::Encoder;
+
+        let mut encoder = Encoder::default();
+        // Method return without body, serial 2.
+        encoder.extend_from_array([b'B', 2, 0, 1, 0, 0, 0, 0, 0, 0, 0, 2]);
+        encoder.marshal_header_fields(
+            &[HeaderField::ReplySerial(DBusUint32::from(0_u32))],
+            Endianness::BigEndian,
+        )?;
+        encoder.align(8);
+
+        assert!(matches!(
+            unmarshal_message(&encoder.finish()),
+            Err(crate::Error::InvalidReplySerial)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn unmarshal_message_prefix_of_two() -> crate::Result<()> {
+        use crate::type_system::marshal::Encoder;
+
+        // Method return with a 4 byte body.
+        let method_return = |serial: u8, value: u32| -> crate::Result<Vec<u8>> {
+            let mut encoder = Encoder::default();
+            encoder.extend_from_array([b'B', 2, 0, 1, 0, 0, 0, 4, 0, 0, 0, serial]);
+            encoder.marshal_header_fields(
+                &[
+                    HeaderField::ReplySerial(DBusUint32::from(1_u32)),
+                    HeaderField::Signature(DBusSignature {
+                        vec: vec![SingleCompleteTypeSignature::DBusUint32],
+                    }),
+                ],
+                Endianness::BigEndian,
+            )?;
+            encoder.align(8);
+            encoder.extend_from_array(value.to_be_bytes());
+            Ok(encoder.finish())
+        };
+        let first = method_return(2, 10)?;
+        let second = method_return(3, 20)?;
+        let both = [first.as_slice(), second.as_slice()].concat();
+
+        let (message, consumed) = unmarshal_message_prefix(&both)?;
+        assert_eq!(consumed, first.len());
+        assert_eq!(message.serial.get(), 2);
+        assert_eq!(message.body.extract::<(u32,)>()?, (10,));
+
+        let (message, consumed) = unmarshal_message_prefix(&both[consumed..])?;
+        assert_eq!(consumed, second.len());
+        assert_eq!(message.serial.get(), 3);
+
+        assert!(matches!(
+            unmarshal_message(&both),
+            Err(crate::Error::ParseError)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn unmarshal_message_body_after_header_padding() -> crate::Result<()> {
+        use crate::type_system::marshal::Encoder;
+
+        let mut encoder = Encoder::default();
+        // Method return with an 8 byte body, serial 2.
+        encoder.extend_from_array([b'B', 2, 0, 1, 0, 0, 0, 8, 0, 0, 0, 2]);
+        encoder.marshal_header_fields(
+            &[
+                HeaderField::ReplySerial(DBusUint32::from(1_u32)),
+                HeaderField::Signature(DBusSignature {
+                    vec: vec![SingleCompleteTypeSignature::DBusUint64],
+                }),
+            ],
+            Endianness::BigEndian,
+        )?;
+        // The header fields end 1 byte before the 8-byte boundary where the body starts.
+        assert_eq!(encoder.buf.len() % 8, 7);
+        encoder.align(8);
+        encoder.extend_from_array(0x0102030405060708_u64.to_be_bytes());
+
+        let message = unmarshal_message(&encoder.finish())?;
+
+        assert_eq!(
+            message.body.arguments,
+            vec![Type::from(DBusUint64::from(0x0102030405060708_u64))]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn unmarshal_message_type_invalid() {
+        // Message type 0, no header fields.
+        let message: [u8; 16] = [b'B', 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0];
+
+        assert!(matches!(
+            unmarshal_message(&message),
+            Err(crate::Error::InvalidMessageType(0))
+        ));
+    }
+
+    #[test]
+    fn unmarshal_message_type_unknown() {
+        // Message type 5, no header fields.
+        let message: [u8; 16] = [b'B', 5, 0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0];
+
+        assert!(matches!(
+            unmarshal_message(&message),
+            Err(crate::Error::UnknownMessageType(5))
+        ));
+    }
+
+    #[test]
+    fn unmarshal_message_reserved_flag_bits() {
+        // Method return with flag bit 0x8 set, no header fields.
+        let message: [u8; 16] = [b'B', 2, 0x8, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0];
+
+        assert!(matches!(
+            unmarshal_message(&message),
+            Err(crate::Error::ReservedFlagBitsSet(0x8))
+        ));
+    }
+
+    #[test]
+    fn unmarshal_message_unix_fd_body() -> crate::Result<()> {
+        use crate::type_system::marshal::Encoder;
+
+        let mut encoder = Encoder::default();
+        // Method call with a body of 4 bytes, serial 1.
+        encoder.extend_from_array([b'B', 1, 0, 1, 0, 0, 0, 4, 0, 0, 0, 1]);
+        encoder.marshal_header_fields(
+            &[
+                HeaderField::Path(DBusObjectPath::from("/")),
+                HeaderField::Member(DBusString::from("Method")),
+                HeaderField::Signature(DBusSignature {
+                    vec: vec![SingleCompleteTypeSignature::DBusUnixFileDescriptor],
+                }),
+            ],
+            Endianness::BigEndian,
+        )?;
+        encoder.align(8);
+        // Index of the file descriptor.
+        encoder.extend_from_array([0, 0, 0, 1]);
+
+        let message = unmarshal_message(&encoder.finish())?;
+
+        assert_eq!(
+            message.body.arguments,
+            vec![Type::from(DBusUnixFileDescriptor::from(1_u32))]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn decode_header_fields_path_member_signature() -> crate::Result<()> {
+        use crate::type_system::marshal::Encoder;
+
+        let header_fields = vec![
+            HeaderField::Path(DBusObjectPath::from("/org/example/Object")),
+            HeaderField::Member(DBusString::from("Method")),
+            HeaderField::Signature(DBusSignature {
+                vec: vec![
+                    SingleCompleteTypeSignature::DBusUint32,
+                    SingleCompleteTypeSignature::DBusString,
+                ],
+            }),
+        ];
+
+        let mut encoder = Encoder::default();
+        encoder.marshal_header_fields(&header_fields, Endianness::BigEndian)?;
+
+        let signature =
+            SingleCompleteTypeSignature::DBusArray(Box::new(HEADER_FIELD_SIGNATURE.clone()));
+        let array =
+            DBusArray::try_from(signature.unmarshal(&encoder.finish(), Endianness::BigEndian)?)?;
+
+        assert_eq!(decode_header_fields(array)?, header_fields);
+
+        Ok(())
+    }
+
+    #[test]
+    fn unmarshal_body_u32_string() -> crate::Result<()> {
+        let signature = DBusSignature {
+            vec: vec![
+                SingleCompleteTypeSignature::DBusUint32,
+                SingleCompleteTypeSignature::DBusString,
+            ],
+        };
+
+        let big_endian: &[u8] = &[0, 0, 0, 42, 0, 0, 0, 2, b'h', b'i', 0];
+        let little_endian: &[u8] = &[42, 0, 0, 0, 2, 0, 0, 0, b'h', b'i', 0];
+
+        let expected = Body {
+            arguments: vec![
+                Type::from(DBusUint32::from(42_u32)),
+                Type::from(DBusString::from("hi")),
+            ],
+        };
+        assert_eq!(
+            Body::unmarshal(big_endian, &signature, Endianness::BigEndian)?,
+            expected
+        );
+        assert_eq!(
+            Body::unmarshal(little_endian, &signature, Endianness::LittleEndian)?,
+            expected
+        );
+
+        // Trailing bytes that are not described by the signature.
+        assert!(Body::unmarshal(&[0, 0, 0, 42, 0], &signature, Endianness::BigEndian).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn decode_header_fields_duplicate() -> crate::Result<()> {
+        use crate::type_system::marshal::Encoder;
+
+        let mut encoder = Encoder::default();
+        encoder.marshal_header_fields(
+            &[
+                HeaderField::Path(DBusObjectPath::from("/org/example/A")),
+                HeaderField::Member(DBusString::from("Method")),
+                HeaderField::Path(DBusObjectPath::from("/org/example/B")),
+            ],
+            Endianness::BigEndian,
+        )?;
+
+        let signature =
+            SingleCompleteTypeSignature::DBusArray(Box::new(HEADER_FIELD_SIGNATURE.clone()));
+        let array =
+            DBusArray::try_from(signature.unmarshal(&encoder.finish(), Endianness::BigEndian)?)?;
+
+        assert!(matches!(
+            decode_header_fields(array),
+            Err(crate::Error::DuplicateHeaderField(1))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn unmarshal_method_call_without_member() -> crate::Result<()> {
+        use crate::type_system::marshal::Encoder;
+
+        let mut encoder = Encoder::default();
+        // Method call without body, serial 1.
+        encoder.extend_from_array([b'B', 1, 0, 1, 0, 0, 0, 0, 0, 0, 0, 1]);
+        encoder.marshal_header_fields(
+            &[HeaderField::Path(DBusObjectPath::from("/org/example"))],
+            Endianness::BigEndian,
+        )?;
+        encoder.align(8);
+
+        assert!(matches!(
+            unmarshal_message(&encoder.finish()),
+            Err(crate::Error::MissingHeaderField(3))
+        ));
+
+        Ok(())
+    }
 }
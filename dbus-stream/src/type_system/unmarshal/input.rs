@@ -4,6 +4,7 @@ use std::ops::RangeFrom;
 use std::slice::Iter;
 
 use crate::type_system::unmarshal::parsers::complete::skip_null_byte;
+use crate::type_system::unmarshal::UnmarshalError;
 
 /// A wrapper over `&[u8]` but with the ability to keep track of global alignment.
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -33,13 +34,13 @@ impl<'a> I<'a> {
     pub fn advance_to_boundary(
         self,
         boundary: usize,
-    ) -> Result<I<'a>, nom::Err<nom::error::Error<I<'a>>>> {
+    ) -> Result<I<'a>, nom::Err<UnmarshalError<I<'a>>>> {
         let mut i = self;
 
         // Don't really expect to need to call this for other boundaries than 1, 2, 4 and 8.
         debug_assert!([1, 2, 4, 8].contains(&boundary), "Sanity check");
 
-        while i.alignment % boundary != 0 {
+        while !i.alignment.is_multiple_of(boundary) {
             i = skip_null_byte(i)?;
         }
 
@@ -179,4 +180,42 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn advance_to_larger_boundary() -> Result<(), Box<dyn std::error::Error>> {
+        let i = I {
+            data: &[0, 0, 0xff],
+            alignment: 2,
+        };
+
+        let i = i.advance_to_boundary(4)?;
+        assert_eq!(i.data, &[0xff]);
+        assert_eq!(i.alignment, 4);
+
+        Ok(())
+    }
+
+    #[test]
+    fn advance_to_satisfied_boundary() -> Result<(), Box<dyn std::error::Error>> {
+        let i = I {
+            data: &[0xff, 0xff],
+            alignment: 2,
+        };
+
+        let i = i.advance_to_boundary(2)?;
+        assert_eq!(i.data, &[0xff, 0xff]);
+        assert_eq!(i.alignment, 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn advance_to_boundary_rejects_non_null_padding() {
+        let i = I {
+            data: &[0, 1, 0xff],
+            alignment: 2,
+        };
+
+        assert!(i.advance_to_boundary(4).is_err());
+    }
 }